@@ -0,0 +1,138 @@
+//! A large zone going through the client: parsing `infoDnsRecords`, and a
+//! batch of additions in one `updateDnsRecords` call, which lists the zone
+//! and parses it back twice. The API is scripted with `MockTransport`, so
+//! this measures the client, not netcup.
+//!
+//! ```sh
+//! cargo bench --bench zone
+//! ```
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use serde_json::{json, Value};
+use truenas_acme_auth::{testing::MockTransport, NetcupAPIClient, RetryPolicy};
+
+/// About the size of a zone with a record per host of a small network.
+const ZONE_SIZE: usize = 1800;
+/// One call at the default batch size.
+const ADDITIONS: usize = 50;
+
+fn record(id: usize, hostname: String, record_type: &str, destination: String) -> Value {
+    json!({
+        "id": id.to_string(),
+        "hostname": hostname,
+        "type": record_type,
+        "priority": "0",
+        "destination": destination,
+        "deleterecord": false,
+        "state": "yes",
+    })
+}
+
+/// A mix of the record types netcup returns, with ids, as
+/// `infoDnsRecords` would.
+fn zone(size: usize) -> Vec<Value> {
+    (0..size)
+        .map(|id| match id % 3 {
+            0 => record(
+                id,
+                format!("host{}", id),
+                "A",
+                format!("192.0.2.{}", id % 256),
+            ),
+            1 => record(
+                id,
+                format!("www.host{}", id),
+                "CNAME",
+                format!("host{}.example.de", id - 1),
+            ),
+            _ => record(
+                id,
+                format!("_acme-challenge.host{}", id),
+                "TXT",
+                format!("\"{:043}\"", id),
+            ),
+        })
+        .collect()
+}
+
+/// The whole envelope as the transport returns it.
+fn response(action: &str, records: &[Value]) -> String {
+    json!({
+        "serverrequestid": "bench",
+        "action": action,
+        "status": "success",
+        "statuscode": 2000,
+        "shortmessage": "DNS records found",
+        "responsedata": { "dnsrecords": records },
+    })
+    .to_string()
+}
+
+fn client(mock: &MockTransport) -> NetcupAPIClient {
+    mock.respond_success("login", json!({ "apisessionid": "session" }));
+    NetcupAPIClient::builder()
+        .transport(mock.clone())
+        .retry_policy(RetryPolicy::none())
+        .rate_limit(u32::MAX)
+        .login("12345".into(), "password".into(), "key".into())
+        .unwrap()
+}
+
+fn list_records(c: &mut Criterion) {
+    let listing = response("infoDnsRecords", &zone(ZONE_SIZE));
+
+    c.bench_function("list_records/1800", |b| {
+        b.iter_batched(
+            || {
+                let mock = MockTransport::new();
+                let client = client(&mock);
+                mock.respond_raw(200, listing.clone());
+                (mock, client)
+            },
+            |(_mock, client)| {
+                let records = client.list_records("example.de").unwrap();
+                // Logging out on drop is not part of it
+                (client, records)
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+fn add_txt_records(c: &mut Criterion) {
+    let mut records = zone(ZONE_SIZE);
+    let listing = response("infoDnsRecords", &records);
+    let contents: Vec<String> = (0..ADDITIONS).map(|n| format!("token{:038}", n)).collect();
+    records.extend(contents.iter().enumerate().map(|(n, content)| {
+        record(
+            ZONE_SIZE + n,
+            "_acme-challenge".into(),
+            "TXT",
+            content.clone(),
+        )
+    }));
+    let updated = response("updateDnsRecords", &records);
+    let contents: Vec<&str> = contents.iter().map(String::as_str).collect();
+
+    c.bench_function("add_txt_records/1800+50", |b| {
+        b.iter_batched(
+            || {
+                let mock = MockTransport::new();
+                let client = client(&mock);
+                mock.respond_raw(200, listing.clone())
+                    .respond_raw(200, updated.clone());
+                (mock, client)
+            },
+            |(_mock, client)| {
+                let added = client
+                    .add_txt_records("example.de", "_acme-challenge", &contents)
+                    .unwrap();
+                (client, added)
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group!(benches, list_records, add_txt_records);
+criterion_main!(benches);
@@ -0,0 +1,37 @@
+//! Logs in and adds a TXT record, the credentials taken from
+//! `NETCUP_CUSTOMER_ID`, `NETCUP_API_KEY` and `NETCUP_API_PASSWORD` as for
+//! the command line tool.
+//!
+//! Builds natively as well, but is meant as a minimal WASI component:
+//!
+//! ```sh
+//! cargo build --target wasm32-wasip2 --no-default-features --features wasi-http \
+//!     --example wasi_add_txt
+//! wasmtime run -S http \
+//!     --env NETCUP_CUSTOMER_ID --env NETCUP_API_KEY --env NETCUP_API_PASSWORD \
+//!     target/wasm32-wasip2/debug/examples/wasi_add_txt.wasm example.de _acme-challenge VALUE
+//! ```
+
+use std::env;
+
+use anyhow::Context;
+use truenas_acme_auth::NetcupAPIClient;
+
+fn main() -> anyhow::Result<()> {
+    let mut args = env::args().skip(1);
+    let (Some(domain), Some(hostname), Some(content)) = (args.next(), args.next(), args.next())
+    else {
+        anyhow::bail!("Usage: wasi_add_txt <DOMAIN> <HOSTNAME> <CONTENT>");
+    };
+
+    let client = NetcupAPIClient::login(
+        env::var("NETCUP_CUSTOMER_ID").context("NETCUP_CUSTOMER_ID is not set")?,
+        env::var("NETCUP_API_PASSWORD").context("NETCUP_API_PASSWORD is not set")?,
+        env::var("NETCUP_API_KEY").context("NETCUP_API_KEY is not set")?,
+    )?;
+
+    client.add_txt_record(&domain, &hostname, &content)?;
+    println!("Added {} TXT {} to {}", hostname, content, domain);
+
+    Ok(())
+}
@@ -0,0 +1,36 @@
+use std::{fmt, net::IpAddr};
+
+/// Pins connections to the API and to nameservers to one IP version, e.g.
+/// when the other one has a broken route.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressFamily {
+    Ipv4,
+    Ipv6,
+}
+
+impl AddressFamily {
+    pub fn matches(self, addr: IpAddr) -> bool {
+        match self {
+            AddressFamily::Ipv4 => addr.is_ipv4(),
+            AddressFamily::Ipv6 => addr.is_ipv6(),
+        }
+    }
+
+    /// Binding to this address only allows connections of the family.
+    #[cfg(not(target_family = "wasm"))]
+    pub(crate) fn unspecified(self) -> IpAddr {
+        match self {
+            AddressFamily::Ipv4 => std::net::Ipv4Addr::UNSPECIFIED.into(),
+            AddressFamily::Ipv6 => std::net::Ipv6Addr::UNSPECIFIED.into(),
+        }
+    }
+}
+
+impl fmt::Display for AddressFamily {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AddressFamily::Ipv4 => write!(f, "IPv4"),
+            AddressFamily::Ipv6 => write!(f, "IPv6"),
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use crate::normalize_dns_name;
+
+/// Restricts which zones a client may touch.
+///
+/// Patterns are either exact zone names (`example.de`) or suffixes
+/// (`*.example.de` or `.example.de`), which match zones below it but not the
+/// name itself.
+#[derive(Debug, Clone, Default)]
+pub struct DomainAllowlist {
+    patterns: Vec<String>,
+}
+
+impl DomainAllowlist {
+    pub fn new<I, S>(patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Self {
+            patterns: patterns
+                .into_iter()
+                .map(|pattern| normalize(pattern.as_ref()))
+                .collect(),
+        }
+    }
+
+    pub fn allows(&self, domain: &str) -> bool {
+        let domain = normalize(domain);
+
+        self.patterns.iter().any(|pattern| {
+            match pattern
+                .strip_prefix("*.")
+                .or_else(|| pattern.strip_prefix('.'))
+            {
+                Some(suffix) => domain.ends_with(&format!(".{}", suffix)),
+                None => domain == *pattern,
+            }
+        })
+    }
+}
+
+/// Patterns and domains compare in punycode. A name that isn't valid stays
+/// as given and matches nothing but itself.
+fn normalize(name: &str) -> String {
+    normalize_dns_name(name).unwrap_or_else(|_| name.trim_end_matches('.').to_lowercase())
+}
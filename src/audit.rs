@@ -0,0 +1,300 @@
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::DNSRecord;
+
+/// An append-only JSON lines log of every change made through
+/// `updateDnsRecords`.
+///
+/// An `intent` entry is written before each call and a `result` entry after
+/// it, so a crash in between still leaves a trace.
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+    command: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditEvent {
+    Intent,
+    Result,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// RFC 3339, UTC
+    pub timestamp: String,
+    pub event: AuditEvent,
+    /// The subcommand that made the change
+    pub command: String,
+    pub domain: String,
+    /// The records submitted, only on `intent`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub records: Vec<DNSRecord>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub created: Vec<DNSRecord>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub updated: Vec<DNSRecord>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deleted: Vec<DNSRecord>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server_request_id: Option<String>,
+    /// `success` or the error, only on `result`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outcome: Option<String>,
+}
+
+impl AuditEntry {
+    fn new(event: AuditEvent, command: &str, domain: &str) -> Self {
+        Self {
+            timestamp: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            event,
+            command: command.into(),
+            domain: domain.into(),
+            records: Vec::new(),
+            created: Vec::new(),
+            updated: Vec::new(),
+            deleted: Vec::new(),
+            server_request_id: None,
+            outcome: None,
+        }
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.outcome.as_deref() == Some("success")
+    }
+}
+
+impl AuditLog {
+    /// `command` is recorded with every entry, e.g. `set` or `record delete`.
+    pub fn new(path: impl Into<PathBuf>, command: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            command: command.into(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn intent(&self, domain: &str, records: &[DNSRecord]) -> anyhow::Result<()> {
+        let mut entry = AuditEntry::new(AuditEvent::Intent, &self.command, domain);
+        entry.records = records.to_vec();
+        self.append(&entry)
+    }
+
+    /// Records how a submission of `submitted` turned out, `returned` being
+    /// the zone netcup sent back.
+    pub(crate) fn result(
+        &self,
+        domain: &str,
+        submitted: &[DNSRecord],
+        returned: Result<&[DNSRecord], &anyhow::Error>,
+        server_request_id: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let mut entry = AuditEntry::new(AuditEvent::Result, &self.command, domain);
+        entry.server_request_id = server_request_id.map(String::from);
+
+        match returned {
+            Ok(returned) => {
+                for record in submitted {
+                    if record.is_deletion() {
+                        entry.deleted.push(record.clone());
+                    } else if let Some(id) = &record.id {
+                        entry.updated.extend(
+                            returned
+                                .iter()
+                                .find(|r| r.id() == Some(id.as_str()))
+                                .cloned(),
+                        );
+                    } else {
+                        entry
+                            .created
+                            .extend(returned.iter().find(|r| r.matches(record)).cloned());
+                    }
+                }
+                entry.outcome = Some("success".into());
+            }
+            Err(err) => entry.outcome = Some(format!("{:#}", err)),
+        }
+
+        self.append(&entry)
+    }
+
+    /// Records that an entry was undone without changing anything, so the
+    /// next undo moves on to the one before it.
+    pub fn mark_undone(&self, domain: &str) -> anyhow::Result<()> {
+        let mut entry = AuditEntry::new(AuditEvent::Result, UNDO_COMMAND, domain);
+        entry.outcome = Some("success".into());
+        self.append(&entry)
+    }
+
+    /// Appends `entry` and syncs it to disk.
+    pub fn append(&self, entry: &AuditEntry) -> anyhow::Result<()> {
+        let mut options = OpenOptions::new();
+        options.append(true).create(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        let mut file = options.open(&self.path).context(format!(
+            "Failed to open the audit log {}",
+            self.path.display()
+        ))?;
+
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        file.write_all(line.as_bytes())?;
+        file.sync_data()?;
+
+        Ok(())
+    }
+
+    /// Reads every entry, oldest first.
+    pub fn read(&self) -> anyhow::Result<Vec<AuditEntry>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+
+        fs::read_to_string(&self.path)
+            .context(format!(
+                "Failed to read the audit log {}",
+                self.path.display()
+            ))?
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .enumerate()
+            .map(|(i, line)| {
+                serde_json::from_str(line).context(format!(
+                    "Invalid entry on line {} of {}",
+                    i + 1,
+                    self.path.display()
+                ))
+            })
+            .collect()
+    }
+}
+
+/// Command name undo entries are recorded under.
+pub const UNDO_COMMAND: &str = "undo";
+
+/// What reverting an entry takes, see [`plan_undo`].
+#[derive(Debug, Clone, Default)]
+pub struct UndoPlan {
+    /// Records to resubmit, deletions marked as such
+    pub changes: Vec<DNSRecord>,
+    /// Records that can't be reverted, with the reason
+    pub skipped: Vec<(DNSRecord, String)>,
+}
+
+impl UndoPlan {
+    /// Applies the plan to a local copy of the zone, for planning the next
+    /// step against it.
+    pub fn apply_to(&self, zone: &mut Vec<DNSRecord>) {
+        for change in &self.changes {
+            if change.is_deletion() {
+                zone.retain(|r| r.id != change.id);
+            } else {
+                zone.push(change.clone());
+            }
+        }
+    }
+}
+
+/// The last `steps` successful changes that haven't been undone yet, newest
+/// first, optionally only those of `domain`.
+///
+/// Every undo is recorded as a change itself (under [`UNDO_COMMAND`]) and
+/// cancels out the newest change before it, so repeated undos walk further
+/// back instead of redoing.
+pub fn undo_candidates<'a>(
+    entries: &'a [AuditEntry],
+    domain: Option<&str>,
+    steps: usize,
+) -> Vec<&'a AuditEntry> {
+    let mut undone: HashMap<String, usize> = HashMap::new();
+    let mut candidates = Vec::new();
+
+    let changes = entries.iter().rev().filter(|entry| {
+        entry.event == AuditEvent::Result
+            && entry.is_success()
+            && domain.is_none_or(|domain| entry.domain.eq_ignore_ascii_case(domain))
+    });
+
+    for entry in changes {
+        let pending = undone.entry(entry.domain.to_lowercase()).or_default();
+
+        if entry.command == UNDO_COMMAND {
+            *pending += 1;
+        } else if entry.created.is_empty() && entry.deleted.is_empty() && entry.updated.is_empty() {
+            continue;
+        } else if *pending > 0 {
+            *pending -= 1;
+        } else {
+            candidates.push(entry);
+            if candidates.len() == steps {
+                break;
+            }
+        }
+    }
+
+    candidates
+}
+
+/// Computes the inverse of `entry` against the current state of its zone:
+/// created records are deleted and deleted records recreated.
+///
+/// Records are matched by content, as ids change when a record is recreated.
+/// Anything that has changed since is skipped rather than guessed at.
+pub fn plan_undo(entry: &AuditEntry, zone: &[DNSRecord]) -> UndoPlan {
+    let mut plan = UndoPlan::default();
+
+    for created in &entry.created {
+        let same_id = zone.iter().find(|r| r.id.is_some() && r.id == created.id);
+
+        match same_id {
+            Some(current) if current.matches(created) => {
+                plan.changes.push(current.clone().into_deletion())
+            }
+            Some(_) => plan
+                .skipped
+                .push((created.clone(), "modified since".into())),
+            None => match zone.iter().find(|r| r.matches(created)) {
+                Some(current) => plan.changes.push(current.clone().into_deletion()),
+                None => plan.skipped.push((created.clone(), "already gone".into())),
+            },
+        }
+    }
+
+    for deleted in &entry.deleted {
+        if zone.iter().any(|r| r.matches(deleted)) {
+            plan.skipped.push((deleted.clone(), "exists again".into()));
+        } else {
+            plan.changes.push(DNSRecord {
+                id: None,
+                deleterecord: None,
+                state: None,
+                ..deleted.clone()
+            });
+        }
+    }
+
+    for updated in &entry.updated {
+        plan.skipped
+            .push((updated.clone(), "the previous value isn't logged".into()));
+    }
+
+    plan
+}
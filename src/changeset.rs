@@ -0,0 +1,437 @@
+use std::{collections::HashSet, thread, time::Duration};
+
+use anyhow::anyhow;
+
+use crate::{DNSRecord, DeletionPolicy, NetcupAPIClient, NetcupError};
+
+/// How often the zone is fetched again when the response to a commit doesn't
+/// show all changes yet, and how long to wait before each (not at all in the
+/// unit tests, whose zone doesn't change by waiting).
+const RECHECK_ATTEMPTS: u32 = 2;
+const RECHECK_DELAY: Duration = if cfg!(test) {
+    Duration::ZERO
+} else {
+    Duration::from_secs(2)
+};
+
+/// Additions and deletions for one zone, submitted together by
+/// [`commit`](Self::commit). Created with [`NetcupAPIClient::changeset`].
+#[must_use = "nothing is changed until the changeset is committed"]
+pub struct ZoneChangeset<'a> {
+    client: &'a NetcupAPIClient,
+    domain: String,
+    additions: Vec<DNSRecord>,
+    deletions: Vec<Deletion>,
+    policy: DeletionPolicy,
+}
+
+enum Deletion {
+    Record(DNSRecord),
+    Id(String),
+    Matching {
+        hostname: String,
+        record_type: String,
+        destination: Option<String>,
+    },
+}
+
+/// What [`ZoneChangeset::commit`] did.
+#[derive(Debug, Default)]
+pub struct ChangesetResult {
+    /// The added records as stored by netcup, with their ids, in the order
+    /// they were added to the changeset
+    pub added: Vec<DNSRecord>,
+    pub deleted: Vec<DNSRecord>,
+    /// Critical records the policy refused to delete
+    pub skipped: Vec<DNSRecord>,
+    /// The zone's records afterwards; empty if nothing had to be submitted
+    /// and the zone wasn't fetched either
+    pub zone: Vec<DNSRecord>,
+}
+
+impl NetcupAPIClient {
+    /// Starts a set of changes to `domain`, submitted together.
+    pub fn changeset(&self, domain: &str) -> ZoneChangeset<'_> {
+        ZoneChangeset {
+            client: self,
+            domain: domain.into(),
+            additions: Vec::new(),
+            deletions: Vec::new(),
+            policy: DeletionPolicy::default(),
+        }
+    }
+}
+
+impl ZoneChangeset<'_> {
+    /// Creates `record`, or updates the record with its id if it has one.
+    #[allow(clippy::should_implement_trait)]
+    pub fn add(mut self, record: DNSRecord) -> Self {
+        self.additions.push(record);
+        self
+    }
+
+    /// Deletes a record as fetched from the zone.
+    pub fn delete(mut self, record: DNSRecord) -> Self {
+        self.deletions.push(Deletion::Record(record));
+        self
+    }
+
+    pub fn delete_by_id(mut self, id: impl Into<String>) -> Self {
+        self.deletions.push(Deletion::Id(id.into()));
+        self
+    }
+
+    /// Deletes every record with this hostname and type, and destination if
+    /// given. It is an error if there is none.
+    pub fn delete_matching(
+        mut self,
+        hostname: impl Into<String>,
+        record_type: impl Into<String>,
+        destination: Option<&str>,
+    ) -> Self {
+        self.deletions.push(Deletion::Matching {
+            hostname: hostname.into(),
+            record_type: record_type.into().to_ascii_uppercase(),
+            destination: destination.map(String::from),
+        });
+        self
+    }
+
+    /// Which deletions are allowed, [`DeletionPolicy::ProtectCritical`]
+    /// unless set.
+    pub fn policy(mut self, policy: DeletionPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Resolves the deletions against one listing of the zone (only fetched
+    /// if a deletion or a new record needs it), checks the whole set and
+    /// submits it with
+    /// [`update_records`](NetcupAPIClient::update_records). Nothing is
+    /// submitted if any check fails, and afterwards every addition has to
+    /// show up in the zone and every deletion has to be gone. Netcup's
+    /// response sometimes lags behind, so if it doesn't show all changes the
+    /// zone is fetched again a few times before the error lists the changes
+    /// netcup did not apply.
+    pub fn commit(self) -> anyhow::Result<ChangesetResult> {
+        for record in &self.additions {
+            record.validate()?;
+        }
+
+        for (index, record) in self.additions.iter().enumerate() {
+            if self.additions[..index].iter().any(|r| r.matches(record)) {
+                return Err(anyhow!("{} is added twice", record));
+            }
+        }
+
+        // New records are told apart from equal ones already there by id
+        let needs_zone = self
+            .deletions
+            .iter()
+            .any(|deletion| !matches!(deletion, Deletion::Record(_)))
+            || self.additions.iter().any(|record| record.id.is_none());
+        let zone = if needs_zone {
+            self.client.list_records(&self.domain)?
+        } else {
+            Vec::new()
+        };
+
+        let deleted = resolve_deletions(&self.domain, &zone, self.deletions)?;
+
+        if let Some(record) = deleted.iter().find(|r| r.id.is_none()) {
+            return Err(anyhow!("Record has no id: {}", record));
+        }
+
+        if let Some(record) = self.additions.iter().find(|added| {
+            deleted.iter().any(|r| match &added.id {
+                Some(id) => r.id.as_ref() == Some(id),
+                None => r.matches(added),
+            })
+        }) {
+            return Err(anyhow!("{} is both added and deleted", record));
+        }
+
+        let (deleted, skipped) = self.policy.partition(deleted);
+
+        if self.additions.is_empty() && deleted.is_empty() {
+            return Ok(ChangesetResult {
+                skipped,
+                zone,
+                ..Default::default()
+            });
+        }
+
+        let existing: HashSet<String> = zone.iter().filter_map(|r| r.id.clone()).collect();
+        let changes = self
+            .additions
+            .iter()
+            .cloned()
+            .chain(deleted.iter().cloned().map(DNSRecord::into_deletion))
+            .collect();
+        let mut zone = self.client.update_records(&self.domain, changes)?;
+
+        let mut attempt = 0;
+        let added = loop {
+            let (added, missing, still_present) =
+                unapplied(&self.additions, &deleted, &existing, &zone);
+            if missing.is_empty() && still_present.is_empty() {
+                break added;
+            }
+
+            if attempt < RECHECK_ATTEMPTS && !self.client.is_dry_run() {
+                attempt += 1;
+                log::info!(
+                    domain = self.domain.as_str();
+                    "Changes to {} not visible yet, checking again",
+                    self.domain
+                );
+                thread::sleep(RECHECK_DELAY);
+                zone = self.client.list_records(&self.domain)?;
+                continue;
+            }
+
+            let mut problems = Vec::new();
+            if !missing.is_empty() {
+                let missing: Vec<_> = missing.iter().map(ToString::to_string).collect();
+                problems.push(format!("missing {}", missing.join(", ")));
+            }
+            if !still_present.is_empty() {
+                let present: Vec<_> = still_present.iter().map(ToString::to_string).collect();
+                problems.push(format!("still present {}", present.join(", ")));
+            }
+            return Err(anyhow!(
+                "Netcup accepted the changes to {} but did not apply all of them: {}",
+                self.domain,
+                problems.join("; ")
+            ));
+        };
+
+        Ok(ChangesetResult {
+            added,
+            deleted,
+            skipped,
+            zone,
+        })
+    }
+}
+
+/// Compares `zone` with the changes: the added records as stored, additions
+/// that don't show up and deleted records that are still there. A new
+/// record is one whose id isn't among the `existing` ones, so an equal
+/// value that was there before isn't taken for it.
+fn unapplied<'a>(
+    additions: &'a [DNSRecord],
+    deleted: &[DNSRecord],
+    existing: &HashSet<String>,
+    zone: &'a [DNSRecord],
+) -> (Vec<DNSRecord>, Vec<&'a DNSRecord>, Vec<&'a DNSRecord>) {
+    let mut added = Vec::new();
+    let mut missing = Vec::new();
+    for record in additions {
+        match zone.iter().find(|r| {
+            r.matches(record)
+                && match &record.id {
+                    Some(id) => r.id.as_ref() == Some(id),
+                    None => r.id.as_ref().is_none_or(|id| !existing.contains(id)),
+                }
+        }) {
+            Some(stored) => added.push(stored.clone()),
+            None => missing.push(record),
+        }
+    }
+
+    let still_present = zone
+        .iter()
+        .filter(|r| r.id.is_some() && deleted.iter().any(|d| d.id == r.id))
+        .collect();
+
+    (added, missing, still_present)
+}
+
+/// The records the deletions refer to, each once. Fails if an id or a
+/// pattern matches nothing; TXT values missing at one name are reported
+/// together as a [`NetcupError::TxtRecordNotFound`].
+fn resolve_deletions(
+    domain: &str,
+    zone: &[DNSRecord],
+    deletions: Vec<Deletion>,
+) -> anyhow::Result<Vec<DNSRecord>> {
+    let mut resolved: Vec<DNSRecord> = Vec::new();
+    let mut unmatched = Vec::new();
+
+    for deletion in deletions {
+        let found: Vec<DNSRecord> = match deletion {
+            Deletion::Record(record) => vec![record],
+            Deletion::Id(id) => match zone.iter().find(|r| r.id.as_deref() == Some(&id)) {
+                Some(record) => vec![record.clone()],
+                None => {
+                    return Err(NetcupError::RecordNotFound {
+                        domain: domain.into(),
+                        id,
+                    }
+                    .into())
+                }
+            },
+            Deletion::Matching {
+                hostname,
+                record_type,
+                destination,
+            } => {
+                let found: Vec<_> = zone
+                    .iter()
+                    .filter(|r| {
+                        r.hostname == hostname
+                            && r.record_type == record_type
+                            && destination.as_ref().is_none_or(|d| r.has_destination(d))
+                    })
+                    .cloned()
+                    .collect();
+
+                if found.is_empty() {
+                    unmatched.push((hostname, record_type, destination));
+                }
+                found
+            }
+        };
+
+        for record in found {
+            if record.id.is_none() || !resolved.iter().any(|r| r.id == record.id) {
+                resolved.push(record);
+            }
+        }
+    }
+
+    let Some((hostname, _, _)) = unmatched.first() else {
+        return Ok(resolved);
+    };
+
+    let txt_values: Option<Vec<String>> = unmatched
+        .iter()
+        .map(|(h, record_type, destination)| {
+            (h == hostname && record_type == "TXT")
+                .then(|| destination.clone())
+                .flatten()
+        })
+        .collect();
+
+    match txt_values {
+        Some(contents) => Err(NetcupError::TxtRecordNotFound {
+            domain: domain.into(),
+            hostname: hostname.clone(),
+            contents,
+        }
+        .into()),
+        None => {
+            let patterns: Vec<_> = unmatched
+                .iter()
+                .map(|(hostname, record_type, destination)| match destination {
+                    Some(destination) => format!("{} {} {}", hostname, record_type, destination),
+                    None => format!("{} {}", hostname, record_type),
+                })
+                .collect();
+            Err(anyhow!("No records match {}", patterns.join(", ")))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{
+        testing::{self, txt},
+        DNSRecord, NetcupError, RecordType,
+    };
+
+    #[test]
+    fn duplicate_of_an_existing_value_reports_the_new_record() {
+        let mock = testing::MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_success("infoDnsRecords", json!({ "dnsrecords": [txt(1, "token")] }))
+            .respond_success(
+                "updateDnsRecords",
+                json!({ "dnsrecords": [txt(1, "token"), txt(2, "token")] }),
+            );
+
+        let record = client
+            .force_add_txt_record("example.com", "_acme-challenge", "token")
+            .unwrap();
+        assert_eq!(record.id(), Some("2"));
+    }
+
+    #[test]
+    fn added_records_are_in_the_order_they_were_added() {
+        let mock = testing::MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_success("infoDnsRecords", json!({ "dnsrecords": [txt(1, "a")] }))
+            .respond_success(
+                "updateDnsRecords",
+                json!({ "dnsrecords": [txt(1, "a"), txt(3, "b"), txt(2, "a")] }),
+            );
+
+        let added = client
+            .add_txt_records("example.com", "_acme-challenge", &["a", "b"])
+            .unwrap();
+        let ids: Vec<_> = added.iter().map(|r| r.id().unwrap()).collect();
+        assert_eq!(ids, ["2", "3"]);
+    }
+
+    #[test]
+    fn deletes_and_adds_in_one_call() {
+        let mock = testing::MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_success("infoDnsRecords", json!({ "dnsrecords": [txt(1, "old")] }))
+            .respond_success("updateDnsRecords", json!({ "dnsrecords": [txt(2, "new")] }));
+
+        let result = client
+            .changeset("example.com")
+            .delete_matching("_acme-challenge", RecordType::Txt, Some("old"))
+            .add(DNSRecord::new("_acme-challenge", RecordType::Txt, "new"))
+            .commit()
+            .unwrap();
+
+        assert_eq!(result.added[0].id(), Some("2"));
+        assert_eq!(result.deleted[0].id(), Some("1"));
+        assert_eq!(
+            mock.actions(),
+            ["login", "infoDnsRecords", "updateDnsRecords"]
+        );
+        let sent = &mock.requests()[2]["param"]["dnsrecordset"]["dnsrecords"];
+        assert_eq!(sent.as_array().unwrap().len(), 2);
+        assert_eq!(sent[1]["deleterecord"], true);
+    }
+
+    #[test]
+    fn missing_deletion_submits_nothing() {
+        let mock = testing::MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_success("infoDnsRecords", json!({ "dnsrecords": [txt(1, "a")] }));
+
+        let err = client
+            .changeset("example.com")
+            .delete_matching("_acme-challenge", RecordType::Txt, Some("b"))
+            .commit()
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<NetcupError>(),
+            Some(NetcupError::TxtRecordNotFound { .. })
+        ));
+        assert_eq!(mock.remaining(), 0);
+        assert_eq!(mock.actions(), ["login", "infoDnsRecords"]);
+    }
+
+    #[test]
+    fn refuses_adding_and_deleting_the_same_record() {
+        let mock = testing::MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_success("infoDnsRecords", json!({ "dnsrecords": [txt(1, "a")] }));
+
+        let result = client
+            .changeset("example.com")
+            .delete_by_id("1")
+            .add(DNSRecord::new("_acme-challenge", RecordType::Txt, "a"))
+            .commit();
+        assert!(result.is_err());
+        assert_eq!(mock.actions(), ["login", "infoDnsRecords"]);
+    }
+}
@@ -0,0 +1,181 @@
+use anyhow::bail;
+use serde::Deserialize;
+
+use crate::{request::Request, DNSRecord, DeletionPolicy, NetcupAPIClient};
+
+const CHALLENGE_LABEL: &str = "_acme-challenge";
+
+/// Whether `record` is an ACME DNS-01 challenge, i.e. a TXT record at
+/// `_acme-challenge` or `_acme-challenge.<host>`.
+pub fn is_acme_challenge(record: &DNSRecord) -> bool {
+    record.record_type == "TXT"
+        && (record.hostname == CHALLENGE_LABEL
+            || record
+                .hostname
+                .strip_prefix(CHALLENGE_LABEL)
+                .is_some_and(|rest| rest.starts_with('.')))
+}
+
+/// Selects the challenge records among `records`, optionally only those of
+/// one host (`www` and `_acme-challenge.www` are equivalent).
+pub fn select_challenge_records(records: Vec<DNSRecord>, host: Option<&str>) -> Vec<DNSRecord> {
+    let hostname = host.map(|host| {
+        if host == CHALLENGE_LABEL || host.starts_with("_acme-challenge.") {
+            host.to_string()
+        } else if host == "@" {
+            CHALLENGE_LABEL.to_string()
+        } else {
+            format!("{}.{}", CHALLENGE_LABEL, host)
+        }
+    });
+
+    records
+        .into_iter()
+        .filter(is_acme_challenge)
+        .filter(|r| hostname.as_deref().is_none_or(|h| r.hostname == h))
+        .collect()
+}
+
+#[derive(Debug, Deserialize)]
+struct DomainInfo {
+    domainname: String,
+}
+
+impl NetcupAPIClient {
+    /// Lists every domain of the account via `listallDomains`. Netcup only
+    /// offers this action to some (reseller) accounts.
+    pub fn list_all_domains(&self) -> anyhow::Result<Vec<String>> {
+        let payload = Request::new("listallDomains", self.session());
+
+        let response = self.http.call::<Vec<DomainInfo>>(&payload)?.check(None)?;
+
+        match response.response_data {
+            Some(domains) => Ok(domains.into_iter().map(|d| d.domainname).collect()),
+            None => bail!(
+                "Could not list domains: {}",
+                response.long_message.unwrap_or(response.short_message)
+            ),
+        }
+    }
+
+    /// Removes leftover ACME challenge records from `domain` in one batch and
+    /// returns them. With `dry_run` nothing is deleted.
+    pub fn cleanup_challenges(
+        &self,
+        domain: &str,
+        host: Option<&str>,
+        dry_run: bool,
+    ) -> anyhow::Result<Vec<DNSRecord>> {
+        let records = select_challenge_records(self.list_records(domain)?, host);
+
+        if dry_run || records.is_empty() {
+            return Ok(records);
+        }
+
+        let outcome = self.delete_records(domain, records, DeletionPolicy::ProtectCritical)?;
+
+        Ok(outcome.deleted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::{self, txt, MockTransport};
+
+    fn record(hostname: &str, record_type: &str) -> DNSRecord {
+        DNSRecord::new(hostname, record_type, "token")
+    }
+
+    /// A zone with challenges at the apex and for www, a TXT record that only
+    /// looks like one and an A record.
+    fn zone() -> serde_json::Value {
+        let mut www = txt(2, "www");
+        www["hostname"] = "_acme-challenge.www".into();
+        let mut lookalike = txt(3, "lookalike");
+        lookalike["hostname"] = "_acme-challengewww".into();
+        let mut address = txt(4, "192.0.2.1");
+        address["hostname"] = "_acme-challenge".into();
+        address["type"] = "A".into();
+        json!({ "dnsrecords": [txt(1, "apex"), www, lookalike, address] })
+    }
+
+    #[test]
+    fn only_txt_records_under_the_challenge_label_are_challenges() {
+        assert!(is_acme_challenge(&record("_acme-challenge", "TXT")));
+        assert!(is_acme_challenge(&record("_acme-challenge.www", "TXT")));
+        assert!(!is_acme_challenge(&record("_acme-challengewww", "TXT")));
+        assert!(!is_acme_challenge(&record("_acme-challenge", "CNAME")));
+        assert!(!is_acme_challenge(&record("www", "TXT")));
+    }
+
+    #[test]
+    fn hostname_restricts_the_selection_to_one_host() {
+        let records: Vec<DNSRecord> = serde_json::from_value(zone()["dnsrecords"].clone()).unwrap();
+        let ids = |host| {
+            select_challenge_records(records.clone(), host)
+                .into_iter()
+                .map(|record| record.id.unwrap())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(ids(None), ["1", "2"]);
+        assert_eq!(ids(Some("www")), ["2"]);
+        assert_eq!(ids(Some("_acme-challenge.www")), ["2"]);
+        assert_eq!(ids(Some("@")), ["1"]);
+        assert!(ids(Some("mail")).is_empty());
+    }
+
+    #[test]
+    fn every_challenge_is_deleted_in_one_call() {
+        let mock = MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_success("infoDnsRecords", zone())
+            .respond_success("updateDnsRecords", json!({ "dnsrecords": [] }));
+
+        let removed = client
+            .cleanup_challenges("example.de", None, false)
+            .unwrap();
+
+        let ids: Vec<_> = removed.iter().map(|record| record.id.as_deref()).collect();
+        assert_eq!(ids, [Some("1"), Some("2")]);
+        assert_eq!(
+            mock.actions(),
+            ["login", "infoDnsRecords", "updateDnsRecords"]
+        );
+        let sent = mock.requests()[2]["param"]["dnsrecordset"]["dnsrecords"].clone();
+        let sent: Vec<_> = sent
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|record| (record["id"].clone(), record["deleterecord"].clone()))
+            .collect();
+        assert_eq!(sent, [(json!("1"), json!(true)), (json!("2"), json!(true))]);
+    }
+
+    #[test]
+    fn clean_zone_and_dry_run_send_no_update() {
+        let mock = MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_success("infoDnsRecords", json!({ "dnsrecords": [] }))
+            .respond_success("infoDnsRecords", zone());
+
+        assert!(client
+            .cleanup_challenges("example.de", None, false)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            client
+                .cleanup_challenges("example.de", Some("www"), true)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            mock.actions(),
+            ["login", "infoDnsRecords", "infoDnsRecords"]
+        );
+    }
+}
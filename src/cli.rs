@@ -0,0 +1,69 @@
+//! Subcommand implementations of the binary.
+
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+pub mod apply;
+pub mod audit;
+pub mod batch;
+pub mod caa;
+pub mod certbot;
+pub mod check;
+pub mod cleanup;
+pub mod config;
+pub mod dyndns;
+pub mod export;
+pub mod interrupt;
+pub mod lock;
+pub mod logging;
+pub mod output;
+pub mod progress;
+pub mod record;
+pub mod selftest;
+pub mod truenas;
+pub mod ttl;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod undo;
+pub mod validate;
+pub mod wait;
+pub mod watch;
+pub mod zone;
+
+/// Runs `f` for every item on at most `concurrency` threads and returns the
+/// results in input order.
+pub fn for_each_bounded<T, R, F>(items: &[T], concurrency: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new((0..items.len()).map(|_| None).collect::<Vec<_>>());
+
+    thread::scope(|scope| {
+        for _ in 0..concurrency.clamp(1, items.len().max(1)) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(index) else {
+                    break;
+                };
+
+                let result = f(item);
+                results.lock().unwrap()[index] = Some(result);
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .map(|result| result.expect("every item was processed"))
+        .collect()
+}
@@ -0,0 +1,398 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use clap::Args;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use truenas_acme_auth::{
+    compute_zone_diff, normalize_dns_name, DNSRecord, DeletionPolicy, NetcupAPIClient,
+};
+
+use super::output::{write_line, FormatArgs, OutputFormat};
+
+#[derive(Debug, Args)]
+pub struct ApplyArgs {
+    pub domain: String,
+    /// The desired records: JSON (an array, or an `export --format json`
+    /// document) or, ending in .toml, `[[records]]` tables
+    file: PathBuf,
+    /// Also delete the records that are not in the file
+    #[arg(long)]
+    prune: bool,
+    #[command(flatten)]
+    format: FormatArgs,
+}
+
+/// The accepted shapes of the file.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DesiredRecords {
+    List(Vec<DNSRecord>),
+    Document { records: Vec<DNSRecord> },
+}
+
+/// Implements `apply`: makes the zone match the file with one changeset.
+/// Records missing from the file are only deleted with `--prune`, and the
+/// policy still protects critical ones. A dry run prints the difference and
+/// stops there.
+pub fn apply(
+    client: &NetcupAPIClient,
+    args: ApplyArgs,
+    policy: DeletionPolicy,
+    json: bool,
+) -> anyhow::Result<()> {
+    apply_to(client, args, policy, json, &mut io::stdout())
+}
+
+fn apply_to(
+    client: &NetcupAPIClient,
+    args: ApplyArgs,
+    policy: DeletionPolicy,
+    json: bool,
+    out: &mut impl Write,
+) -> anyhow::Result<()> {
+    let format = args.format.resolve(json);
+    let domain = normalize_dns_name(&args.domain)?;
+    let desired = read_desired(&args.file)?;
+    let current = client.list_records(&domain)?;
+    let diff = compute_zone_diff(&current, &desired);
+
+    let mut report = Report {
+        domain: &domain,
+        dry_run: client.is_dry_run(),
+        created: diff.create.clone(),
+        deleted: Vec::new(),
+        kept: Vec::new(),
+        protected: Vec::new(),
+        unchanged: diff.unchanged,
+    };
+    if args.prune {
+        (report.deleted, report.protected) = policy.partition(diff.delete);
+    } else {
+        report.kept = diff.delete;
+    }
+
+    if report.dry_run || (report.created.is_empty() && report.deleted.is_empty()) {
+        return report.print(format, out);
+    }
+
+    // What stays as it is won't change with the commit
+    if format == OutputFormat::Ndjson {
+        report.print_known(out)?;
+    }
+
+    let mut changeset = client.changeset(&domain).policy(policy);
+    for record in diff.create {
+        changeset = changeset.add(record);
+    }
+    for record in &report.deleted {
+        changeset = changeset.delete(record.clone());
+    }
+    let result = changeset.commit()?;
+
+    report.created = result.added;
+    report.deleted = result.deleted;
+    if format == OutputFormat::Ndjson {
+        return report.print_committed(out);
+    }
+    report.print(format, out)
+}
+
+fn read_desired(path: &Path) -> anyhow::Result<Vec<DNSRecord>> {
+    let contents =
+        fs::read_to_string(path).context(format!("Failed to read {}", path.display()))?;
+
+    let records = if path.extension().is_some_and(|ext| ext == "toml") {
+        #[derive(Deserialize)]
+        struct TomlFile {
+            records: Vec<DNSRecord>,
+        }
+        toml::from_str::<TomlFile>(&contents)
+            .context(format!("Failed to parse {}", path.display()))?
+            .records
+    } else {
+        match serde_json::from_str(&contents)
+            .context(format!("Failed to parse {}", path.display()))?
+        {
+            DesiredRecords::List(records) | DesiredRecords::Document { records } => records,
+        }
+    };
+
+    Ok(records)
+}
+
+struct Report<'a> {
+    domain: &'a str,
+    dry_run: bool,
+    created: Vec<DNSRecord>,
+    deleted: Vec<DNSRecord>,
+    /// Not in the file, but `--prune` wasn't given
+    kept: Vec<DNSRecord>,
+    /// Not in the file, but the policy protects them
+    protected: Vec<DNSRecord>,
+    unchanged: Vec<DNSRecord>,
+}
+
+/// An element of the `changes` array of the json format, and a line of the
+/// ndjson one.
+#[derive(Serialize)]
+struct Change<'a> {
+    change: &'static str,
+    record: &'a DNSRecord,
+}
+
+impl Report<'_> {
+    /// The changes known before the commit, in the order they are printed.
+    fn known(&self) -> impl Iterator<Item = Change<'_>> {
+        changes("unchanged", &self.unchanged)
+            .chain(changes("kept", &self.kept))
+            .chain(changes("protected", &self.protected))
+    }
+
+    fn committed(&self) -> impl Iterator<Item = Change<'_>> {
+        changes("created", &self.created).chain(changes("deleted", &self.deleted))
+    }
+
+    fn print_known(&self, out: &mut impl Write) -> anyhow::Result<()> {
+        self.known().try_for_each(|change| write_line(out, &change))
+    }
+
+    fn print_committed(&self, out: &mut impl Write) -> anyhow::Result<()> {
+        self.committed()
+            .try_for_each(|change| write_line(out, &change))
+    }
+
+    /// With `Json` `{"domain": ..., "dry_run": ..., "changes": [...]}`, where
+    /// each change is `{"change": "created", "record": {...}}`, and with
+    /// `Ndjson` these changes.
+    fn print(&self, format: OutputFormat, out: &mut impl Write) -> anyhow::Result<()> {
+        match format {
+            OutputFormat::Json => {
+                let changes: Vec<_> = self.known().chain(self.committed()).collect();
+                writeln!(
+                    out,
+                    "{}",
+                    json!({
+                        "domain": self.domain,
+                        "dry_run": self.dry_run,
+                        "changes": changes,
+                    })
+                )?;
+                return Ok(());
+            }
+            OutputFormat::Ndjson => {
+                self.print_known(out)?;
+                return self.print_committed(out);
+            }
+            OutputFormat::Text => {}
+        }
+
+        for record in &self.created {
+            writeln!(out, "+ {}", record)?;
+        }
+        for record in &self.deleted {
+            writeln!(out, "- {}", record)?;
+        }
+        for record in &self.kept {
+            writeln!(out, "  {} (not in the file, kept without --prune)", record)?;
+        }
+        for record in &self.protected {
+            writeln!(out, "  {} (not in the file, protected)", record)?;
+        }
+
+        let verb = if self.dry_run { "would be" } else { "were" };
+        writeln!(
+            out,
+            "{} records {} created and {} deleted in {}, {} unchanged",
+            self.created.len(),
+            verb,
+            self.deleted.len(),
+            self.domain,
+            self.unchanged.len()
+        )?;
+        Ok(())
+    }
+}
+
+fn changes<'a>(change: &'static str, records: &'a [DNSRecord]) -> impl Iterator<Item = Change<'a>> {
+    records.iter().map(move |record| Change { change, record })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use clap::Parser;
+    use serde_json::{json, Value};
+    use truenas_acme_auth::{
+        testing::{self, txt, MockTransport},
+        NetcupTransport, TransportResponse,
+    };
+
+    use super::*;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        args: ApplyArgs,
+    }
+
+    /// Output shared with [`Slow`], which only sees what was flushed.
+    #[derive(Debug, Clone, Default)]
+    struct Output {
+        buffer: Arc<Mutex<Vec<u8>>>,
+        flushed: Arc<Mutex<String>>,
+    }
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.buffer.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            *self.flushed.lock().unwrap() =
+                String::from_utf8(self.buffer.lock().unwrap().clone()).unwrap();
+            Ok(())
+        }
+    }
+
+    /// Notes how many lines had been flushed when each request went out,
+    /// i.e. before its response arrived.
+    #[derive(Debug)]
+    struct Slow {
+        mock: MockTransport,
+        output: Output,
+        lines_before: Arc<Mutex<Vec<usize>>>,
+    }
+
+    impl NetcupTransport for Slow {
+        fn post(&self, endpoint: &str, body: String) -> anyhow::Result<TransportResponse> {
+            let lines = self.output.flushed.lock().unwrap().lines().count();
+            self.lines_before.lock().unwrap().push(lines);
+            self.mock.post(endpoint, body)
+        }
+    }
+
+    fn args(file: &Path, format: &str) -> ApplyArgs {
+        Cli::parse_from([
+            "apply",
+            "example.com",
+            file.to_str().unwrap(),
+            "--format",
+            format,
+        ])
+        .args
+    }
+
+    fn desired() -> PathBuf {
+        let path = testing::temp_dir("apply").join("zone.json");
+        let records = json!([
+            { "hostname": "_acme-challenge", "type": "TXT", "destination": "keep" },
+            { "hostname": "_acme-challenge", "type": "TXT", "destination": "new" },
+        ]);
+        fs::write(&path, records.to_string()).unwrap();
+        path
+    }
+
+    fn lines(output: &str) -> Vec<Value> {
+        output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn ndjson_lines_are_the_json_changes() {
+        let file = desired();
+        let mut printed = Vec::new();
+        for format in ["json", "ndjson"] {
+            let mock = MockTransport::new();
+            mock.respond_success("login", json!({ "apisessionid": "session" }));
+            let client = testing::builder()
+                .transport(mock.clone())
+                .dry_run(true)
+                .login("12345".into(), "password".into(), "key".into())
+                .unwrap();
+            mock.respond_success(
+                "infoDnsRecords",
+                json!({ "dnsrecords": [txt(1, "keep"), txt(2, "old")] }),
+            );
+
+            let mut out = Vec::new();
+            apply_to(
+                &client,
+                args(&file, format),
+                DeletionPolicy::default(),
+                false,
+                &mut out,
+            )
+            .unwrap();
+            printed.push(String::from_utf8(out).unwrap());
+        }
+
+        let json: Value = serde_json::from_str(&printed[0]).unwrap();
+        assert_eq!(json["changes"], Value::Array(lines(&printed[1])));
+        let changes: Vec<_> = lines(&printed[1])
+            .iter()
+            .map(|line| line["change"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(changes, ["unchanged", "kept", "created"]);
+    }
+
+    #[test]
+    fn ndjson_prints_what_is_known_before_the_commit() {
+        let file = desired();
+        let mock = MockTransport::new();
+        let output = Output::default();
+        let lines_before = Arc::new(Mutex::new(Vec::new()));
+        let slow = Slow {
+            mock: mock.clone(),
+            output: output.clone(),
+            lines_before: lines_before.clone(),
+        };
+        mock.respond_success("login", json!({ "apisessionid": "session" }));
+        let client = testing::builder()
+            .transport(slow)
+            .login("12345".into(), "password".into(), "key".into())
+            .unwrap();
+        let zone = json!({ "dnsrecords": [txt(1, "keep"), txt(2, "old")] });
+        mock.respond_success("infoDnsRecords", zone.clone())
+            .respond_success("infoDnsRecords", zone)
+            .respond_success(
+                "updateDnsRecords",
+                json!({ "dnsrecords": [txt(1, "keep"), txt(2, "old"), txt(3, "new")] }),
+            );
+
+        let mut out = output.clone();
+        apply_to(
+            &client,
+            args(&file, "ndjson"),
+            DeletionPolicy::default(),
+            false,
+            &mut out,
+        )
+        .unwrap();
+
+        assert_eq!(
+            mock.actions(),
+            [
+                "login",
+                "infoDnsRecords",
+                "infoDnsRecords",
+                "updateDnsRecords"
+            ]
+        );
+        // Nothing before the zone was listed, its unchanged and kept records
+        // before the commit asked for it again
+        assert_eq!(lines_before.lock().unwrap()[1..], [0, 2, 2]);
+        let printed = lines(&output.flushed.lock().unwrap());
+        assert_eq!(printed.len(), 3);
+        assert_eq!(printed[2]["change"], "created");
+        assert_eq!(printed[2]["record"]["id"], "3");
+    }
+}
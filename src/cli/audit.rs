@@ -0,0 +1,67 @@
+use clap::Subcommand;
+use truenas_acme_auth::{AuditEvent, AuditLog};
+
+#[derive(Debug, Subcommand)]
+pub enum AuditCommands {
+    /// Print the most recent entries for a domain
+    Show {
+        domain: String,
+        /// How many entries to print
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+}
+
+/// Implements `audit show`.
+pub fn show(audit_log: &AuditLog, domain: &str, limit: usize) -> anyhow::Result<()> {
+    let entries: Vec<_> = audit_log
+        .read()?
+        .into_iter()
+        .filter(|entry| entry.domain.eq_ignore_ascii_case(domain))
+        .collect();
+
+    if entries.is_empty() {
+        println!(
+            "No entries for {} in {}",
+            domain,
+            audit_log.path().display()
+        );
+        return Ok(());
+    }
+
+    for entry in &entries[entries.len().saturating_sub(limit)..] {
+        match entry.event {
+            AuditEvent::Intent => {
+                println!("{}  {}  intent", entry.timestamp, entry.command);
+                for record in &entry.records {
+                    let action = if record.is_deletion() {
+                        "delete"
+                    } else {
+                        "submit"
+                    };
+                    println!("    {}  {}", action, record);
+                }
+            }
+            AuditEvent::Result => {
+                println!(
+                    "{}  {}  {} (request {})",
+                    entry.timestamp,
+                    entry.command,
+                    entry.outcome.as_deref().unwrap_or("unknown"),
+                    entry.server_request_id.as_deref().unwrap_or("-")
+                );
+                for record in &entry.created {
+                    println!("    created {}", record);
+                }
+                for record in &entry.updated {
+                    println!("    updated {}", record);
+                }
+                for record in &entry.deleted {
+                    println!("    deleted {}", record);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
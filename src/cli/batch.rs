@@ -0,0 +1,462 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Read},
+    path::PathBuf,
+};
+
+use anyhow::{anyhow, bail, Context};
+use clap::Args;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use truenas_acme_auth::{
+    normalize_dns_name, split_record_name, DNSRecord, NetcupAPIClient, RecordType,
+};
+
+use super::{
+    interrupt::Interrupt,
+    output::{print_line, FormatArgs, OutputFormat},
+};
+
+#[derive(Debug, Args)]
+pub struct BatchArgs {
+    /// File with one `<domain> <hostname> <content>` or
+    /// `{"domain": ..., "hostname": ..., "content": ...}` per line, `-` or
+    /// nothing for stdin
+    file: Option<PathBuf>,
+    #[command(flatten)]
+    format: FormatArgs,
+}
+
+/// One line of the input. `domain` is the zone, `hostname` is relative to it
+/// or the full name.
+#[derive(Debug, Clone, Deserialize)]
+struct Entry {
+    domain: String,
+    hostname: String,
+    content: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+    Created,
+    Exists,
+    Deleted,
+    Absent,
+    Failed,
+}
+
+/// What happened to one entry, in the order of the input.
+#[derive(Debug, Serialize)]
+struct EntryResult {
+    line: usize,
+    domain: String,
+    hostname: String,
+    content: String,
+    status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Clone, Copy)]
+enum Operation<'a> {
+    /// Tracking what was created
+    Set(&'a Interrupt),
+    Unset,
+}
+
+/// Implements `set-batch`: adds every value that doesn't exist yet, with one
+/// `updateDnsRecords` call per zone.
+pub fn set_batch(
+    client: &NetcupAPIClient,
+    args: BatchArgs,
+    json: bool,
+    interrupt: &Interrupt,
+) -> anyhow::Result<()> {
+    run(client, args, json, Operation::Set(interrupt))
+}
+
+/// Implements `unset-batch`: deletes every value that exists, with one
+/// `updateDnsRecords` call per zone. Values that don't exist are reported as
+/// absent, not as failures.
+pub fn unset_batch(client: &NetcupAPIClient, args: BatchArgs, json: bool) -> anyhow::Result<()> {
+    run(client, args, json, Operation::Unset)
+}
+
+fn run(
+    client: &NetcupAPIClient,
+    args: BatchArgs,
+    json: bool,
+    operation: Operation,
+) -> anyhow::Result<()> {
+    let format = args.format.resolve(json);
+    let input = match args.file.as_deref() {
+        Some(path) if path.as_os_str() != "-" => {
+            fs::read_to_string(path).context(format!("Failed to read {}", path.display()))?
+        }
+        _ => {
+            let mut input = String::new();
+            io::stdin()
+                .read_to_string(&mut input)
+                .context("Failed to read stdin")?;
+            input
+        }
+    };
+
+    let (mut results, zones) = parse_input(&input);
+
+    // With NDJSON each entry is printed once its zone is done, those that
+    // didn't parse right away
+    if format == OutputFormat::Ndjson {
+        for result in results.iter().filter(|result| result.error.is_some()) {
+            print_line(result)?;
+        }
+    }
+
+    for (domain, indices) in &zones {
+        let outcome = match operation {
+            Operation::Set(interrupt) => set_zone(client, domain, &mut results, indices, interrupt),
+            Operation::Unset => unset_zone(client, domain, &mut results, indices),
+        };
+        if let Err(err) = outcome {
+            for &index in indices {
+                let result = &mut results[index];
+                if result.error.is_none() {
+                    result.status = Status::Failed;
+                    result.error = Some(format!("{:#}", err));
+                }
+            }
+        }
+        if format == OutputFormat::Ndjson {
+            for &index in indices {
+                print_line(&results[index])?;
+            }
+        }
+    }
+
+    let failed = results
+        .iter()
+        .filter(|result| result.status == Status::Failed)
+        .count();
+
+    match format {
+        OutputFormat::Json => println!(
+            "{}",
+            json!({ "dry_run": client.is_dry_run(), "entries": results })
+        ),
+        OutputFormat::Ndjson => {}
+        OutputFormat::Text => {
+            for result in &results {
+                print_result(result);
+            }
+        }
+    }
+
+    if failed > 0 {
+        bail!("{} of {} entries failed", failed, results.len());
+    }
+
+    Ok(())
+}
+
+/// The entries of the input, failed if they don't parse, and the indices of
+/// the others grouped by zone.
+fn parse_input(input: &str) -> (Vec<EntryResult>, BTreeMap<String, Vec<usize>>) {
+    let mut results = Vec::new();
+    let mut zones: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+
+    for (index, line) in input.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (entry, error) = match parse_entry(line) {
+            Ok(entry) => (entry, None),
+            Err(err) => {
+                let entry = Entry {
+                    domain: String::new(),
+                    hostname: String::new(),
+                    content: line.into(),
+                };
+                (entry, Some(format!("{:#}", err)))
+            }
+        };
+
+        if error.is_none() {
+            zones
+                .entry(entry.domain.clone())
+                .or_default()
+                .push(results.len());
+        }
+        results.push(EntryResult {
+            line: index + 1,
+            domain: entry.domain,
+            hostname: entry.hostname,
+            content: entry.content,
+            status: Status::Failed,
+            id: None,
+            error,
+        });
+    }
+
+    (results, zones)
+}
+
+/// Reads `<domain> <hostname> <content>` or a JSON object. The content may
+/// contain spaces, everything after the hostname belongs to it.
+fn parse_entry(line: &str) -> anyhow::Result<Entry> {
+    let mut entry = if line.starts_with('{') {
+        serde_json::from_str::<Entry>(line).context("Invalid JSON entry")?
+    } else {
+        let mut fields = line.splitn(3, char::is_whitespace);
+        match (fields.next(), fields.next(), fields.next()) {
+            (Some(domain), Some(hostname), Some(content)) if !content.trim().is_empty() => Entry {
+                domain: domain.into(),
+                hostname: hostname.into(),
+                content: content.trim().into(),
+            },
+            _ => bail!("Expected <domain> <hostname> <content>"),
+        }
+    };
+
+    entry.domain = normalize_dns_name(&entry.domain)?;
+    if entry.domain.is_empty() || entry.hostname.is_empty() {
+        return Err(anyhow!("The domain and hostname can't be empty"));
+    }
+    if let Ok(hostname) = split_record_name(&entry.hostname, &entry.domain) {
+        entry.hostname = hostname;
+    }
+
+    Ok(entry)
+}
+
+/// Adds the zone's values that don't exist yet in one changeset.
+fn set_zone(
+    client: &NetcupAPIClient,
+    domain: &str,
+    results: &mut [EntryResult],
+    indices: &[usize],
+    interrupt: &Interrupt,
+) -> anyhow::Result<()> {
+    let zone = client.list_records(domain)?;
+    let mut changeset = client.changeset(domain);
+    let mut pending = Vec::new();
+
+    for &index in indices {
+        let result = &mut results[index];
+        let record = DNSRecord::new(&result.hostname, RecordType::Txt, &result.content);
+        let same = |r: &DNSRecord| {
+            r.hostname() == record.hostname()
+                && r.record_type() == record.record_type()
+                && r.has_destination(record.destination())
+        };
+
+        if let Some(existing) = zone.iter().find(|r| same(r)) {
+            result.status = Status::Exists;
+            result.id = existing.id().map(String::from);
+        } else if pending.iter().any(|(_, queued)| same(queued)) {
+            // Listed twice, the first entry creates it
+            result.status = Status::Exists;
+        } else {
+            changeset = changeset.add(record.clone());
+            pending.push((index, record));
+        }
+    }
+
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    let added = changeset.commit()?.added;
+    for ((index, _), record) in pending.into_iter().zip(added) {
+        results[index].status = Status::Created;
+        results[index].id = record.id().map(String::from);
+        interrupt.track(domain, record);
+    }
+
+    Ok(())
+}
+
+/// Deletes every record with one of the zone's values in one changeset.
+fn unset_zone(
+    client: &NetcupAPIClient,
+    domain: &str,
+    results: &mut [EntryResult],
+    indices: &[usize],
+) -> anyhow::Result<()> {
+    let zone = client.list_records(domain)?;
+    let mut changeset = client.changeset(domain);
+    let mut deleting = Vec::new();
+    let mut pending = Vec::new();
+
+    for &index in indices {
+        let result = &mut results[index];
+        let found: Vec<&DNSRecord> = zone
+            .iter()
+            .filter(|r| {
+                r.hostname() == result.hostname
+                    && r.record_type() == RecordType::Txt.as_str()
+                    && r.has_destination(&result.content)
+            })
+            .collect();
+
+        if found.is_empty() {
+            result.status = Status::Absent;
+            continue;
+        }
+
+        let ids: Vec<_> = found
+            .iter()
+            .filter_map(|r| r.id().map(String::from))
+            .collect();
+        result.id = Some(ids.join(","));
+        for record in found {
+            if !deleting.iter().any(|r: &DNSRecord| r.id() == record.id()) {
+                changeset = changeset.delete(record.clone());
+                deleting.push(record.clone());
+            }
+        }
+        pending.push(index);
+    }
+
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    changeset.commit()?;
+    for index in pending {
+        results[index].status = Status::Deleted;
+    }
+
+    Ok(())
+}
+
+fn print_result(result: &EntryResult) {
+    let name = if result.hostname == "@" {
+        result.domain.clone()
+    } else {
+        format!("{}.{}", result.hostname, result.domain)
+    };
+    let status = match result.status {
+        Status::Created => "created",
+        Status::Exists => "already exists",
+        Status::Deleted => "deleted",
+        Status::Absent => "already absent",
+        Status::Failed => "FAILED",
+    };
+
+    match (&result.error, &result.id) {
+        (Some(error), _) if result.domain.is_empty() => {
+            println!("line {}: {}: {}", result.line, status, error)
+        }
+        (Some(error), _) => println!("{} TXT {}: {}: {}", name, result.content, status, error),
+        (None, Some(id)) => println!("{} TXT {}: {} ({})", name, result.content, status, id),
+        (None, None) => println!("{} TXT {}: {}", name, result.content, status),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use serde_json::json;
+    use truenas_acme_auth::testing::{self, txt, MockTransport};
+
+    use super::*;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        args: BatchArgs,
+    }
+
+    const INPUT: &str = "\
+example.net _acme-challenge a
+# comment
+
+{\"domain\": \"Example.COM\", \"hostname\": \"_acme-challenge.example.com\", \"content\": \"b\"}
+example.net _acme-challenge.www c
+not enough
+example.com _acme-challenge d
+";
+
+    #[test]
+    fn entries_are_grouped_by_zone_in_input_order() {
+        let (results, zones) = parse_input(INPUT);
+
+        let lines: Vec<_> = results.iter().map(|result| result.line).collect();
+        assert_eq!(lines, [1, 4, 5, 6, 7]);
+        assert_eq!(
+            zones.into_iter().collect::<Vec<_>>(),
+            [
+                ("example.com".to_string(), vec![1, 4]),
+                ("example.net".to_string(), vec![0, 2]),
+            ]
+        );
+        assert_eq!(results[1].hostname, "_acme-challenge");
+        assert_eq!(results[2].hostname, "_acme-challenge.www");
+        assert_eq!(
+            results[3].error.as_deref(),
+            Some("Expected <domain> <hostname> <content>")
+        );
+    }
+
+    #[test]
+    fn each_zone_is_updated_with_one_call() {
+        let path = testing::temp_dir("batch").join("entries.txt");
+        fs::write(&path, INPUT).unwrap();
+        let mock = MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        for (zone, added) in [
+            ("example.com", [txt(1, "b"), txt(2, "d")]),
+            ("example.net", [txt(3, "a"), txt(4, "c")]),
+        ] {
+            let mut added = added;
+            if zone == "example.net" {
+                added[1]["hostname"] = "_acme-challenge.www".into();
+            }
+            mock.respond_success("infoDnsRecords", json!({ "dnsrecords": [] }))
+                .respond_success("infoDnsRecords", json!({ "dnsrecords": [] }))
+                .respond_success("updateDnsRecords", json!({ "dnsrecords": added }));
+        }
+
+        let args = Cli::parse_from(["set-batch", path.to_str().unwrap()]).args;
+        let err = set_batch(&client, args, true, &Interrupt::install()).unwrap_err();
+        assert_eq!(err.to_string(), "1 of 5 entries failed");
+
+        let updates: Vec<_> = mock
+            .requests()
+            .into_iter()
+            .filter(|request| request["action"] == "updateDnsRecords")
+            .map(|request| {
+                let records = request["param"]["dnsrecordset"]["dnsrecords"].clone();
+                let contents: Vec<_> = records
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|record| record["destination"].as_str().unwrap().to_string())
+                    .collect();
+                (
+                    request["param"]["domainname"].as_str().unwrap().to_string(),
+                    contents,
+                )
+            })
+            .collect();
+        assert_eq!(
+            updates,
+            [
+                (
+                    "example.com".to_string(),
+                    vec!["b".to_string(), "d".to_string()]
+                ),
+                (
+                    "example.net".to_string(),
+                    vec!["a".to_string(), "c".to_string()]
+                ),
+            ]
+        );
+    }
+}
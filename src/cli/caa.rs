@@ -0,0 +1,251 @@
+use anyhow::bail;
+use clap::{Args, Subcommand};
+use serde_json::json;
+use truenas_acme_auth::{CaaRecord, CaaTag, DNSRecord, DeletionPolicy, NetcupAPIClient};
+
+use super::interrupt::Interrupt;
+
+#[derive(Debug, Subcommand)]
+pub enum CaaCommands {
+    /// Add a CAA record, e.g. `caa add example.de --tag issue --value letsencrypt.org`
+    Add(CaaArgs),
+    /// Remove the CAA record with this tag and value
+    Remove(CaaArgs),
+    /// Print the zone's CAA records
+    List {
+        domain: String,
+        /// Only the records of this hostname ("@" for the apex)
+        #[arg(long)]
+        hostname: Option<String>,
+    },
+}
+
+impl CaaCommands {
+    pub fn domain(&self) -> &str {
+        match self {
+            CaaCommands::Add(args) | CaaCommands::Remove(args) => &args.domain,
+            CaaCommands::List { domain, .. } => domain,
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct CaaArgs {
+    domain: String,
+    /// Hostname relative to the domain ("@" for the apex)
+    #[arg(long, default_value = "@")]
+    hostname: String,
+    /// Property tag (issue, issuewild, iodef)
+    #[arg(long)]
+    tag: CaaTag,
+    /// The issuer domain, or the mailto:/https:// URL for iodef, without quotes
+    #[arg(long)]
+    value: String,
+    /// Set the issuer-critical flag
+    #[arg(long)]
+    critical: bool,
+}
+
+impl CaaArgs {
+    fn caa(&self) -> anyhow::Result<CaaRecord> {
+        let caa = CaaRecord::new(self.tag, &self.value)?;
+        Ok(if self.critical { caa.critical() } else { caa })
+    }
+}
+
+/// Implements `caa`.
+pub fn caa(
+    client: &NetcupAPIClient,
+    command: CaaCommands,
+    policy: DeletionPolicy,
+    json: bool,
+    interrupt: &Interrupt,
+) -> anyhow::Result<()> {
+    match command {
+        CaaCommands::Add(args) => {
+            let caa = args.caa()?;
+            let existing = caa_records(client, &args.domain, Some(&args.hostname))?;
+            if let Some((record, _)) = existing.iter().find(|(_, other)| *other == caa) {
+                bail!(
+                    "{} already has the CAA record {} (id {})",
+                    args.hostname,
+                    caa,
+                    record.id().unwrap_or("-")
+                );
+            }
+
+            let record = client.add_record(&args.domain, caa.to_record(&args.hostname))?;
+            if json {
+                println!("{}", json!({ "domain": args.domain, "records": [&record] }));
+            } else if !client.is_dry_run() {
+                println!("Added {}", record);
+            }
+            interrupt.track(&args.domain, record);
+        }
+        CaaCommands::Remove(args) => {
+            let caa = args.caa()?;
+            let matching: Vec<DNSRecord> = caa_records(client, &args.domain, Some(&args.hostname))?
+                .into_iter()
+                .filter(|(_, other)| *other == caa)
+                .map(|(record, _)| record)
+                .collect();
+            if matching.is_empty() {
+                bail!("{} has no CAA record {}", args.hostname, caa);
+            }
+
+            let outcome = client.delete_records(&args.domain, matching, policy)?;
+            if json {
+                println!(
+                    "{}",
+                    json!({
+                        "domain": args.domain,
+                        "deleted": outcome.deleted,
+                        "skipped": outcome.skipped,
+                    })
+                );
+            } else {
+                for record in &outcome.deleted {
+                    println!("Deleted {}", record);
+                }
+                for record in &outcome.skipped {
+                    println!("Skipped critical record {} (use --allow-critical)", record);
+                }
+            }
+        }
+        CaaCommands::List { domain, hostname } => {
+            let mut records = caa_records(client, &domain, hostname.as_deref())?;
+            records.sort_by(|(a, _), (b, _)| a.hostname().cmp(b.hostname()));
+
+            if json {
+                let records: Vec<_> = records
+                    .iter()
+                    .map(|(record, caa)| {
+                        json!({
+                            "id": record.id(),
+                            "hostname": record.hostname(),
+                            "flags": caa.flags(),
+                            "tag": caa.tag().as_str(),
+                            "value": caa.value(),
+                        })
+                    })
+                    .collect();
+                println!("{}", json!({ "domain": domain, "records": records }));
+                return Ok(());
+            }
+
+            if records.is_empty() {
+                eprintln!("No CAA records in {}", domain);
+            }
+            let width = records
+                .iter()
+                .map(|(record, _)| record.hostname().len())
+                .max()
+                .unwrap_or(0);
+            for (record, caa) in &records {
+                println!(
+                    "{:width$}  {:>3}  {:9}  {}  (id {})",
+                    record.hostname(),
+                    caa.flags(),
+                    caa.tag().as_str(),
+                    caa.value(),
+                    record.id().unwrap_or("-"),
+                    width = width
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The zone's CAA records, parsed. Records netcup holds in a form this tool
+/// doesn't understand are skipped with a warning.
+fn caa_records(
+    client: &NetcupAPIClient,
+    domain: &str,
+    hostname: Option<&str>,
+) -> anyhow::Result<Vec<(DNSRecord, CaaRecord)>> {
+    Ok(client
+        .list_records(domain)?
+        .into_iter()
+        .filter(|r| r.record_type().eq_ignore_ascii_case("CAA"))
+        .filter(|r| hostname.is_none_or(|h| r.hostname() == h))
+        .filter_map(|record| match CaaRecord::try_from(&record) {
+            Ok(caa) => Some((record, caa)),
+            Err(err) => {
+                eprintln!("Warning: skipping {}: {:#}", record, err);
+                None
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use serde_json::{json, Value};
+    use truenas_acme_auth::testing::{self, MockTransport};
+
+    use super::*;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(subcommand)]
+        command: CaaCommands,
+    }
+
+    fn command(argv: &[&str]) -> CaaCommands {
+        Cli::parse_from([&["caa"], argv].concat()).command
+    }
+
+    fn caa_record(id: u32, destination: &str) -> Value {
+        json!({
+            "id": id.to_string(),
+            "hostname": "@",
+            "type": "CAA",
+            "priority": "0",
+            "destination": destination,
+            "deleterecord": false,
+            "state": "yes",
+        })
+    }
+
+    #[test]
+    fn an_identical_entry_is_not_added_again() {
+        let mock = MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_success(
+            "infoDnsRecords",
+            json!({ "dnsrecords": [caa_record(4, "0 issue \"letsencrypt.org\"")] }),
+        );
+
+        let err = caa(
+            &client,
+            command(&[
+                "add",
+                "example.de",
+                "--tag",
+                "issue",
+                "--value",
+                "letsencrypt.org",
+            ]),
+            DeletionPolicy::default(),
+            false,
+            &Interrupt::install(),
+        )
+        .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "@ already has the CAA record 0 issue \"letsencrypt.org\" (id 4)"
+        );
+        assert_eq!(mock.actions(), ["login", "infoDnsRecords"]);
+    }
+
+    #[test]
+    fn an_unsupported_tag_is_refused_by_the_parser() {
+        let result =
+            Cli::try_parse_from(["caa", "add", "example.de", "--tag", "tbs", "--value", "x"]);
+        assert!(result.is_err());
+    }
+}
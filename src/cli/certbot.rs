@@ -0,0 +1,505 @@
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::Path,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use clap::{Args, Subcommand};
+use serde::{Deserialize, Serialize};
+use truenas_acme_auth::{NetcupAPIClient, NetcupError};
+
+use super::{
+    interrupt::Interrupt,
+    progress::Progress,
+    wait::{wait_for_txt_record, warn_unverified, WaitArgs},
+};
+
+/// Orders whose last challenge never came are dropped after this long.
+const PENDING_ORDER_EXPIRY: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Subcommand)]
+pub enum CertbotCommands {
+    /// Create the challenge record, for --manual-auth-hook
+    Auth(CertbotAuthArgs),
+    /// Delete the challenge record, for --manual-cleanup-hook
+    Cleanup(CertbotCleanupArgs),
+}
+
+impl CertbotCommands {
+    pub fn domain(&self) -> Option<&str> {
+        match self {
+            CertbotCommands::Auth(args) => args.challenge.domain.as_deref(),
+            CertbotCommands::Cleanup(args) => args.challenge.domain.as_deref(),
+        }
+    }
+}
+
+/// The challenge certbot passes to its hooks.
+#[derive(Debug, Args)]
+pub struct ChallengeArgs {
+    /// The domain being validated
+    #[arg(long, env = "CERTBOT_DOMAIN")]
+    domain: Option<String>,
+    /// The value of the TXT record
+    #[arg(long, env = "CERTBOT_VALIDATION")]
+    validation: Option<String>,
+}
+
+impl ChallengeArgs {
+    /// The record name and value, `_acme-challenge.<domain>` also for a
+    /// wildcard.
+    fn challenge(&self) -> anyhow::Result<(String, &str)> {
+        let domain = self
+            .domain
+            .as_deref()
+            .filter(|domain| !domain.is_empty())
+            .context("CERTBOT_DOMAIN is not set")?;
+        let validation = self
+            .validation
+            .as_deref()
+            .filter(|validation| !validation.is_empty())
+            .context("CERTBOT_VALIDATION is not set")?;
+
+        let fqdn = format!("_acme-challenge.{}", domain.trim_start_matches("*."));
+        Ok((fqdn, validation))
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct CertbotAuthArgs {
+    #[command(flatten)]
+    challenge: ChallengeArgs,
+    /// Wait until the authoritative nameservers serve the record. Within an
+    /// order the wait happens once, at the last challenge, for all of them.
+    #[arg(long)]
+    wait: bool,
+    #[command(flatten)]
+    wait_args: WaitArgs,
+    /// How many challenges of the order come after this one
+    #[arg(long, env = "CERTBOT_REMAINING_CHALLENGES")]
+    remaining_challenges: Option<usize>,
+    /// All domains of the order, identifying it between the hook calls
+    #[arg(long, env = "CERTBOT_ALL_DOMAINS")]
+    all_domains: Option<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct CertbotCleanupArgs {
+    #[command(flatten)]
+    challenge: ChallengeArgs,
+    /// What `certbot auth` printed, the id of the record
+    #[arg(long, env = "CERTBOT_AUTH_OUTPUT")]
+    auth_output: Option<String>,
+}
+
+/// A challenge whose wait was deferred to the last one of its order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PendingChallenge {
+    zone: String,
+    hostname: String,
+    value: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PendingOrder {
+    /// Unix seconds
+    updated: u64,
+    challenges: Vec<PendingChallenge>,
+}
+
+/// Implements `certbot auth`: creates the record and prints its id, which
+/// certbot hands to the cleanup hook. With `--wait`, challenges before the
+/// last of an order are remembered in `state_file` and the last one waits
+/// for all of them.
+pub fn auth(
+    client: &NetcupAPIClient,
+    args: CertbotAuthArgs,
+    state_file: &Path,
+    progress: &Arc<Progress>,
+    interrupt: &Interrupt,
+) -> anyhow::Result<()> {
+    let (fqdn, value) = args.challenge.challenge()?;
+    let (zone, hostname) = client.detect_zone(&fqdn)?;
+
+    // certbot retries a failed auth hook with the same value
+    let outcome = client.add_txt_record(&zone, &hostname, value)?;
+    println!("{}", outcome.record().id().unwrap_or_default());
+    if outcome.is_created() {
+        interrupt.track(&zone, outcome.into_record());
+    }
+
+    if !args.wait {
+        return Ok(());
+    }
+
+    let challenge = PendingChallenge {
+        zone,
+        hostname,
+        value: value.into(),
+    };
+
+    let remaining = args.remaining_challenges.unwrap_or(0);
+    let Some(challenges) = challenges_to_wait_for(
+        state_file,
+        args.all_domains.as_deref(),
+        remaining,
+        challenge,
+    )?
+    else {
+        eprintln!(
+            "{} more challenges in this order, waiting at the last one",
+            remaining
+        );
+        return Ok(());
+    };
+
+    let options = args.wait_args.options(client, progress, interrupt);
+    for challenge in &challenges {
+        let waited = wait_for_txt_record(
+            client,
+            &challenge.zone,
+            &challenge.hostname,
+            &challenge.value,
+            &options,
+            &args.wait_args.reassert,
+            progress,
+        );
+        progress.finish();
+        let (report, _) = waited?;
+        eprintln!(
+            "{}.{} TXT {} is served after {:.1?}",
+            challenge.hostname, challenge.zone, challenge.value, report.elapsed
+        );
+        warn_unverified(&report, true);
+    }
+
+    Ok(())
+}
+
+/// Implements `certbot cleanup`: deletes the record by the id from the auth
+/// hook, else by name and value. A record that is already gone is only a
+/// warning, as certbot also cleans up after failed auth hooks.
+pub fn cleanup(client: &NetcupAPIClient, args: CertbotCleanupArgs) -> anyhow::Result<()> {
+    let (fqdn, value) = args.challenge.challenge()?;
+    let (zone, hostname) = client.detect_zone(&fqdn)?;
+
+    let id = args
+        .auth_output
+        .as_deref()
+        .and_then(|output| output.lines().last())
+        .map(str::trim)
+        .filter(|id| !id.is_empty());
+
+    if let Some(id) = id {
+        match client.delete_record(id, &zone, &hostname, value) {
+            // The output of another auth hook, look the record up instead
+            Err(err)
+                if matches!(
+                    err.downcast_ref::<NetcupError>(),
+                    Some(NetcupError::RecordNotFound { .. })
+                ) => {}
+            result => return result,
+        }
+    }
+
+    // Only this value, the other challenge of a wildcard order may share the
+    // name; stale duplicates of the value go with it
+    match client.delete_txt_records(&zone, &hostname, &[value]) {
+        Ok(_) => Ok(()),
+        Err(err) => match err.downcast_ref::<NetcupError>() {
+            Some(NetcupError::TxtRecordNotFound { .. }) => {
+                eprintln!("{} TXT {} does not exist, nothing to delete", fqdn, value);
+                Ok(())
+            }
+            _ => Err(err),
+        },
+    }
+}
+
+/// The challenges to wait for now: `None` while `remaining` challenges of
+/// the order (named by `CERTBOT_ALL_DOMAINS`) are still to come, with
+/// `challenge` remembered in `state_file`, and at the last one every
+/// challenge of the order. Without an order only `challenge`.
+fn challenges_to_wait_for(
+    state_file: &Path,
+    order: Option<&str>,
+    remaining: usize,
+    challenge: PendingChallenge,
+) -> anyhow::Result<Option<Vec<PendingChallenge>>> {
+    match order {
+        Some(order) if remaining > 0 => {
+            update_pending(state_file, |orders| {
+                let pending = orders.entry(order.into()).or_default();
+                pending.updated = unix_secs(SystemTime::now());
+                pending.challenges.push(challenge);
+            })?;
+            Ok(None)
+        }
+        Some(order) => {
+            let mut challenges = Vec::new();
+            update_pending(state_file, |orders| {
+                if let Some(pending) = orders.remove(order) {
+                    challenges = pending.challenges;
+                }
+            })?;
+            challenges.push(challenge);
+            Ok(Some(challenges))
+        }
+        None => Ok(Some(vec![challenge])),
+    }
+}
+
+/// Reads the pending orders under the file's lock, drops expired ones,
+/// applies `f` and writes the result back, so concurrent certbot runs
+/// don't lose each other's challenges.
+fn update_pending(
+    path: &Path,
+    f: impl FnOnce(&mut HashMap<String, PendingOrder>),
+) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .context(format!("Failed to open {}", path.display()))?;
+    file.lock()
+        .context(format!("Failed to lock {}", path.display()))?;
+
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+    let mut orders: HashMap<String, PendingOrder> =
+        serde_json::from_str(&contents).unwrap_or_default();
+
+    let expired = unix_secs(SystemTime::now()).saturating_sub(PENDING_ORDER_EXPIRY.as_secs());
+    orders.retain(|_, order| order.updated > expired);
+
+    f(&mut orders);
+
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(serde_json::to_string(&orders)?.as_bytes())?;
+    file.unlock()?;
+
+    Ok(())
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use clap::Parser;
+    use serde_json::json;
+    use truenas_acme_auth::testing::{self, txt, MockTransport};
+
+    use super::*;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(subcommand)]
+        command: CertbotCommands,
+    }
+
+    fn args(domain: Option<&str>, validation: Option<&str>) -> ChallengeArgs {
+        ChallengeArgs {
+            domain: domain.map(Into::into),
+            validation: validation.map(Into::into),
+        }
+    }
+
+    fn zone() -> serde_json::Value {
+        json!({
+            "name": "example.com",
+            "ttl": "86400",
+            "serial": "2026101401",
+            "refresh": "28800",
+            "retry": "7200",
+            "expire": "1209600",
+            "dnssecstatus": "false",
+        })
+    }
+
+    fn challenge(value: &str) -> PendingChallenge {
+        PendingChallenge {
+            zone: "example.com".into(),
+            hostname: "_acme-challenge".into(),
+            value: value.into(),
+        }
+    }
+
+    #[test]
+    fn an_order_waits_once_at_its_last_challenge() {
+        let state_file = testing::temp_dir("certbot_order").join("pending.json");
+        let order = Some("example.com,*.example.com,www.example.com");
+
+        let waits: Vec<_> = [(2, "a"), (1, "b"), (0, "c")]
+            .into_iter()
+            .map(|(remaining, value)| {
+                challenges_to_wait_for(&state_file, order, remaining, challenge(value)).unwrap()
+            })
+            .collect();
+
+        assert!(waits[0].is_none());
+        assert!(waits[1].is_none());
+        let values: Vec<_> = waits[2]
+            .iter()
+            .flatten()
+            .map(|challenge| challenge.value.as_str())
+            .collect();
+        assert_eq!(values, ["a", "b", "c"]);
+
+        // The order is gone, a retry of the last hook waits only for itself
+        let retry = challenges_to_wait_for(&state_file, order, 0, challenge("c")).unwrap();
+        assert_eq!(retry.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn without_an_order_each_challenge_waits() {
+        let state_file = testing::temp_dir("certbot_no_order").join("pending.json");
+
+        let wait = challenges_to_wait_for(&state_file, None, 2, challenge("a")).unwrap();
+
+        assert_eq!(wait.unwrap().len(), 1);
+        assert!(!state_file.exists());
+    }
+
+    #[test]
+    fn concurrent_hooks_keep_all_challenges() {
+        let state_file = testing::temp_dir("certbot_concurrent").join("pending.json");
+
+        thread::scope(|scope| {
+            for i in 0..8 {
+                let state_file = &state_file;
+                scope.spawn(move || {
+                    challenges_to_wait_for(state_file, Some("order"), 1, challenge(&i.to_string()))
+                        .unwrap()
+                });
+            }
+        });
+
+        let last = challenges_to_wait_for(&state_file, Some("order"), 0, challenge("last"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(last.len(), 9);
+    }
+
+    #[test]
+    fn challenge_is_read_from_the_certbot_variables() {
+        let challenge = args(Some("example.com"), Some("token"));
+        assert_eq!(
+            challenge.challenge().unwrap(),
+            ("_acme-challenge.example.com".into(), "token")
+        );
+
+        // A wildcard is validated at the name of its base domain
+        let challenge = args(Some("*.example.com"), Some("token"));
+        assert_eq!(
+            challenge.challenge().unwrap().0,
+            "_acme-challenge.example.com"
+        );
+
+        for (domain, validation, message) in [
+            (None, Some("token"), "CERTBOT_DOMAIN is not set"),
+            (Some(""), Some("token"), "CERTBOT_DOMAIN is not set"),
+            (Some("example.com"), None, "CERTBOT_VALIDATION is not set"),
+            (
+                Some("example.com"),
+                Some(""),
+                "CERTBOT_VALIDATION is not set",
+            ),
+        ] {
+            let err = args(domain, validation).challenge().unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
+    }
+
+    #[test]
+    fn cleanup_deletes_the_record_auth_created() {
+        let mock = MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_success("infoDnsZone", zone())
+            .respond_success("infoDnsRecords", json!({ "dnsrecords": [] }))
+            .respond_success("infoDnsRecords", json!({ "dnsrecords": [] }))
+            .respond_success(
+                "updateDnsRecords",
+                json!({ "dnsrecords": [txt(7, "token")] }),
+            );
+
+        let Cli {
+            command: CertbotCommands::Auth(auth_args),
+        } = Cli::parse_from([
+            "certbot",
+            "auth",
+            "--domain",
+            "example.com",
+            "--validation",
+            "token",
+        ])
+        else {
+            unreachable!()
+        };
+        let state_file = testing::temp_dir("certbot_auth").join("pending.json");
+        let progress = Arc::new(Progress::new(true, false));
+        auth(
+            &client,
+            auth_args,
+            &state_file,
+            &progress,
+            &Interrupt::install(),
+        )
+        .unwrap();
+
+        mock.respond_success("infoDnsRecords", json!({ "dnsrecords": [txt(7, "token")] }))
+            .respond_success("updateDnsRecords", json!({ "dnsrecords": [] }));
+        let cleanup_args = CertbotCleanupArgs {
+            challenge: args(Some("example.com"), Some("token")),
+            auth_output: Some("7\n".into()),
+        };
+        cleanup(&client, cleanup_args).unwrap();
+
+        let update = mock.requests().last().unwrap().clone();
+        assert_eq!(update["action"], "updateDnsRecords");
+        let sent = &update["param"]["dnsrecordset"]["dnsrecords"];
+        assert_eq!(sent.as_array().unwrap().len(), 1);
+        assert_eq!(sent[0]["id"], "7");
+        assert_eq!(sent[0]["deleterecord"], true);
+        // The zone was detected once, for both hooks
+        let detections = mock
+            .actions()
+            .iter()
+            .filter(|a| *a == "infoDnsZone")
+            .count();
+        assert_eq!(detections, 1);
+    }
+
+    #[test]
+    fn cleanup_of_a_record_already_gone_succeeds() {
+        let mock = MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_success("infoDnsZone", zone())
+            .respond_success("infoDnsRecords", json!({ "dnsrecords": [txt(8, "other")] }))
+            .respond_success("infoDnsRecords", json!({ "dnsrecords": [txt(8, "other")] }));
+
+        let cleanup_args = CertbotCleanupArgs {
+            challenge: args(Some("example.com"), Some("token")),
+            auth_output: Some("7".into()),
+        };
+        cleanup(&client, cleanup_args).unwrap();
+
+        assert!(!mock.actions().iter().any(|a| a == "updateDnsRecords"));
+    }
+}
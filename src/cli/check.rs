@@ -0,0 +1,536 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::Context;
+use clap::{Args, ValueEnum};
+use serde_json::json;
+use truenas_acme_auth::{select_challenge_records, AuditLog, NetcupAPIClient, NetcupError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CheckOutput {
+    Text,
+    /// A Nagios/Icinga plugin status line with performance data
+    Nagios,
+}
+
+#[derive(Debug, Args)]
+pub struct HealthcheckArgs {
+    /// Also read these zones, each with the credentials selected for it
+    domains: Vec<String>,
+    #[arg(long, value_enum, default_value_t = CheckOutput::Text)]
+    output: CheckOutput,
+    /// Warn if logging in takes longer
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "2s")]
+    warn_latency: Duration,
+    /// Critical if logging in takes longer
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "10s")]
+    crit_latency: Duration,
+}
+
+#[derive(Debug, Args)]
+pub struct VerifyArgs {
+    /// Zones that should be manageable, each checked with the credentials
+    /// selected for it
+    domains: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct MonitorArgs {
+    /// Zones to check for leftover _acme-challenge records
+    #[arg(required = true)]
+    pub domains: Vec<String>,
+    #[arg(long, value_enum, default_value_t = CheckOutput::Text)]
+    output: CheckOutput,
+    /// Warn about leftovers older than this
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "1h")]
+    warn_age: Duration,
+    /// Critical for leftovers older than this
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "1d")]
+    crit_age: Duration,
+}
+
+/// Plugin states, ordered from best to worst for aggregating.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum State {
+    Ok,
+    Warning,
+    Unknown,
+    Critical,
+}
+
+impl State {
+    fn exit_code(self) -> i32 {
+        match self {
+            State::Ok => 0,
+            State::Warning => 1,
+            State::Critical => 2,
+            State::Unknown => 3,
+        }
+    }
+
+    fn from_thresholds(value: Duration, warn: Duration, crit: Duration) -> Self {
+        if value > crit {
+            State::Critical
+        } else if value > warn {
+            State::Warning
+        } else {
+            State::Ok
+        }
+    }
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            State::Ok => "OK",
+            State::Warning => "WARNING",
+            State::Critical => "CRITICAL",
+            State::Unknown => "UNKNOWN",
+        })
+    }
+}
+
+/// The result of one item of a check, e.g. one zone.
+struct Item {
+    name: String,
+    state: State,
+    detail: String,
+}
+
+/// Aggregates the items to the worst state, prints the result and returns
+/// the exit code.
+fn report(output: CheckOutput, ok_summary: String, items: &[Item], perfdata: &[String]) -> i32 {
+    let state = items
+        .iter()
+        .map(|item| item.state)
+        .max()
+        .unwrap_or(State::Ok);
+
+    let summary = if state == State::Ok {
+        ok_summary
+    } else {
+        items
+            .iter()
+            .filter(|item| item.state == state)
+            .map(|item| format!("{}: {}", item.name, item.detail))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    match output {
+        CheckOutput::Nagios => {
+            if perfdata.is_empty() {
+                println!("NETCUP {} - {}", state, summary);
+            } else {
+                println!("NETCUP {} - {} | {}", state, summary, perfdata.join(" "));
+            }
+            for item in items {
+                println!("[{}] {}: {}", item.state, item.name, item.detail);
+            }
+        }
+        CheckOutput::Text => {
+            for item in items {
+                println!(
+                    "{:8} {}: {}",
+                    item.state.to_string(),
+                    item.name,
+                    item.detail
+                );
+            }
+            println!("{} - {}", state, summary);
+        }
+    }
+
+    state.exit_code()
+}
+
+/// Implements `healthcheck`: logs in with every set of credentials involved
+/// and reads the given zones. `login` logs in with the credentials for a
+/// domain, or the default ones for `None`.
+pub fn healthcheck(
+    args: &HealthcheckArgs,
+    login: impl Fn(Option<&str>) -> anyhow::Result<NetcupAPIClient>,
+) -> i32 {
+    let targets: Vec<Option<&str>> = if args.domains.is_empty() {
+        vec![None]
+    } else {
+        args.domains
+            .iter()
+            .map(|domain| Some(domain.as_str()))
+            .collect()
+    };
+
+    let mut items = Vec::new();
+    let mut perfdata = Vec::new();
+    let mut latencies = Vec::new();
+
+    for domain in targets {
+        let name = domain.unwrap_or("netcup").to_string();
+        let prefix = domain.map_or(String::new(), |domain| format!("{}_", domain));
+
+        let start = Instant::now();
+        let client = match login(domain) {
+            Ok(client) => client,
+            Err(err) => {
+                items.push(Item {
+                    name,
+                    state: failure_state(&err),
+                    detail: format!("login failed: {:#}", err),
+                });
+                continue;
+            }
+        };
+        let login_latency = start.elapsed();
+        latencies.push(login_latency);
+        perfdata.push(format!(
+            "{}login_ms={};{};{}",
+            prefix,
+            login_latency.as_millis(),
+            args.warn_latency.as_millis(),
+            args.crit_latency.as_millis()
+        ));
+
+        let mut state = State::from_thresholds(login_latency, args.warn_latency, args.crit_latency);
+        let mut detail = format!("login {}ms", login_latency.as_millis());
+
+        if let Some(domain) = domain {
+            let start = Instant::now();
+            match client.list_records(domain) {
+                Ok(_) => {
+                    let zone_latency = start.elapsed();
+                    perfdata.push(format!("{}zone_ms={}", prefix, zone_latency.as_millis()));
+                    detail.push_str(&format!(", zone {}ms", zone_latency.as_millis()));
+                }
+                Err(err) => {
+                    state = state.max(failure_state(&err));
+                    detail.push_str(&format!(", reading the zone failed: {:#}", err));
+                }
+            }
+        }
+
+        items.push(Item {
+            name,
+            state,
+            detail,
+        });
+    }
+
+    let slowest = latencies.iter().max().copied().unwrap_or_default();
+    let ok_summary = if items.len() == 1 {
+        items[0].detail.clone()
+    } else {
+        format!(
+            "{} zones healthy, slowest login {}ms",
+            items.len(),
+            slowest.as_millis()
+        )
+    };
+
+    report(args.output, ok_summary, &items, &perfdata)
+}
+
+/// Implements `verify`: logs in and reads each zone, printing OK or FAIL per
+/// check with netcup's message. Returns 0 only if every check passed.
+pub fn verify(
+    args: &VerifyArgs,
+    login: impl Fn(Option<&str>) -> anyhow::Result<NetcupAPIClient>,
+    json: bool,
+) -> i32 {
+    let checks = checks(args, login);
+    print!("{}", summary(&checks, json));
+
+    if checks.iter().all(|(_, outcome)| outcome.is_ok()) {
+        0
+    } else {
+        1
+    }
+}
+
+/// The outcome of each check `verify` makes, by zone, or of logging in if
+/// it wasn't given any.
+fn checks(
+    args: &VerifyArgs,
+    login: impl Fn(Option<&str>) -> anyhow::Result<NetcupAPIClient>,
+) -> Vec<(String, anyhow::Result<String>)> {
+    let mut checks = Vec::new();
+
+    if args.domains.is_empty() {
+        checks.push(match login(None) {
+            Ok(_) => ("login".to_string(), Ok("logged in".to_string())),
+            Err(err) => ("login".to_string(), Err(err)),
+        });
+    }
+    for domain in &args.domains {
+        let outcome = login(Some(domain))
+            .context("Login failed")
+            .and_then(|client| client.list_records(domain))
+            .map(|records| format!("logged in, {} records", records.len()));
+        checks.push((domain.clone(), outcome));
+    }
+
+    checks
+}
+
+/// What `verify` prints: a line per check and a count, or one JSON object.
+fn summary(checks: &[(String, anyhow::Result<String>)], json: bool) -> String {
+    let failed = checks
+        .iter()
+        .filter(|(_, outcome)| outcome.is_err())
+        .count();
+
+    if json {
+        let checks: Vec<_> = checks
+            .iter()
+            .map(|(name, outcome)| match outcome {
+                Ok(detail) => json!({ "name": name, "ok": true, "detail": detail }),
+                Err(err) => json!({ "name": name, "ok": false, "detail": format!("{:#}", err) }),
+            })
+            .collect();
+        format!("{}\n", json!({ "ok": failed == 0, "checks": checks }))
+    } else {
+        let mut out = String::new();
+        for (name, outcome) in checks {
+            match outcome {
+                Ok(detail) => out.push_str(&format!("OK    {}: {}\n", name, detail)),
+                Err(err) => out.push_str(&format!("FAIL  {}: {:#}\n", name, err)),
+            }
+        }
+        out.push_str(&format!(
+            "{} of {} checks passed\n",
+            checks.len() - failed,
+            checks.len()
+        ));
+        out
+    }
+}
+
+/// Rejected credentials or zones make the check critical, anything that
+/// kept it from finding out (connection, rate limit) unknown.
+fn failure_state(err: &anyhow::Error) -> State {
+    match err
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<NetcupError>())
+    {
+        Some(
+            NetcupError::AuthenticationFailed { .. }
+            | NetcupError::DomainNotFound { .. }
+            | NetcupError::DomainNotAllowed { .. },
+        ) => State::Critical,
+        _ => State::Unknown,
+    }
+}
+
+/// Implements `monitor`: finds leftover challenge records and rates them by
+/// age. Netcup doesn't say when a record was created, so the age comes from
+/// the audit log; leftovers it doesn't know about are warnings.
+pub fn monitor(client: &NetcupAPIClient, audit_log: Option<&AuditLog>, args: MonitorArgs) -> i32 {
+    let created = match audit_log.map(creation_times).transpose() {
+        Ok(created) => created.unwrap_or_default(),
+        Err(err) => {
+            let item = Item {
+                name: "audit log".into(),
+                state: State::Unknown,
+                detail: format!("{:#}", err),
+            };
+            return report(args.output, String::new(), &[item], &[]);
+        }
+    };
+    let now = SystemTime::now();
+
+    let mut items = Vec::new();
+    let mut perfdata = Vec::new();
+    let mut total = 0;
+
+    for domain in &args.domains {
+        let records = match client.list_records(domain) {
+            Ok(records) => select_challenge_records(records, None),
+            Err(err) => {
+                items.push(Item {
+                    name: domain.clone(),
+                    state: failure_state(&err),
+                    detail: format!("reading the zone failed: {:#}", err),
+                });
+                continue;
+            }
+        };
+
+        let ages: Vec<Option<Duration>> = records
+            .iter()
+            .map(|record| {
+                let created = record
+                    .id()
+                    .and_then(|id| created.get(&(domain.clone(), id.into())));
+                created.map(|time| now.duration_since(*time).unwrap_or_default())
+            })
+            .collect();
+        let oldest = ages.iter().flatten().max().copied();
+        let unknown = ages.iter().filter(|age| age.is_none()).count();
+
+        let mut state = oldest.map_or(State::Ok, |oldest| {
+            State::from_thresholds(oldest, args.warn_age, args.crit_age)
+        });
+        if unknown > 0 {
+            state = state.max(State::Warning);
+        }
+
+        let mut detail = format!("{} leftover challenges", records.len());
+        if let Some(oldest) = oldest {
+            detail.push_str(&format!(
+                ", oldest {}",
+                humantime::format_duration(Duration::from_secs(oldest.as_secs()))
+            ));
+        }
+        if unknown > 0 {
+            detail.push_str(&format!(", {} of unknown age", unknown));
+        }
+
+        total += records.len();
+        perfdata.push(format!("{}_leftovers={}", domain, records.len()));
+        perfdata.push(format!(
+            "{}_oldest={}s;{};{}",
+            domain,
+            oldest.unwrap_or_default().as_secs(),
+            args.warn_age.as_secs(),
+            args.crit_age.as_secs()
+        ));
+
+        items.push(Item {
+            name: domain.clone(),
+            state,
+            detail,
+        });
+    }
+
+    let ok_summary = format!(
+        "{} leftover challenges in {} zones",
+        total,
+        args.domains.len()
+    );
+
+    report(args.output, ok_summary, &items, &perfdata)
+}
+
+/// When each record still known by (domain, id) was created, from the
+/// successful changes in the audit log.
+fn creation_times(audit_log: &AuditLog) -> anyhow::Result<HashMap<(String, String), SystemTime>> {
+    let mut created = HashMap::new();
+
+    for entry in audit_log.read()? {
+        if !entry.is_success() {
+            continue;
+        }
+        let Ok(time) = humantime::parse_rfc3339(&entry.timestamp) else {
+            continue;
+        };
+
+        for record in &entry.created {
+            if let Some(id) = record.id() {
+                created.insert((entry.domain.clone(), id.to_string()), time);
+            }
+        }
+    }
+
+    Ok(created)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use truenas_acme_auth::testing::{self, txt, MockTransport};
+
+    use super::*;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        args: VerifyArgs,
+    }
+
+    /// Logs in to a mock of its own per zone: example.com has two records,
+    /// example.net rejects the credentials, example.org isn't in the
+    /// account.
+    fn login(domain: Option<&str>) -> anyhow::Result<NetcupAPIClient> {
+        let mock = MockTransport::new();
+        if domain == Some("example.net") {
+            mock.respond_error("login", 4013, "The login credentials are invalid");
+            return testing::builder().transport(mock).login(
+                "12345".into(),
+                "password".into(),
+                "key".into(),
+            );
+        }
+
+        let client = testing::login(&mock, testing::builder());
+        if domain == Some("example.org") {
+            mock.respond_error("infoDnsRecords", 5029, "Domain not found");
+        } else {
+            mock.respond_success(
+                "infoDnsRecords",
+                json!({ "dnsrecords": [txt(1, "a"), txt(2, "b")] }),
+            );
+        }
+        Ok(client)
+    }
+
+    fn args(domains: &[&str]) -> VerifyArgs {
+        Cli::parse_from(std::iter::once("verify").chain(domains.iter().copied())).args
+    }
+
+    #[test]
+    fn exit_code_is_zero_only_if_every_check_passes() {
+        assert_eq!(verify(&args(&[]), login, false), 0);
+        assert_eq!(verify(&args(&["example.com"]), login, false), 0);
+        assert_eq!(
+            verify(&args(&["example.com", "example.net"]), login, false),
+            1
+        );
+        assert_eq!(verify(&args(&["example.org"]), login, true), 1);
+    }
+
+    #[test]
+    fn summary_reports_each_zone_with_netcups_message() {
+        let checks = checks(&args(&["example.com", "example.net", "example.org"]), login);
+
+        let text = summary(&checks, false);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "OK    example.com: logged in, 2 records");
+        assert!(
+            lines[1].starts_with("FAIL  example.net: Login failed: ")
+                && lines[1].contains("The login credentials are invalid"),
+            "{}",
+            lines[1]
+        );
+        assert!(
+            lines[2].starts_with("FAIL  example.org: ") && lines[2].contains("Domain not found"),
+            "{}",
+            lines[2]
+        );
+        assert_eq!(lines[3], "1 of 3 checks passed");
+        assert_eq!(lines.len(), 4);
+
+        let json: serde_json::Value = serde_json::from_str(&summary(&checks, true)).unwrap();
+        assert_eq!(json["ok"], false);
+        let ok: Vec<_> = json["checks"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|check| {
+                (
+                    check["name"].as_str().unwrap(),
+                    check["ok"].as_bool().unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            ok,
+            [
+                ("example.com", true),
+                ("example.net", false),
+                ("example.org", false)
+            ]
+        );
+    }
+}
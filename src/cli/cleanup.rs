@@ -0,0 +1,187 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::{bail, Context};
+use clap::Args;
+use serde_json::json;
+use truenas_acme_auth::{DNSRecord, NetcupAPIClient, NetcupError};
+
+use super::for_each_bounded;
+
+#[derive(Debug, Args)]
+pub struct CleanupArgs {
+    /// Zone to clean up
+    #[arg(required_unless_present_any = ["all_domains", "domains_file"])]
+    pub domain: Option<String>,
+    /// Clean up every domain of the account (uses listallDomains)
+    #[arg(long, conflicts_with_all = ["domain", "domains_file"])]
+    all_domains: bool,
+    /// Clean up the domains listed in a file, one per line
+    #[arg(long, conflicts_with = "domain")]
+    domains_file: Option<PathBuf>,
+    /// Only remove the challenges of this host (e.g. `www`)
+    #[arg(long)]
+    hostname: Option<String>,
+    /// Not supported, netcup does not expose when a record was created
+    #[arg(long, hide = true)]
+    older_than: Option<String>,
+    /// How many zones to process at the same time
+    #[arg(long, default_value_t = 4)]
+    concurrency: usize,
+}
+
+/// Implements `cleanup`.
+pub fn cleanup(client: &NetcupAPIClient, args: CleanupArgs, json: bool) -> anyhow::Result<()> {
+    if args.older_than.is_some() {
+        bail!("--older-than is not supported: netcup does not expose when a record was created");
+    }
+
+    let dry_run = client.is_dry_run();
+    let verb = if dry_run {
+        "would be removed"
+    } else {
+        "removed"
+    };
+
+    if let Some(domain) = &args.domain {
+        let records = client.cleanup_challenges(domain, args.hostname.as_deref(), dry_run)?;
+
+        if json {
+            println!(
+                "{}",
+                json!({ "domain": domain, "dry_run": dry_run, "records": records })
+            );
+            return Ok(());
+        }
+
+        for record in &records {
+            println!("{}", record);
+        }
+        println!("{} records {}", records.len(), verb);
+        if dry_run && records.len() > client.batch_size() {
+            println!(
+                "in {} calls of up to {} records",
+                records.len().div_ceil(client.batch_size()),
+                client.batch_size()
+            );
+        }
+
+        return Ok(());
+    }
+
+    let domains: Vec<String> = if let Some(path) = &args.domains_file {
+        fs::read_to_string(path)
+            .context(format!("Failed to read {}", path.display()))?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(String::from)
+            .collect()
+    } else {
+        client
+            .list_all_domains()
+            .context("Could not enumerate the account's domains, pass --domains-file instead")?
+    };
+
+    let domains: Vec<String> = domains
+        .into_iter()
+        .filter(|domain| {
+            let allowed = client.is_domain_allowed(domain);
+            if !allowed {
+                eprintln!("Skipping {}, not in allowed_domains", domain);
+            }
+            allowed
+        })
+        .collect();
+
+    let results = for_each_bounded(&domains, args.concurrency, |domain| {
+        client.cleanup_challenges(domain, args.hostname.as_deref(), dry_run)
+    });
+
+    let failed = results.iter().filter(|result| result.is_err()).count();
+
+    if json {
+        let zones: Vec<_> = domains
+            .iter()
+            .zip(&results)
+            .map(|(domain, result)| match result {
+                Ok(records) => json!({ "domain": domain, "records": records }),
+                Err(err) => json!({ "domain": domain, "error": format!("{:#}", err) }),
+            })
+            .collect();
+        println!("{}", json!({ "dry_run": dry_run, "zones": zones }));
+    } else {
+        print_summary(&domains, &results, verb);
+    }
+
+    if failed > 0 {
+        bail!("{} of {} zones failed", failed, domains.len());
+    }
+
+    Ok(())
+}
+
+fn print_summary(domains: &[String], results: &[anyhow::Result<Vec<DNSRecord>>], verb: &str) {
+    let width = domains.iter().map(String::len).max().unwrap_or(0).max(4);
+    let mut failed = 0;
+
+    println!("{:width$}  RESULT", "ZONE", width = width);
+    for (domain, result) in domains.iter().zip(results) {
+        match result {
+            Ok(records) => println!(
+                "{:width$}  {} records {}",
+                domain,
+                records.len(),
+                verb,
+                width = width
+            ),
+            Err(err) => {
+                failed += 1;
+                match err.downcast_ref::<NetcupError>() {
+                    Some(NetcupError::CircuitOpen { .. }) => println!(
+                        "{:width$}  skipped due to open circuit",
+                        domain,
+                        width = width
+                    ),
+                    _ => println!("{:width$}  FAILED: {:#}", domain, err, width = width),
+                }
+            }
+        }
+    }
+
+    let removed: usize = results.iter().flatten().map(Vec::len).sum();
+    println!(
+        "{} records {} across {} zones",
+        removed,
+        verb,
+        domains.len() - failed
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use truenas_acme_auth::testing::{self, MockTransport};
+
+    use super::*;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        args: CleanupArgs,
+    }
+
+    #[test]
+    fn older_than_is_rejected_before_the_zone_is_read() {
+        let mock = MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        let args = Cli::parse_from(["cleanup", "example.de", "--older-than", "7d"]).args;
+
+        let err = cleanup(&client, args, false).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "--older-than is not supported: netcup does not expose when a record was created"
+        );
+        assert_eq!(mock.actions(), ["login"]);
+    }
+}
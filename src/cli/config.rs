@@ -0,0 +1,1196 @@
+use std::{
+    collections::HashMap,
+    env,
+    ffi::OsString,
+    fmt, fs,
+    io::{self, BufRead},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context};
+use clap::Args;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+/// Resolved credentials for one netcup account.
+#[derive(Debug)]
+pub struct NetcupAuth {
+    pub customer_id: String,
+    pub api_password: String,
+    pub api_key: String,
+}
+
+/// Keys that are only ever taken from a single layer together, each set
+/// directly or, for the secrets, as a file, under either name.
+const CREDENTIAL_KEYS: [&[&str]; 3] = [
+    &["CID", "customer_id"],
+    &["API_PW", "API_PW_FILE", "api_password", "api_password_file"],
+    &["API_KEY", "API_KEY_FILE", "api_key", "api_key_file"],
+];
+const SECRET_KEYS: [&str; 4] = ["API_PW", "API_KEY", "api_password", "api_key"];
+
+/// The lowercase names the credential keys are also accepted under, as
+/// (alias, key).
+pub const KEY_ALIASES: [(&str, &str); 5] = [
+    ("customer_id", "CID"),
+    ("api_password", "API_PW"),
+    ("api_password_file", "API_PW_FILE"),
+    ("api_key", "API_KEY"),
+    ("api_key_file", "API_KEY_FILE"),
+];
+
+/// The syntax of a config file, from its extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    Json,
+    Yaml,
+}
+
+impl ConfigFormat {
+    /// `None` for an unknown extension.
+    fn from_path(path: &Path) -> Option<Self> {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase);
+
+        match extension.as_deref() {
+            Some("toml") => Some(ConfigFormat::Toml),
+            Some("json") => Some(ConfigFormat::Json),
+            Some("yaml" | "yml") => Some(ConfigFormat::Yaml),
+            _ => None,
+        }
+    }
+
+    /// Every format is read into the same table, so the checks and the
+    /// merging don't depend on it. YAML's `null` has no TOML equivalent and
+    /// is refused.
+    fn parse(self, contents: &str) -> anyhow::Result<toml::value::Table> {
+        Ok(match self {
+            ConfigFormat::Toml => toml::from_str(contents)?,
+            ConfigFormat::Json => serde_json::from_str(contents)?,
+            ConfigFormat::Yaml => serde_yaml::from_str(contents)?,
+        })
+    }
+}
+
+impl fmt::Display for ConfigFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ConfigFormat::Toml => "TOML",
+            ConfigFormat::Json => "JSON",
+            ConfigFormat::Yaml => "YAML",
+        })
+    }
+}
+
+/// Parses a config file as the format its extension names, or for another
+/// extension as JSON if it starts with `{` and as TOML otherwise. Returns
+/// the format it was read as.
+pub fn parse_layer(
+    path: &Path,
+    contents: &str,
+) -> anyhow::Result<(toml::value::Table, ConfigFormat)> {
+    let format = ConfigFormat::from_path(path).unwrap_or_else(|| {
+        if contents.trim_start().starts_with('{') {
+            ConfigFormat::Json
+        } else {
+            ConfigFormat::Toml
+        }
+    });
+
+    let layer = format.parse(contents).context(format!(
+        "Failed to parse {} as {}",
+        path.display(),
+        format
+    ))?;
+
+    Ok((layer, format))
+}
+
+/// The effective configuration, merged from every file on the search path.
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(rename = "CID", alias = "customer_id")]
+    pub customer_id: Option<String>,
+    #[serde(rename = "API_PW", alias = "api_password")]
+    pub api_password: Option<String>,
+    /// File whose contents (surrounding whitespace trimmed) are the API
+    /// password, instead of API_PW
+    #[serde(rename = "API_PW_FILE", alias = "api_password_file")]
+    pub api_password_file: Option<PathBuf>,
+    #[serde(rename = "API_KEY", alias = "api_key")]
+    pub api_key: Option<String>,
+    /// File whose contents are the API key, instead of API_KEY
+    #[serde(rename = "API_KEY_FILE", alias = "api_key_file")]
+    pub api_key_file: Option<PathBuf>,
+    /// Overrides the netcup API endpoint
+    pub endpoint: Option<String>,
+    /// Proxy for the API requests, instead of HTTPS_PROXY
+    pub proxy: Option<String>,
+    /// HTTP timeout in seconds
+    pub timeout: Option<u64>,
+    /// Named credential sets for additional accounts
+    #[serde(default)]
+    pub profiles: HashMap<String, Profile>,
+    /// Maps domain suffixes to profile names
+    #[serde(default)]
+    pub domains: HashMap<String, String>,
+    /// Profile for domains not listed in `domains`
+    pub default_profile: Option<String>,
+    /// Zones this deployment may touch, exact or as `*.suffix`
+    pub allowed_domains: Option<Vec<String>>,
+    /// Append-only JSON lines log of every change
+    pub audit_log: Option<PathBuf>,
+    /// Only connect over IPv4
+    #[serde(default)]
+    pub ipv4_only: bool,
+    /// Only connect over IPv6
+    #[serde(default)]
+    pub ipv6_only: bool,
+    /// Records per `updateDnsRecords` call
+    pub batch_size: Option<usize>,
+    /// Attempts per API call including the first, 1 disables retries
+    pub retry_attempts: Option<u32>,
+    /// Where sessions are kept between invocations
+    pub session_cache: Option<PathBuf>,
+}
+
+/// The credentials of one account, selected through `[domains]`.
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    #[serde(rename = "CID", alias = "customer_id")]
+    pub customer_id: String,
+    #[serde(rename = "API_PW", alias = "api_password")]
+    pub api_password: Option<String>,
+    #[serde(rename = "API_PW_FILE", alias = "api_password_file")]
+    pub api_password_file: Option<PathBuf>,
+    #[serde(rename = "API_KEY", alias = "api_key")]
+    pub api_key: Option<String>,
+    #[serde(rename = "API_KEY_FILE", alias = "api_key_file")]
+    pub api_key_file: Option<PathBuf>,
+}
+
+impl Config {
+    /// Picks the profile for `domain`: the longest matching suffix in
+    /// `domains`, else `default_profile`. `None` means the top-level
+    /// credentials apply.
+    pub fn select_profile(&self, domain: Option<&str>) -> anyhow::Result<Option<(&str, &Profile)>> {
+        let matched = domain.and_then(|domain| {
+            let domain = domain.trim_end_matches('.').to_lowercase();
+
+            self.domains
+                .iter()
+                .filter(|(suffix, _)| {
+                    let suffix = suffix.trim_matches('.').to_lowercase();
+                    domain == suffix || domain.ends_with(&format!(".{}", suffix))
+                })
+                .max_by_key(|(suffix, _)| suffix.trim_matches('.').len())
+                .map(|(_, profile)| profile)
+        });
+
+        let Some(name) = matched.or(self.default_profile.as_ref()) else {
+            if !self.domains.is_empty() && self.customer_id.is_none() {
+                bail!(
+                    "{} matches no entry in [domains] and neither default_profile nor top-level credentials are set",
+                    domain.unwrap_or("The command")
+                );
+            }
+            return Ok(None);
+        };
+
+        let profile = self
+            .profiles
+            .get(name)
+            .context(format!("Profile {} is not defined in [profiles]", name))?;
+
+        Ok(Some((name, profile)))
+    }
+}
+
+/// A merged configuration and the files it was read from, in the order
+/// they were applied.
+#[derive(Debug, Default)]
+pub struct LoadedConfig {
+    pub config: Config,
+    pub files: Vec<PathBuf>,
+    /// Every location that was looked at, for error messages
+    pub searched: Vec<PathBuf>,
+    /// The merged raw values, used for `config show`
+    merged: toml::value::Table,
+}
+
+impl LoadedConfig {
+    /// Renders the merged configuration with secrets masked.
+    pub fn to_masked_toml(&self) -> anyhow::Result<String> {
+        let mut merged = self.merged.clone();
+        mask_secrets(&mut merged);
+
+        // Serializing through `Value` emits plain values before tables
+        Ok(toml::to_string(&toml::Value::Table(merged))?)
+    }
+
+    /// The loaded files other users can read, which matters as they may hold
+    /// the API password. Always empty off unix.
+    pub fn world_readable_files(&self) -> Vec<&Path> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            self.files
+                .iter()
+                .filter(|path| {
+                    fs::metadata(path).is_ok_and(|meta| meta.permissions().mode() & 0o004 != 0)
+                })
+                .map(PathBuf::as_path)
+                .collect()
+        }
+        #[cfg(not(unix))]
+        {
+            Vec::new()
+        }
+    }
+}
+
+fn mask_secrets(table: &mut toml::value::Table) {
+    for (key, value) in table.iter_mut() {
+        match value {
+            toml::Value::Table(table) => mask_secrets(table),
+            value if SECRET_KEYS.contains(&key.as_str()) => {
+                *value = toml::Value::String("***".into());
+            }
+            // Only the credentials in the proxy URL
+            toml::Value::String(proxy) if key == "proxy" => {
+                if let Some((_, host)) = proxy.rsplit_once('@') {
+                    let scheme = proxy.split_once("://").map_or("", |(scheme, _)| scheme);
+                    *proxy = match scheme {
+                        "" => format!("***@{}", host),
+                        scheme => format!("{}://***@{}", scheme, host),
+                    };
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Config files from least to most specific; later files override earlier ones.
+/// Only `explicit` if given, from `--config`.
+pub fn search_path(explicit: Option<&Path>) -> anyhow::Result<Vec<PathBuf>> {
+    if let Some(path) = explicit {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let mut paths = Vec::new();
+
+    if let Some(dir) = system_config_dir(process_env) {
+        paths.push(dir.join("truenas-netcup-acme").join("config.toml"));
+    }
+
+    // Next to the executable, where the config used to live exclusively. A
+    // symlink in e.g. /usr/local/bin points to the installation directory.
+    let exe = env::current_exe()?;
+    let mut exe_adjacent = fs::canonicalize(&exe).unwrap_or(exe);
+    exe_adjacent.set_file_name("config.toml");
+    paths.push(exe_adjacent);
+
+    if let Some(dir) = user_config_dir(process_env) {
+        paths.push(dir.join("truenas-netcup-acme").join("config.toml"));
+    }
+
+    if let Some(path) = env::var_os("NETCUP_ACME_CONFIG") {
+        paths.push(PathBuf::from(path));
+    }
+
+    Ok(paths)
+}
+
+/// Location of the request budget shared between invocations.
+pub fn rate_limit_state_file() -> PathBuf {
+    state_file("ratelimit.json", process_env)
+}
+
+/// Location of the challenges `certbot auth` defers to the last of an order.
+pub fn certbot_state_file() -> PathBuf {
+    state_file("certbot.json", process_env)
+}
+
+/// Location of the lock that invocations changing zones take turns on.
+pub fn lock_file() -> PathBuf {
+    state_file("invocation.lock", process_env)
+}
+
+/// Location of the original TTLs of the zones `set --lower-ttl` lowered.
+pub fn ttl_state_file() -> PathBuf {
+    state_file("ttl.json", process_env)
+}
+
+/// Location of the sessions kept between invocations.
+pub fn session_cache_file() -> PathBuf {
+    state_file("session.json", process_env)
+}
+
+/// The lookup of the directories below in this process's environment.
+fn process_env(name: &str) -> Option<OsString> {
+    env::var_os(name)
+}
+
+/// `name` in the user's state directory, or the temp dir without one.
+fn state_file(name: &str, var: impl Fn(&str) -> Option<OsString>) -> PathBuf {
+    user_state_dir(var)
+        .unwrap_or_else(env::temp_dir)
+        .join("truenas-netcup-acme")
+        .join(name)
+}
+
+#[cfg(not(windows))]
+fn system_config_dir(_var: impl Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+    Some(PathBuf::from("/etc"))
+}
+
+#[cfg(windows)]
+fn system_config_dir(var: impl Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+    var("ProgramData").map(PathBuf::from)
+}
+
+#[cfg(not(windows))]
+fn user_config_dir(var: impl Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+    var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| var("HOME").map(|home| PathBuf::from(home).join(".config")))
+}
+
+#[cfg(windows)]
+fn user_config_dir(var: impl Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+    var("APPDATA").map(PathBuf::from)
+}
+
+#[cfg(not(windows))]
+fn user_state_dir(var: impl Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+    var("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| var("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+}
+
+#[cfg(windows)]
+fn user_state_dir(var: impl Fn(&str) -> Option<OsString>) -> Option<PathBuf> {
+    var("LOCALAPPDATA").map(PathBuf::from)
+}
+
+/// Reads and deep-merges every existing file on the search path. An
+/// explicit file has to exist.
+pub fn load_config(explicit: Option<&Path>) -> anyhow::Result<LoadedConfig> {
+    if let Some(path) = explicit {
+        if !path.is_file() {
+            bail!("Config file {} not found", path.display());
+        }
+    }
+
+    load_files(search_path(explicit)?)
+}
+
+/// Reads and deep-merges the files of `searched` that exist, later ones on
+/// top.
+fn load_files(searched: Vec<PathBuf>) -> anyhow::Result<LoadedConfig> {
+    let mut loaded = LoadedConfig {
+        searched,
+        ..Default::default()
+    };
+
+    for path in loaded.searched.clone() {
+        if !path.is_file() {
+            continue;
+        }
+
+        let contents =
+            fs::read_to_string(&path).context(format!("Failed to load {}", path.display()))?;
+        let (layer, format) = parse_layer(&path, &contents)?;
+        // Checked on its own so the error names the file with a typo
+        toml::Value::Table(layer.clone())
+            .try_into::<Config>()
+            .context(format!(
+                "Invalid configuration in {} (read as {})",
+                path.display(),
+                format
+            ))?;
+
+        merge_layer(&mut loaded.merged, layer)
+            .context(format!("Invalid configuration in {}", path.display()))?;
+        loaded.files.push(path);
+    }
+
+    loaded.config = toml::Value::Table(loaded.merged.clone()).try_into()?;
+
+    Ok(loaded)
+}
+
+/// Implements `config show`.
+pub fn show(loaded: &LoadedConfig, resolved: bool) -> anyhow::Result<()> {
+    if loaded.files.is_empty() {
+        println!("No config files found");
+        return Ok(());
+    }
+
+    if resolved {
+        for path in &loaded.files {
+            println!("# {}", path.display());
+        }
+        print!("{}", loaded.to_masked_toml()?);
+        return Ok(());
+    }
+
+    for path in &loaded.files {
+        let contents = fs::read_to_string(path)?;
+        let (mut table, _) = parse_layer(path, &contents)?;
+        mask_secrets(&mut table);
+
+        println!("# {}", path.display());
+        println!("{}", toml::to_string(&toml::Value::Table(table))?);
+    }
+
+    Ok(())
+}
+
+/// Merges `layer` on top of `base`: tables are merged key-wise, everything
+/// else is replaced. The credentials are replaced as a whole so key and
+/// password always come from the same file.
+pub fn merge_layer(base: &mut toml::value::Table, layer: toml::value::Table) -> anyhow::Result<()> {
+    let has = |keys: &[&str]| keys.iter().any(|key| layer.contains_key(*key));
+
+    if CREDENTIAL_KEYS.iter().any(|keys| has(keys)) {
+        let missing: Vec<_> = CREDENTIAL_KEYS
+            .iter()
+            .filter(|keys| !has(keys))
+            // Only the original names, the aliases are the same keys
+            .map(|keys| {
+                keys.iter()
+                    .filter(|key| !key.chars().any(|c| c.is_ascii_lowercase()))
+                    .copied()
+                    .collect::<Vec<_>>()
+                    .join(" or ")
+            })
+            .collect();
+
+        if !missing.is_empty() {
+            bail!("Incomplete credentials, missing {}", missing.join(", "));
+        }
+
+        for key in CREDENTIAL_KEYS.concat() {
+            base.remove(key);
+        }
+    }
+
+    // A profile is a set of credentials as well
+    if let (Some(toml::Value::Table(base)), Some(toml::Value::Table(layer))) =
+        (base.get_mut("profiles"), layer.get("profiles"))
+    {
+        for name in layer.keys() {
+            base.remove(name);
+        }
+    }
+
+    merge_tables(base, layer);
+
+    Ok(())
+}
+
+fn merge_tables(base: &mut toml::value::Table, layer: toml::value::Table) {
+    for (key, value) in layer {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(layer)) => {
+                merge_tables(base, layer)
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Credentials passed on the command line, each overriding the same one
+/// from the environment and the config.
+#[derive(Debug, Default, Args)]
+pub struct CredentialArgs {
+    #[arg(long, global = true, visible_alias = "customer-id")]
+    customer_number: Option<String>,
+    #[arg(long, global = true)]
+    api_key: Option<String>,
+    /// Visible in the process list, prefer --api-password-file or --password-stdin
+    #[arg(long, global = true, conflicts_with_all = ["api_password_file", "password_stdin"])]
+    api_password: Option<String>,
+    /// Read the API password from a file (surrounding whitespace is trimmed)
+    #[arg(long, global = true, conflicts_with = "password_stdin")]
+    api_password_file: Option<PathBuf>,
+    /// Read the API password from the first line of stdin
+    #[arg(long, global = true)]
+    password_stdin: bool,
+}
+
+impl CredentialArgs {
+    /// Reads the password file or stdin if one of them was given.
+    fn read(&self) -> anyhow::Result<PartialCredentials> {
+        let api_password = if let Some(password) = &self.api_password {
+            eprintln!("Warning: --api-password is visible in the process list, consider --api-password-file or --password-stdin");
+            Some(password.clone())
+        } else if let Some(path) = &self.api_password_file {
+            Some(
+                fs::read_to_string(path)
+                    .context(format!("Failed to read {}", path.display()))?
+                    .trim()
+                    .to_string(),
+            )
+        } else if self.password_stdin {
+            let mut line = String::new();
+            io::stdin()
+                .lock()
+                .read_line(&mut line)
+                .context("Failed to read the API password from stdin")?;
+            Some(line.trim_end_matches(['\r', '\n']).to_string())
+        } else {
+            None
+        };
+
+        Ok(PartialCredentials {
+            customer_id: self.customer_number.clone(),
+            api_password,
+            api_key: self.api_key.clone(),
+        })
+    }
+}
+
+/// Credentials from the command line or the environment, each of them
+/// optional.
+#[derive(Debug, Default)]
+struct PartialCredentials {
+    customer_id: Option<String>,
+    api_password: Option<String>,
+    api_key: Option<String>,
+}
+
+impl PartialCredentials {
+    fn from_env() -> Self {
+        // Empty counts as unset, as with `NETCUP_API_PASSWORD=` in a unit file
+        let var = |name| {
+            env::var(name)
+                .ok()
+                .filter(|value: &String| !value.is_empty())
+        };
+
+        Self {
+            customer_id: var("NETCUP_CUSTOMER_ID"),
+            api_password: var("NETCUP_API_PASSWORD"),
+            api_key: var("NETCUP_API_KEY"),
+        }
+    }
+
+    /// Each credential from `self`, or else from `other`.
+    fn or(self, other: Self) -> Self {
+        Self {
+            customer_id: self.customer_id.or(other.customer_id),
+            api_password: self.api_password.or(other.api_password),
+            api_key: self.api_key.or(other.api_key),
+        }
+    }
+}
+
+/// Determines the credentials to use for `domain`.
+///
+/// Each of the three is taken from the command line if given, else from
+/// `NETCUP_CUSTOMER_ID`, `NETCUP_API_KEY` and `NETCUP_API_PASSWORD` if set,
+/// else from the profile selected for the domain, else from the top-level
+/// credentials of the merged config files. So e.g. only the password can
+/// come from the environment. Given all three on the command line or in
+/// the environment, no config file is needed.
+///
+/// This replaces the earlier rule that a partial set of credentials on the
+/// command line or in the environment is an error. Within one config file
+/// it still is, see [`merge_layer`].
+pub fn resolve_auth(
+    cli: &CredentialArgs,
+    loaded: &LoadedConfig,
+    domain: Option<&str>,
+) -> anyhow::Result<NetcupAuth> {
+    resolve(cli.read()?, PartialCredentials::from_env(), loaded, domain)
+}
+
+/// [`resolve_auth`] with the command line's and the environment's
+/// credentials already read.
+fn resolve(
+    cli: PartialCredentials,
+    env: PartialCredentials,
+    loaded: &LoadedConfig,
+    domain: Option<&str>,
+) -> anyhow::Result<NetcupAuth> {
+    let given = cli.or(env);
+    if let PartialCredentials {
+        customer_id: Some(customer_id),
+        api_password: Some(api_password),
+        api_key: Some(api_key),
+    } = given
+    {
+        return Ok(NetcupAuth {
+            customer_id,
+            api_password,
+            api_key,
+        });
+    }
+
+    let config = &loaded.config;
+
+    let (customer_id, api_password, api_key) = match config.select_profile(domain)? {
+        Some((name, profile)) => {
+            eprintln!(
+                "Using profile {} for {}",
+                name,
+                domain.unwrap_or("this command")
+            );
+            (
+                Some(&profile.customer_id),
+                (&profile.api_password, &profile.api_password_file),
+                (&profile.api_key, &profile.api_key_file),
+            )
+        }
+        None => (
+            config.customer_id.as_ref(),
+            (&config.api_password, &config.api_password_file),
+            (&config.api_key, &config.api_key_file),
+        ),
+    };
+
+    let customer_id = given.customer_id.or_else(|| customer_id.cloned());
+    // The files are only read if the command line or the environment
+    // doesn't override them
+    let api_password = match given.api_password {
+        Some(api_password) => Some(api_password),
+        None => secret("API_PW", api_password)?,
+    };
+    let api_key = match given.api_key {
+        Some(api_key) => Some(api_key),
+        None => secret("API_KEY", api_key)?,
+    };
+
+    match (customer_id, api_password, api_key) {
+        (Some(customer_id), Some(api_password), Some(api_key)) => Ok(NetcupAuth {
+            customer_id,
+            api_password,
+            api_key,
+        }),
+        (customer_id, api_password, api_key) => {
+            let missing: Vec<_> = [
+                (
+                    customer_id.is_none(),
+                    "CID (NETCUP_CUSTOMER_ID, --customer-number)",
+                ),
+                (
+                    api_key.is_none(),
+                    "API_KEY or API_KEY_FILE (NETCUP_API_KEY, --api-key)",
+                ),
+                (
+                    api_password.is_none(),
+                    "API_PW or API_PW_FILE (NETCUP_API_PASSWORD, --api-password-file, --password-stdin)",
+                ),
+            ]
+            .into_iter()
+            .filter(|(missing, _)| *missing)
+            .map(|(_, name)| name)
+            .collect();
+
+            let tried: Vec<_> = loaded
+                .searched
+                .iter()
+                .map(|path| format!("  {}", path.display()))
+                .collect();
+
+            bail!(
+                "Missing {}; tried the command line, the environment and these config files:\n{}",
+                missing.join("; "),
+                tried.join("\n")
+            )
+        }
+    }
+}
+
+/// A secret of the config, given as `key` or as `key_FILE`, a file whose
+/// trimmed contents are the value. Errors name the file, never what it
+/// contains.
+fn secret(
+    key: &str,
+    (value, file): (&Option<String>, &Option<PathBuf>),
+) -> anyhow::Result<Option<String>> {
+    match (value, file) {
+        (Some(_), Some(_)) => bail!("Set either {} or {}_FILE, not both", key, key),
+        (Some(value), None) => Ok(Some(value.clone())),
+        (None, Some(path)) => {
+            let contents = fs::read_to_string(path).context(format!(
+                "Failed to read {}_FILE {}",
+                key,
+                path.display()
+            ))?;
+            let value = contents.trim();
+            if value.is_empty() {
+                bail!("{}_FILE {} is empty", key, path.display());
+            }
+            Ok(Some(value.to_string()))
+        }
+        (None, None) => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use truenas_acme_auth::testing;
+
+    use super::*;
+
+    fn temp_file(dir: &str, name: &str, contents: &str) -> PathBuf {
+        let path = testing::temp_dir(dir).join(name);
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn parse(name: &str, contents: &str) -> Config {
+        let (layer, _) = parse_layer(Path::new(name), contents).unwrap();
+        toml::Value::Table(layer).try_into().unwrap()
+    }
+
+    #[test]
+    fn each_format_reads_the_same_config() {
+        let toml = parse(
+            "config.toml",
+            "customer_id = \"12345\"\napi_password = \"pw\"\napi_key = \"key\"\nbatch_size = 20\n",
+        );
+        let json = parse(
+            "config.json",
+            r#"{"customer_id": "12345", "api_password": "pw", "api_key": "key", "batch_size": 20}"#,
+        );
+        let yaml = parse(
+            "config.yaml",
+            "customer_id: \"12345\"\napi_password: pw\napi_key: key\nbatch_size: 20\n",
+        );
+
+        for config in [toml, json, yaml] {
+            assert_eq!(config.customer_id.as_deref(), Some("12345"));
+            assert_eq!(config.api_password.as_deref(), Some("pw"));
+            assert_eq!(config.api_key.as_deref(), Some("key"));
+            assert_eq!(config.batch_size, Some(20));
+        }
+    }
+
+    #[test]
+    fn format_is_guessed_without_a_known_extension() {
+        let (_, format) = parse_layer(Path::new("config"), "{\"CID\": \"1\"}").unwrap();
+        assert_eq!(format, ConfigFormat::Json);
+        let (_, format) = parse_layer(Path::new("config"), "CID = \"1\"").unwrap();
+        assert_eq!(format, ConfigFormat::Toml);
+        let (_, format) = parse_layer(Path::new("config.YML"), "CID: \"1\"").unwrap();
+        assert_eq!(format, ConfigFormat::Yaml);
+    }
+
+    #[test]
+    fn legacy_keys_are_aliases() {
+        let legacy = parse(
+            "config.json",
+            r#"{"CID": "12345", "API_PW": "pw", "API_KEY_FILE": "/run/secrets/key"}"#,
+        );
+
+        assert_eq!(legacy.customer_id.as_deref(), Some("12345"));
+        assert_eq!(legacy.api_password.as_deref(), Some("pw"));
+        assert_eq!(
+            legacy.api_key_file.as_deref(),
+            Some(Path::new("/run/secrets/key"))
+        );
+
+        let profile = parse(
+            "config.yaml",
+            "profiles:\n  work:\n    CID: \"1\"\n    api_password: pw\n    API_KEY: key\n",
+        );
+        assert_eq!(profile.profiles["work"].customer_id, "1");
+        assert_eq!(profile.profiles["work"].api_key.as_deref(), Some("key"));
+    }
+
+    #[test]
+    fn unknown_key_names_the_file_and_format() {
+        let path = temp_file(
+            "unknown_key",
+            "typo.yaml",
+            "customer_id: \"12345\"\napi_pasword: pw\napi_key: key\n",
+        );
+
+        let err = load_config(Some(&path)).unwrap_err();
+
+        let message = format!("{:#}", err);
+        assert!(message.contains(&path.display().to_string()), "{}", message);
+        assert!(message.contains("read as YAML"), "{}", message);
+        assert!(message.contains("api_pasword"), "{}", message);
+    }
+
+    #[test]
+    fn existing_files_on_the_search_path_are_merged_in_order() {
+        let dir = testing::temp_dir("discovery");
+        let system = dir.join("etc").join("config.toml");
+        let user = dir.join("home").join("config.yaml");
+        let env = dir.join("env.json");
+        fs::create_dir_all(system.parent().unwrap()).unwrap();
+        fs::write(
+            &system,
+            "customer_id = \"1\"\napi_password = \"system\"\napi_key = \"system\"\nbatch_size = 10\n",
+        )
+        .unwrap();
+        fs::write(&env, r#"{"batch_size": 30}"#).unwrap();
+
+        let loaded = load_files(vec![system.clone(), user.clone(), env.clone()]).unwrap();
+
+        assert_eq!(loaded.searched, [system.clone(), user, env.clone()]);
+        assert_eq!(loaded.files, [system, env]);
+        assert_eq!(loaded.config.api_password.as_deref(), Some("system"));
+        assert_eq!(loaded.config.batch_size, Some(30));
+    }
+
+    #[test]
+    fn nothing_found_leaves_the_defaults() {
+        let dir = testing::temp_dir("discovery_empty");
+
+        let loaded = load_files(vec![dir.join("config.toml")]).unwrap();
+
+        assert!(loaded.files.is_empty());
+        assert_eq!(loaded.config.batch_size, None);
+    }
+
+    #[test]
+    fn explicit_file_is_the_whole_search_path_and_has_to_exist() {
+        let path = temp_file("explicit", "mine.toml", "batch_size = 5\n");
+
+        assert_eq!(search_path(Some(&path)).unwrap(), vec![path.clone()]);
+        let loaded = load_config(Some(&path)).unwrap();
+        assert_eq!(loaded.files, vec![path.clone()]);
+
+        let missing = path.with_file_name("missing.toml");
+        let err = load_config(Some(&missing)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("Config file {} not found", missing.display())
+        );
+    }
+
+    #[test]
+    fn search_path_includes_the_file_next_to_the_real_executable() {
+        let mut exe_adjacent = fs::canonicalize(env::current_exe().unwrap()).unwrap();
+        exe_adjacent.set_file_name("config.toml");
+
+        let paths = search_path(None).unwrap();
+
+        assert!(paths.contains(&exe_adjacent), "{:?}", paths);
+        #[cfg(not(windows))]
+        assert_eq!(paths[0], Path::new("/etc/truenas-netcup-acme/config.toml"));
+    }
+
+    /// An environment holding only `vars`.
+    fn env<'a>(vars: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<OsString> + 'a {
+        move |name| {
+            vars.iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| OsString::from(value))
+        }
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn unix_paths_follow_the_xdg_directories() {
+        let xdg = env(&[
+            ("XDG_CONFIG_HOME", "/xdg/config"),
+            ("XDG_STATE_HOME", "/xdg/state"),
+            ("HOME", "/home/me"),
+        ]);
+        let home = env(&[("HOME", "/home/me")]);
+
+        assert_eq!(user_config_dir(&xdg), Some(PathBuf::from("/xdg/config")));
+        assert_eq!(
+            user_config_dir(&home),
+            Some(PathBuf::from("/home/me/.config"))
+        );
+        assert_eq!(system_config_dir(&xdg), Some(PathBuf::from("/etc")));
+        assert_eq!(
+            state_file("ttl.json", &xdg),
+            Path::new("/xdg/state/truenas-netcup-acme/ttl.json")
+        );
+        assert_eq!(
+            state_file("certbot.json", &home),
+            Path::new("/home/me/.local/state/truenas-netcup-acme/certbot.json")
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn windows_paths_follow_the_known_folders() {
+        let windows = env(&[
+            ("APPDATA", r"C:\Users\me\AppData\Roaming"),
+            ("LOCALAPPDATA", r"C:\Users\me\AppData\Local"),
+            ("ProgramData", r"C:\ProgramData"),
+            ("XDG_CONFIG_HOME", r"C:\xdg"),
+        ]);
+
+        assert_eq!(
+            user_config_dir(&windows),
+            Some(PathBuf::from(r"C:\Users\me\AppData\Roaming"))
+        );
+        assert_eq!(
+            system_config_dir(&windows),
+            Some(PathBuf::from(r"C:\ProgramData"))
+        );
+        assert_eq!(
+            state_file("ttl.json", &windows),
+            Path::new(r"C:\Users\me\AppData\Local\truenas-netcup-acme\ttl.json")
+        );
+    }
+
+    #[test]
+    fn state_falls_back_to_the_temp_dir() {
+        let empty = env(&[]);
+
+        assert_eq!(user_config_dir(&empty), None);
+        assert_eq!(
+            state_file("certbot.json", &empty),
+            env::temp_dir()
+                .join("truenas-netcup-acme")
+                .join("certbot.json")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn files_other_users_can_read_are_reported() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let private = temp_file("permissions", "private.toml", "batch_size = 1\n");
+        let shared = private.with_file_name("shared.toml");
+        fs::write(&shared, "batch_size = 2\n").unwrap();
+        fs::set_permissions(&private, fs::Permissions::from_mode(0o600)).unwrap();
+        fs::set_permissions(&shared, fs::Permissions::from_mode(0o644)).unwrap();
+
+        let loaded = load_files(vec![private, shared.clone()]).unwrap();
+
+        assert_eq!(loaded.world_readable_files(), [shared.as_path()]);
+    }
+
+    #[cfg(not(unix))]
+    #[test]
+    fn permissions_are_not_checked_off_unix() {
+        let path = temp_file("permissions", "config.toml", "batch_size = 1\n");
+
+        let loaded = load_files(vec![path]).unwrap();
+
+        assert!(loaded.world_readable_files().is_empty());
+    }
+
+    fn credentials(customer_id: &str, api_password: &str, api_key: &str) -> PartialCredentials {
+        let given = |value: &str| Some(value.to_string()).filter(|value| !value.is_empty());
+        PartialCredentials {
+            customer_id: given(customer_id),
+            api_password: given(api_password),
+            api_key: given(api_key),
+        }
+    }
+
+    fn loaded(name: &str, contents: &str) -> LoadedConfig {
+        LoadedConfig {
+            config: parse(name, contents),
+            searched: vec![PathBuf::from("/etc/truenas-netcup-acme/config.toml")],
+            ..Default::default()
+        }
+    }
+
+    fn resolved(
+        cli: PartialCredentials,
+        env: PartialCredentials,
+        file: &LoadedConfig,
+    ) -> [String; 3] {
+        let auth = resolve(cli, env, file, None).unwrap();
+        [auth.customer_id, auth.api_password, auth.api_key]
+    }
+
+    #[test]
+    fn command_line_beats_environment_beats_file() {
+        let file = loaded(
+            "config.toml",
+            "CID = \"file\"\nAPI_PW = \"file\"\nAPI_KEY = \"file\"\n",
+        );
+        let cli = || credentials("cli", "cli", "cli");
+        let env = || credentials("env", "env", "env");
+        let none = || credentials("", "", "");
+
+        assert_eq!(resolved(cli(), env(), &file), ["cli", "cli", "cli"]);
+        assert_eq!(resolved(none(), env(), &file), ["env", "env", "env"]);
+        assert_eq!(resolved(none(), none(), &file), ["file", "file", "file"]);
+    }
+
+    #[test]
+    fn each_key_may_come_from_another_layer() {
+        let file = loaded(
+            "config.toml",
+            "CID = \"file\"\nAPI_PW = \"file\"\nAPI_KEY = \"file\"\n",
+        );
+
+        assert_eq!(
+            resolved(
+                credentials("cli", "", ""),
+                credentials("", "env", ""),
+                &file
+            ),
+            ["cli", "env", "file"]
+        );
+    }
+
+    #[test]
+    fn credentials_of_two_files_are_never_mixed() {
+        let dir = testing::temp_dir("mixed_credentials");
+        let system = dir.join("system.toml");
+        let user = dir.join("user.toml");
+        fs::write(
+            &system,
+            "CID = \"system\"\nAPI_PW = \"system\"\nAPI_KEY = \"system\"\n",
+        )
+        .unwrap();
+        fs::write(&user, "api_key = \"user\"\n").unwrap();
+
+        let err = load_files(vec![system.clone(), user.clone()]).unwrap_err();
+        assert_eq!(
+            format!("{:#}", err),
+            format!(
+                "Invalid configuration in {}: Incomplete credentials, missing CID, API_PW or API_PW_FILE",
+                user.display()
+            )
+        );
+
+        fs::write(
+            &user,
+            "customer_id = \"user\"\napi_password = \"user\"\napi_key = \"user\"\n",
+        )
+        .unwrap();
+        let loaded = load_files(vec![system, user]).unwrap();
+        let auth = resolve(
+            credentials("", "", ""),
+            credentials("", "", ""),
+            &loaded,
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            [auth.customer_id, auth.api_password, auth.api_key],
+            ["user", "user", "user"]
+        );
+    }
+
+    #[test]
+    fn missing_keys_are_named_with_where_they_were_looked_for() {
+        let file = LoadedConfig {
+            searched: vec![PathBuf::from("/etc/truenas-netcup-acme/config.toml")],
+            ..Default::default()
+        };
+
+        let err = resolve(
+            credentials("cli", "", ""),
+            credentials("", "", ""),
+            &file,
+            None,
+        )
+        .unwrap_err();
+
+        let message = err.to_string();
+        assert!(!message.contains("CID"), "{}", message);
+        assert!(
+            message.contains("API_KEY or API_KEY_FILE (NETCUP_API_KEY, --api-key)"),
+            "{}",
+            message
+        );
+        assert!(message.contains("API_PW or API_PW_FILE"), "{}", message);
+        assert!(
+            message.contains("  /etc/truenas-netcup-acme/config.toml"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn secret_files_are_trimmed_and_overridden() {
+        let path = temp_file("secret_file", "password", "from the file\n");
+        let file = loaded(
+            "config.toml",
+            &format!(
+                "CID = \"1\"\nAPI_KEY = \"key\"\nAPI_PW_FILE = {:?}\n",
+                path.display().to_string()
+            ),
+        );
+        let none = || credentials("", "", "");
+
+        assert_eq!(resolved(none(), none(), &file)[1], "from the file");
+        assert_eq!(
+            resolved(none(), credentials("", "env", ""), &file)[1],
+            "env"
+        );
+
+        let cli = CredentialArgs {
+            api_password_file: Some(path),
+            ..Default::default()
+        };
+        assert_eq!(
+            cli.read().unwrap().api_password.as_deref(),
+            Some("from the file")
+        );
+    }
+
+    #[test]
+    fn a_missing_secret_file_is_named_unless_overridden() {
+        let path = testing::temp_dir("secret_missing").join("password");
+        let file = loaded(
+            "config.toml",
+            &format!(
+                "CID = \"1\"\nAPI_KEY = \"key\"\nAPI_PW_FILE = {:?}\n",
+                path.display().to_string()
+            ),
+        );
+
+        let err = resolve(
+            credentials("", "", ""),
+            credentials("", "", ""),
+            &file,
+            None,
+        )
+        .unwrap_err();
+        let message = err.to_string();
+        assert!(
+            message.contains(&format!("Failed to read API_PW_FILE {}", path.display())),
+            "{}",
+            message
+        );
+
+        // Not read at all when the environment has the password
+        resolve(
+            credentials("", "", ""),
+            credentials("", "env", ""),
+            &file,
+            None,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn a_secret_may_not_be_given_twice_or_empty() {
+        let empty = temp_file("secret_empty", "password", "\n");
+        let value = Some("pw".to_string());
+
+        let err = secret("API_PW", (&value, &Some(empty.clone()))).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Set either API_PW or API_PW_FILE, not both"
+        );
+
+        let err = secret("API_PW", (&None, &Some(empty.clone()))).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("API_PW_FILE {} is empty", empty.display())
+        );
+    }
+}
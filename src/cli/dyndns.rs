@@ -0,0 +1,279 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    time::Duration,
+};
+
+use anyhow::{bail, Context};
+use clap::Args;
+use reqwest::blocking::Client;
+use serde_json::json;
+use truenas_acme_auth::{split_record_name, DNSRecord, NetcupAPIClient, RecordType};
+
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Args)]
+pub struct DyndnsArgs {
+    pub domain: String,
+    /// Relative to the domain ("@" for the apex) or the full name
+    hostname: String,
+    /// Update the A record; the default unless -6 is given
+    #[arg(short = '4')]
+    v4: bool,
+    /// Update the AAAA record
+    #[arg(short = '6')]
+    v6: bool,
+    /// Use this address instead of looking it up; may be given once per
+    /// IP version, which then selects the records instead of -4/-6
+    #[arg(long, value_name = "ADDRESS")]
+    ip: Vec<IpAddr>,
+    /// HTTPS endpoint that answers with the caller's address as plain
+    /// text, asked over each IP version in turn
+    #[arg(long, default_value = "https://api64.ipify.org")]
+    ip_url: String,
+    /// Create the record if the hostname has none, instead of failing
+    #[arg(long)]
+    create: bool,
+}
+
+/// Implements `dyndns`: points the hostname's A and/or AAAA record at the
+/// current public address. A record that already has it is left alone,
+/// so running this from cron only submits a change when the address
+/// changed; one that doesn't keeps its id and gets the new address.
+pub fn dyndns(client: &NetcupAPIClient, args: DyndnsArgs, json: bool) -> anyhow::Result<()> {
+    dyndns_with(client, args, json, &public_address)
+}
+
+/// [`dyndns`] looking up the public address with `detect`, given the URL and
+/// the local address to connect from.
+fn dyndns_with(
+    client: &NetcupAPIClient,
+    args: DyndnsArgs,
+    json: bool,
+    detect: &dyn Fn(&str, IpAddr) -> anyhow::Result<IpAddr>,
+) -> anyhow::Result<()> {
+    let hostname = split_record_name(&args.hostname, &args.domain).unwrap_or(args.hostname);
+
+    let addresses = if args.ip.is_empty() {
+        let v4 = args.v4 || !args.v6;
+        let mut addresses = Vec::new();
+        if v4 {
+            addresses.push(detect(&args.ip_url, Ipv4Addr::UNSPECIFIED.into())?);
+        }
+        if args.v6 {
+            addresses.push(detect(&args.ip_url, Ipv6Addr::UNSPECIFIED.into())?);
+        }
+        addresses
+    } else {
+        if args.ip.iter().filter(|ip| ip.is_ipv4()).count() > 1
+            || args.ip.iter().filter(|ip| ip.is_ipv6()).count() > 1
+        {
+            bail!("--ip can only be given once per IP version");
+        }
+        args.ip
+    };
+
+    let mut results = Vec::new();
+    for address in addresses {
+        let record_type = if address.is_ipv4() {
+            RecordType::A
+        } else {
+            RecordType::Aaaa
+        };
+
+        let existing = client.find_records(&args.domain, &hostname, record_type)?;
+        let (outcome, previous) = match existing.as_slice() {
+            [] if !args.create => bail!(
+                "{} has no {} record in {}, pass --create to add one",
+                hostname,
+                record_type,
+                args.domain
+            ),
+            [] => {
+                let record = DNSRecord::new(&*hostname, record_type, address.to_string());
+                client.add_record(&args.domain, record)?;
+                ("created", None)
+            }
+            [record] if record.destination().parse() == Ok(address) => ("unchanged", None),
+            [record] => {
+                let previous = record.destination().to_string();
+                client.update_record(
+                    &args.domain,
+                    record.clone().with_destination(address.to_string()),
+                )?;
+                ("updated", Some(previous))
+            }
+            records => bail!(
+                "Found {} {} records at {}, refusing to pick one",
+                records.len(),
+                record_type,
+                hostname
+            ),
+        };
+
+        if !json {
+            println!(
+                "{} {} {} {}{}",
+                hostname,
+                record_type,
+                address,
+                outcome,
+                previous
+                    .as_ref()
+                    .map_or(String::new(), |p| format!(" (was {})", p))
+            );
+        }
+        results.push(json!({
+            "type": record_type.as_str(),
+            "address": address,
+            "outcome": outcome,
+            "previous": previous,
+        }));
+    }
+
+    if json {
+        println!(
+            "{}",
+            json!({
+                "domain": args.domain,
+                "hostname": hostname,
+                "dry_run": client.is_dry_run(),
+                "records": results,
+            })
+        );
+    }
+    Ok(())
+}
+
+/// Asks `url` for the public address, connecting from `local` so the
+/// answer is of its IP version.
+fn public_address(url: &str, local: IpAddr) -> anyhow::Result<IpAddr> {
+    let version = if local.is_ipv4() { "IPv4" } else { "IPv6" };
+    let text = Client::builder()
+        .local_address(local)
+        .timeout(LOOKUP_TIMEOUT)
+        .build()?
+        .get(url)
+        .send()
+        .and_then(|response| response.error_for_status())
+        .and_then(|response| response.text())
+        .context(format!(
+            "Failed to look up the public {} address at {}",
+            version, url
+        ))?;
+
+    let address: IpAddr = text.trim().parse().context(format!(
+        "{} didn't answer with an address: {}",
+        url,
+        text.trim()
+    ))?;
+    if address.is_ipv4() != local.is_ipv4() {
+        bail!(
+            "{} answered with {}, not an {} address",
+            url,
+            address,
+            version
+        );
+    }
+    Ok(address)
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use serde_json::{json, Value};
+    use truenas_acme_auth::testing::{self, MockTransport};
+
+    use super::*;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        args: DyndnsArgs,
+    }
+
+    fn args(extra: &[&str]) -> DyndnsArgs {
+        let mut argv = vec!["dyndns", "example.com", "home"];
+        argv.extend(extra);
+        Cli::parse_from(argv).args
+    }
+
+    fn detect(_url: &str, local: IpAddr) -> anyhow::Result<IpAddr> {
+        assert!(local.is_ipv4());
+        Ok("192.0.2.2".parse().unwrap())
+    }
+
+    fn a(id: u32, address: &str) -> Value {
+        json!({
+            "id": id.to_string(),
+            "hostname": "home",
+            "type": "A",
+            "priority": "0",
+            "destination": address,
+            "deleterecord": false,
+            "state": "yes",
+        })
+    }
+
+    #[test]
+    fn a_changed_address_updates_the_record() {
+        let mock = MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_success(
+            "infoDnsRecords",
+            json!({ "dnsrecords": [a(1, "192.0.2.1")] }),
+        )
+        .respond_success(
+            "updateDnsRecords",
+            json!({ "dnsrecords": [a(1, "192.0.2.2")] }),
+        );
+
+        dyndns_with(&client, args(&[]), false, &detect).unwrap();
+
+        let update = mock.requests().last().unwrap().clone();
+        assert_eq!(update["action"], "updateDnsRecords");
+        let sent = &update["param"]["dnsrecordset"]["dnsrecords"];
+        assert_eq!(sent[0]["id"], "1");
+        assert_eq!(sent[0]["destination"], "192.0.2.2");
+    }
+
+    #[test]
+    fn an_unchanged_address_submits_nothing() {
+        let mock = MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_success(
+            "infoDnsRecords",
+            json!({ "dnsrecords": [a(1, "192.0.2.2")] }),
+        );
+
+        dyndns_with(&client, args(&[]), false, &detect).unwrap();
+
+        assert_eq!(mock.actions(), ["login", "infoDnsRecords"]);
+    }
+
+    #[test]
+    fn a_missing_record_is_only_created_with_create() {
+        let mock = MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_success("infoDnsRecords", json!({ "dnsrecords": [] }));
+
+        let err = dyndns_with(&client, args(&[]), false, &detect).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "home has no A record in example.com, pass --create to add one"
+        );
+
+        mock.respond_success("infoDnsRecords", json!({ "dnsrecords": [] }))
+            .respond_success("infoDnsRecords", json!({ "dnsrecords": [] }))
+            .respond_success(
+                "updateDnsRecords",
+                json!({ "dnsrecords": [a(3, "192.0.2.2")] }),
+            );
+        dyndns_with(&client, args(&["--create"]), false, &detect).unwrap();
+
+        let update = mock.requests().last().unwrap().clone();
+        assert_eq!(update["action"], "updateDnsRecords");
+        let sent = &update["param"]["dnsrecordset"]["dnsrecords"][0];
+        assert_eq!(sent["destination"], "192.0.2.2");
+        assert!(sent.get("id").is_none());
+    }
+}
@@ -0,0 +1,262 @@
+use std::{fmt::Write as _, fs, path::PathBuf, time::SystemTime};
+
+use anyhow::Context;
+use clap::{Args, ValueEnum};
+use serde::Serialize;
+use truenas_acme_auth::{DNSRecord, DnsZone, NetcupAPIClient};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    /// A zone file as BIND and most other nameservers read it
+    Bind,
+    /// The records as the API returns them, ids included
+    Json,
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    pub domain: String,
+    #[arg(long, value_enum, default_value_t = ExportFormat::Bind)]
+    format: ExportFormat,
+    /// Write to this file instead of stdout
+    #[arg(long, value_name = "PATH")]
+    out: Option<PathBuf>,
+}
+
+/// What `export --format json` writes. The records deserialize back into
+/// [`DNSRecord`]s unchanged.
+#[derive(Serialize)]
+struct Export<'a> {
+    domain: &'a str,
+    zone: Option<&'a DnsZone>,
+    records: &'a [DNSRecord],
+}
+
+/// Implements `export`: the zone's records, and its settings unless netcup
+/// refuses them, as a zone file or JSON.
+pub fn export(client: &NetcupAPIClient, args: ExportArgs) -> anyhow::Result<()> {
+    let domain = args.domain.trim_end_matches('.').to_ascii_lowercase();
+    let records = client.list_records(&domain)?;
+    let zone = match client.get_zone(&domain) {
+        Ok(zone) => Some(zone),
+        Err(err) => {
+            eprintln!("Warning: exporting without the zone settings: {:#}", err);
+            None
+        }
+    };
+
+    let output = match args.format {
+        ExportFormat::Json => json(&domain, zone.as_ref(), &records)?,
+        ExportFormat::Bind => zone_file(&domain, zone.as_ref(), &records, SystemTime::now()),
+    };
+
+    match &args.out {
+        Some(path) => {
+            fs::write(path, output).context(format!("Failed to write {}", path.display()))?;
+            eprintln!("Exported {} records to {}", records.len(), path.display());
+        }
+        None => print!("{}", output),
+    }
+
+    Ok(())
+}
+
+/// The [`Export`] document, pretty-printed.
+fn json(domain: &str, zone: Option<&DnsZone>, records: &[DNSRecord]) -> anyhow::Result<String> {
+    let export = Export {
+        domain,
+        zone,
+        records,
+    };
+    Ok(format!("{}\n", serde_json::to_string_pretty(&export)?))
+}
+
+/// Renders the records as an RFC 1035 zone file, relative to `$ORIGIN`.
+fn zone_file(
+    domain: &str,
+    zone: Option<&DnsZone>,
+    records: &[DNSRecord],
+    exported_at: SystemTime,
+) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "; {} exported from netcup at {}",
+        domain,
+        humantime::format_rfc3339_seconds(exported_at)
+    );
+    let _ = writeln!(out, "$ORIGIN {}.", domain);
+
+    if let Some(zone) = zone {
+        let _ = writeln!(out, "$TTL {}", zone.ttl);
+        // Netcup doesn't expose the primary nameserver and the contact
+        let _ = writeln!(
+            out,
+            "@ IN SOA root-dns.netcup.net. hostmaster.{}. ( {} {} {} {} {} )",
+            domain, zone.serial, zone.refresh, zone.retry, zone.expire, zone.ttl
+        );
+    }
+
+    let width = records
+        .iter()
+        .map(|record| record.hostname().len())
+        .max()
+        .unwrap_or(1);
+
+    for record in records {
+        let _ = write!(
+            out,
+            "{:width$} IN {} {}",
+            record.hostname(),
+            record.record_type(),
+            rdata(record),
+            width = width
+        );
+        match record.id() {
+            Some(id) => {
+                let _ = writeln!(out, " ; id {}", id);
+            }
+            None => out.push('\n'),
+        }
+    }
+
+    out
+}
+
+/// The record data in zone file syntax: priorities in front, names
+/// absolute and TXT values quoted.
+fn rdata(record: &DNSRecord) -> String {
+    let destination = record.destination();
+    let priority = record.priority().map(str::trim).unwrap_or("0");
+
+    match record.record_type() {
+        "TXT" => quoted(destination),
+        "MX" => format!("{} {}", priority, absolute(destination)),
+        "SRV" => {
+            // Netcup packs `weight port target` into the destination
+            let mut fields: Vec<&str> = destination.split_whitespace().collect();
+            let target = fields.pop().map(absolute).unwrap_or_else(|| ".".into());
+            format!("{} {} {}", priority, fields.join(" "), target)
+        }
+        "CNAME" | "NS" | "PTR" => absolute(destination),
+        _ => destination.into(),
+    }
+}
+
+/// A name with the trailing dot that makes it absolute, `@` as it is.
+fn absolute(name: &str) -> String {
+    match name {
+        "@" | "." => name.into(),
+        name if name.ends_with('.') => name.into(),
+        name => format!("{}.", name),
+    }
+}
+
+/// A TXT value as quoted strings of at most 255 bytes each, with quotes,
+/// backslashes and non-printable bytes escaped.
+fn quoted(value: &str) -> String {
+    let value = value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value);
+
+    let chunks: Vec<String> = value
+        .as_bytes()
+        .chunks(255)
+        .map(|chunk| {
+            let mut quoted = String::from("\"");
+            for &byte in chunk {
+                match byte {
+                    b'"' | b'\\' => {
+                        quoted.push('\\');
+                        quoted.push(byte as char);
+                    }
+                    0x20..=0x7e => quoted.push(byte as char),
+                    _ => {
+                        let _ = write!(quoted, "\\{:03}", byte);
+                    }
+                }
+            }
+            quoted.push('"');
+            quoted
+        })
+        .collect();
+
+    if chunks.is_empty() {
+        "\"\"".into()
+    } else {
+        chunks.join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use serde::Deserialize;
+
+    use super::*;
+
+    /// The synthetic zone the golden files are rendered from.
+    #[derive(Deserialize)]
+    struct Fixture {
+        zone: DnsZone,
+        dnsrecords: Vec<DNSRecord>,
+    }
+
+    fn fixture() -> Fixture {
+        serde_json::from_str(include_str!("../../tests/fixtures/export/zone.json")).unwrap()
+    }
+
+    #[test]
+    fn zone_file_matches_the_golden_file() {
+        let fixture = fixture();
+        let exported_at = UNIX_EPOCH + Duration::from_secs(1_791_936_000);
+
+        let output = zone_file(
+            "example.com",
+            Some(&fixture.zone),
+            &fixture.dnsrecords,
+            exported_at,
+        );
+        assert_eq!(
+            output,
+            include_str!("../../tests/fixtures/export/zone.bind"),
+            "zone file changed:\n{}",
+            output
+        );
+    }
+
+    #[test]
+    fn json_matches_the_golden_file_and_round_trips() {
+        let fixture = fixture();
+
+        let output = json("example.com", Some(&fixture.zone), &fixture.dnsrecords).unwrap();
+        assert_eq!(
+            output,
+            include_str!("../../tests/fixtures/export/export.json"),
+            "JSON export changed:\n{}",
+            output
+        );
+
+        let export: serde_json::Value = serde_json::from_str(&output).unwrap();
+        let records: Vec<DNSRecord> = serde_json::from_value(export["records"].clone()).unwrap();
+        assert_eq!(
+            serde_json::to_value(&records).unwrap(),
+            serde_json::to_value(&fixture.dnsrecords).unwrap()
+        );
+        assert_eq!(records[5].id(), Some("6"));
+    }
+
+    #[test]
+    fn long_and_empty_txt_values_are_quoted() {
+        let long = "a".repeat(300);
+
+        assert_eq!(
+            quoted(&long),
+            format!("\"{}\" \"{}\"", "a".repeat(255), "a".repeat(45))
+        );
+        assert_eq!(quoted(""), "\"\"");
+        assert_eq!(quoted("\"\""), "\"\"");
+    }
+}
@@ -0,0 +1,129 @@
+use std::{
+    process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use truenas_acme_auth::{DNSRecord, DeletionPolicy, NetcupAPIClient};
+
+/// Exit code after an interrupt, as shells report it for SIGINT
+pub const EXIT_INTERRUPTED: i32 = 130;
+
+/// Handles SIGINT and SIGTERM for every command: the first signal asks the
+/// running command to stop, so the records it created can be removed again
+/// before exiting; a second one exits immediately.
+pub struct Interrupt {
+    flag: Arc<AtomicBool>,
+    created: Mutex<Vec<(String, DNSRecord)>>,
+}
+
+impl Interrupt {
+    pub fn install() -> Arc<Self> {
+        let flag = Arc::new(AtomicBool::new(false));
+
+        let handler_flag = flag.clone();
+        let result = ctrlc::set_handler(move || {
+            if handler_flag.swap(true, Ordering::SeqCst) {
+                process::exit(EXIT_INTERRUPTED);
+            }
+            eprintln!("Interrupted, cleaning up (interrupt again to exit immediately)");
+        });
+        if let Err(err) = result {
+            eprintln!("Warning: could not install the signal handler: {}", err);
+        }
+
+        Arc::new(Self {
+            flag,
+            created: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// For [`PropagationOptions::cancel`](truenas_acme_auth::PropagationOptions).
+    pub fn flag(&self) -> Arc<AtomicBool> {
+        self.flag.clone()
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.flag.load(Ordering::SeqCst)
+    }
+
+    /// Sleeps for `duration`, returning `false` if interrupted before.
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+
+        while !self.is_set() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return true;
+            }
+            thread::sleep(remaining.min(Duration::from_millis(200)));
+        }
+
+        false
+    }
+
+    /// Remembers a record this invocation created, as returned with its id.
+    pub fn track(&self, domain: &str, record: DNSRecord) {
+        self.created.lock().unwrap().push((domain.into(), record));
+    }
+
+    /// Best-effort removal of the tracked records by their ids, so an equal
+    /// record that was there before stays. One without an id, or whose id
+    /// now holds something else, is left for removing manually.
+    pub fn remove_created(&self, client: &NetcupAPIClient) {
+        // A dry run created nothing
+        if client.is_dry_run() {
+            return;
+        }
+
+        for (domain, record) in self.created.lock().unwrap().drain(..) {
+            let Some(id) = record.id() else {
+                eprintln!(
+                    "Could not remove {} from {}, its id is unknown; remove it manually",
+                    record, domain
+                );
+                continue;
+            };
+
+            let removed = client
+                .get_record(&domain, id)
+                .and_then(|found| match found {
+                    None => Ok(None),
+                    Some(found)
+                        if found.hostname() != record.hostname()
+                            || found.record_type() != record.record_type()
+                            || !found.has_destination(record.destination()) =>
+                    {
+                        Err(anyhow!("record {} is now {}", id, found))
+                    }
+                    Some(found) => client
+                        .delete_records(
+                            &domain,
+                            vec![found],
+                            DeletionPolicy::from_allow_critical(false),
+                        )
+                        .map(Some),
+                });
+
+            match removed {
+                Ok(None) => eprintln!("{} is already gone from {}", record, domain),
+                Ok(Some(outcome)) if outcome.skipped.is_empty() => {
+                    eprintln!("Removed {} from {}", record, domain)
+                }
+                Ok(Some(_)) => eprintln!(
+                    "Could not remove {} from {}, it is protected; remove it manually",
+                    record, domain
+                ),
+                Err(err) => eprintln!(
+                    "Could not remove {} from {}, remove it manually: {:#}",
+                    record, domain, err
+                ),
+            }
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    process, thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Held while a command changes zones, so concurrent invocations (e.g.
+/// several renewals started at once) take turns. The lock is the file's
+/// advisory lock, which the OS releases when the process dies, so a file
+/// left behind by a crashed run doesn't block anyone. The holder's pid in
+/// the file is only for the error message.
+pub struct InvocationLock {
+    file: File,
+    path: PathBuf,
+}
+
+impl InvocationLock {
+    /// Waits up to `timeout` for the other invocations to finish.
+    pub fn acquire(path: &Path, timeout: Duration) -> anyhow::Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .context(format!("Failed to open {}", path.display()))?;
+
+        let start = Instant::now();
+        let mut waiting = false;
+        loop {
+            match file.try_lock() {
+                Ok(()) => break,
+                Err(fs::TryLockError::WouldBlock) => {}
+                Err(fs::TryLockError::Error(err)) => {
+                    return Err(err).context(format!("Failed to lock {}", path.display()))
+                }
+            }
+
+            let holder = holder(&mut file);
+            if start.elapsed() >= timeout {
+                bail!(
+                    "Another invocation{} still holds {} after {}, pass --no-lock to run anyway",
+                    holder.map_or(String::new(), |pid| format!(" (pid {})", pid)),
+                    path.display(),
+                    humantime::format_duration(timeout)
+                );
+            }
+            if !waiting {
+                waiting = true;
+                log::info!(
+                    "Waiting for another invocation{} to finish",
+                    holder.map_or(String::new(), |pid| format!(" (pid {})", pid))
+                );
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        write!(file, "{}", process::id())?;
+        log::debug!("Acquired the invocation lock {}", path.display());
+
+        Ok(Self {
+            file,
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for InvocationLock {
+    fn drop(&mut self) {
+        let _ = self.file.set_len(0);
+        if let Err(err) = self.file.unlock() {
+            log::warn!("Failed to unlock {}: {}", self.path.display(), err);
+        }
+    }
+}
+
+/// The pid the holder wrote into the file, if it is readable and wrote one.
+fn holder(file: &mut File) -> Option<u32> {
+    let mut contents = String::new();
+    file.seek(SeekFrom::Start(0)).ok()?;
+    file.read_to_string(&mut contents).ok()?;
+    contents.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use truenas_acme_auth::testing;
+
+    use super::*;
+
+    #[test]
+    fn a_held_lock_times_out_until_it_is_dropped() {
+        let path = testing::temp_dir("lock_held").join("invocation.lock");
+        let first = InvocationLock::acquire(&path, Duration::ZERO).unwrap();
+
+        let err = InvocationLock::acquire(&path, Duration::from_millis(300))
+            .err()
+            .unwrap();
+        let message = err.to_string();
+        assert!(
+            message.contains(&format!("(pid {})", process::id())),
+            "{}",
+            message
+        );
+
+        drop(first);
+        InvocationLock::acquire(&path, Duration::ZERO).unwrap();
+    }
+
+    #[test]
+    fn a_file_left_by_a_dead_process_doesnt_block() {
+        let path = testing::temp_dir("lock_stale").join("invocation.lock");
+        fs::write(&path, u32::MAX.to_string()).unwrap();
+
+        let _lock = InvocationLock::acquire(&path, Duration::ZERO).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            process::id().to_string()
+        );
+    }
+}
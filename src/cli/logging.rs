@@ -0,0 +1,108 @@
+use std::{
+    io::{self, Write},
+    time::SystemTime,
+};
+
+use log::{
+    kv::{self, Key, Value, VisitSource},
+    Level, LevelFilter, Log, Metadata, Record,
+};
+use serde_json::{json, Map};
+
+/// Prints the log lines of this crate to stderr, as text or one JSON object
+/// per line. The library keeps credentials out of what it logs.
+struct StderrLogger {
+    level: LevelFilter,
+    json: bool,
+}
+
+/// Installs the logger: warnings only by default, `-v` adds info, `-vv`
+/// debug (every request and response) and `-vvv` trace.
+pub fn init(verbosity: u8, json: bool) {
+    let level = match verbosity {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+
+    if log::set_boxed_logger(Box::new(StderrLogger { level, json })).is_ok() {
+        log::set_max_level(level);
+    }
+}
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        // Dependencies like reqwest log as well, which is rarely of interest
+        metadata.level() <= self.level && metadata.target().starts_with(env!("CARGO_CRATE_NAME"))
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let mut fields = Fields::default();
+        let _ = record.key_values().visit(&mut fields);
+
+        let line = if self.json {
+            let mut object = Map::new();
+            object.insert(
+                "time".into(),
+                json!(humantime::format_rfc3339_millis(SystemTime::now()).to_string()),
+            );
+            object.insert("level".into(), json!(level_name(record.level())));
+            object.insert("target".into(), json!(record.target()));
+            object.insert("message".into(), json!(record.args().to_string()));
+            object.extend(fields.json);
+            serde_json::Value::Object(object).to_string()
+        } else {
+            format!(
+                "[{}] {}{}",
+                level_name(record.level()),
+                record.args(),
+                fields.text
+            )
+        };
+
+        let _ = writeln!(io::stderr(), "{}", line);
+    }
+
+    fn flush(&self) {}
+}
+
+fn level_name(level: Level) -> &'static str {
+    match level {
+        Level::Error => "error",
+        Level::Warn => "warn",
+        Level::Info => "info",
+        Level::Debug => "debug",
+        Level::Trace => "trace",
+    }
+}
+
+/// The key-value pairs of a record, for either format.
+#[derive(Default)]
+struct Fields {
+    text: String,
+    json: Map<String, serde_json::Value>,
+}
+
+impl<'kvs> VisitSource<'kvs> for Fields {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        // Empty values, e.g. the domain of a login, are left out
+        let value = if let Some(number) = value.to_u64() {
+            json!(number)
+        } else {
+            let text = value.to_string();
+            if text.is_empty() {
+                return Ok(());
+            }
+            json!(text)
+        };
+
+        self.text.push_str(&format!(" {}={}", key, value));
+        self.json.insert(key.to_string(), value);
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+use std::io::{self, Write};
+
+use clap::{Args, ValueEnum};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// For people
+    Text,
+    /// One JSON document at the end, as with --json
+    Json,
+    /// One JSON object per line as soon as it is known, each shaped like an
+    /// element of the arrays in the json format
+    Ndjson,
+}
+
+#[derive(Debug, Args)]
+pub struct FormatArgs {
+    /// Text unless --json is given
+    #[arg(long, value_enum)]
+    format: Option<OutputFormat>,
+}
+
+impl FormatArgs {
+    /// The format given, else the one --json stands for.
+    pub fn resolve(&self, json: bool) -> OutputFormat {
+        self.format.unwrap_or(if json {
+            OutputFormat::Json
+        } else {
+            OutputFormat::Text
+        })
+    }
+}
+
+/// Writes NDJSON lines to stdout, flushing each so a consumer reading a pipe
+/// gets it right away rather than when the buffer fills.
+pub fn print_line(value: &impl Serialize) -> anyhow::Result<()> {
+    write_line(&mut io::stdout().lock(), value)
+}
+
+/// [`print_line`] to any writer.
+pub fn write_line(out: &mut impl Write, value: &impl Serialize) -> anyhow::Result<()> {
+    serde_json::to_writer(&mut *out, value)?;
+    out.write_all(b"\n")?;
+    out.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    /// Remembers what had been written at each flush.
+    #[derive(Default)]
+    struct Flushes {
+        buffer: Vec<u8>,
+        flushed: Vec<String>,
+    }
+
+    impl Write for Flushes {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.buffer.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushed
+                .push(String::from_utf8(self.buffer.clone()).unwrap());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn each_line_is_flushed_as_it_is_written() {
+        let mut out = Flushes::default();
+
+        write_line(&mut out, &json!({ "id": "1" })).unwrap();
+        assert_eq!(out.flushed, ["{\"id\":\"1\"}\n"]);
+
+        write_line(&mut out, &json!({ "id": "2" })).unwrap();
+        assert_eq!(out.flushed[1], "{\"id\":\"1\"}\n{\"id\":\"2\"}\n");
+    }
+
+    #[test]
+    fn json_flag_is_the_default_format() {
+        let args = FormatArgs { format: None };
+        assert_eq!(args.resolve(false), OutputFormat::Text);
+        assert_eq!(args.resolve(true), OutputFormat::Json);
+
+        let args = FormatArgs {
+            format: Some(OutputFormat::Ndjson),
+        };
+        assert_eq!(args.resolve(true), OutputFormat::Ndjson);
+    }
+}
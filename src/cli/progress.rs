@@ -0,0 +1,100 @@
+use std::{
+    io::{self, IsTerminal, Write},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use super::interrupt::Interrupt;
+use truenas_acme_auth::{ProgressCallback, PropagationProgress};
+
+/// How often plain progress lines are printed when the line can't be
+/// updated in place.
+const PLAIN_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A status line on stderr for long waits.
+///
+/// On a terminal the line is redrawn in place. Otherwise, with `--quiet` or
+/// with `--json` (whose output shouldn't be mixed with control sequences),
+/// it degrades to a plain line every 30 seconds.
+#[derive(Debug)]
+pub struct Progress {
+    live: bool,
+    last_plain: Mutex<Option<Instant>>,
+}
+
+impl Progress {
+    pub fn new(quiet: bool, json: bool) -> Self {
+        Self {
+            live: !quiet && !json && io::stderr().is_terminal(),
+            last_plain: Mutex::new(None),
+        }
+    }
+
+    pub fn update(&self, message: &str) {
+        if self.live {
+            eprint!("\r\x1b[2K{}", message);
+            let _ = io::stderr().flush();
+            return;
+        }
+
+        let mut last = self.last_plain.lock().unwrap();
+        if last.is_none_or(|last| last.elapsed() >= PLAIN_INTERVAL) {
+            eprintln!("{}", message);
+            *last = Some(Instant::now());
+        }
+    }
+
+    /// Clears the live line, so regular output continues on a fresh line.
+    pub fn finish(&self) {
+        if self.live {
+            eprint!("\r\x1b[2K");
+            let _ = io::stderr().flush();
+        }
+        *self.last_plain.lock().unwrap() = None;
+    }
+
+    /// Sleeps for `duration`, counting down with `message` (which gets the
+    /// remaining seconds appended). Returns `false` if interrupted.
+    pub fn wait(&self, duration: Duration, message: &str, interrupt: &Interrupt) -> bool {
+        let deadline = Instant::now() + duration;
+
+        let completed = loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break true;
+            }
+
+            self.update(&format!("{} {}s", message, remaining.as_secs() + 1));
+            if !interrupt.sleep(remaining.min(Duration::from_secs(1))) {
+                break false;
+            }
+        };
+
+        self.finish();
+        completed
+    }
+
+    /// A callback for [`PropagationOptions::progress`](truenas_acme_auth::PropagationOptions).
+    pub fn propagation(self: &Arc<Self>) -> ProgressCallback {
+        let progress = self.clone();
+
+        Arc::new(move |status: &PropagationProgress| {
+            let mut needed = if status.required < status.total {
+                format!(" ({} needed)", status.required)
+            } else {
+                String::new()
+            };
+            if status.unreachable > 0 {
+                needed.push_str(&format!(", {} unreachable", status.unreachable));
+            }
+            progress.update(&format!(
+                "{}s elapsed, {}/{} nameservers confirmed{}, next poll in {}s",
+                status.elapsed.as_secs(),
+                status.confirmed,
+                status.total,
+                needed,
+                status.next_poll.as_secs() + 1
+            ))
+        })
+    }
+}
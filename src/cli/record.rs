@@ -0,0 +1,421 @@
+use std::{path::PathBuf, time::Duration};
+
+use anyhow::{bail, Context};
+use clap::{Args, Subcommand};
+use truenas_acme_auth::{
+    CaaRecord, CaaTag, DNSRecord, DeletionOutcome, DeletionPolicy, MxRecord, NetcupAPIClient,
+    NetcupError, SrvRecord, TlsaRecord,
+};
+
+use super::output::{print_line, FormatArgs, OutputFormat};
+
+#[derive(Debug, Subcommand)]
+pub enum RecordCommands {
+    /// Add a record, e.g. `record add --type CAA --caa-tag issue --caa-value letsencrypt.org example.de @`
+    Add(RecordArgs),
+    /// Change the record with this id (see `list`) to the one given as for `add`
+    Update {
+        #[arg(long)]
+        id: String,
+        #[command(flatten)]
+        record: RecordArgs,
+    },
+    /// Poll the zone and print every change until interrupted (NDJSON with
+    /// --json or --format json, as a watch never ends)
+    Watch {
+        domain: String,
+        #[arg(long, default_value = "30s", value_parser = humantime::parse_duration)]
+        interval: Duration,
+        #[command(flatten)]
+        format: FormatArgs,
+    },
+    /// Delete all records matching hostname, type and (optionally) destination
+    Delete {
+        #[arg(long = "type")]
+        record_type: String,
+        domain: String,
+        /// Hostname relative to the domain ("@" for the apex)
+        hostname: String,
+        destination: Option<String>,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct RecordArgs {
+    /// Record type (A, AAAA, CNAME, MX, TXT, CAA, ...)
+    #[arg(long = "type")]
+    record_type: String,
+    #[arg(long)]
+    priority: Option<String>,
+    /// CAA property tag (issue, issuewild, iodef)
+    #[arg(long, requires = "caa_value")]
+    caa_tag: Option<CaaTag>,
+    /// CAA value, e.g. the issuer domain (without quotes)
+    #[arg(long, requires = "caa_tag")]
+    caa_value: Option<String>,
+    /// Set the issuer-critical flag on the CAA record
+    #[arg(long, requires = "caa_tag")]
+    caa_critical: bool,
+    /// TLSA usage, selector and matching type, e.g. `--tlsa-params 3 1 1`
+    #[arg(long, num_args = 3, value_names = ["USAGE", "SELECTOR", "MATCHING_TYPE"])]
+    tlsa_params: Option<Vec<u8>>,
+    /// Certificate or public key (PEM or DER) to compute the TLSA data from
+    #[arg(long, requires = "tlsa_params", conflicts_with = "tlsa_hash")]
+    tlsa_from_cert: Option<PathBuf>,
+    /// Precomputed TLSA data as hex
+    #[arg(long, requires = "tlsa_params")]
+    tlsa_hash: Option<String>,
+    /// SRV weight (the SRV priority is passed with --priority)
+    #[arg(long, requires_all = ["srv_port", "srv_target"])]
+    srv_weight: Option<u16>,
+    #[arg(long, requires_all = ["srv_weight", "srv_target"])]
+    srv_port: Option<u16>,
+    /// SRV target hostname
+    #[arg(long, requires_all = ["srv_weight", "srv_port"])]
+    srv_target: Option<String>,
+    pub domain: String,
+    /// Hostname relative to the domain ("@" for the apex)
+    hostname: String,
+    /// Record destination, can be omitted if the type-specific flags are used
+    destination: Option<String>,
+}
+
+impl RecordArgs {
+    pub fn into_record(self) -> anyhow::Result<DNSRecord> {
+        let record_type = self.record_type.to_ascii_uppercase();
+
+        if record_type != "CAA" && self.caa_tag.is_some() {
+            bail!("--caa-tag can only be used with --type CAA");
+        }
+        if record_type != "TLSA" && self.tlsa_params.is_some() {
+            bail!("--tlsa-params can only be used with --type TLSA");
+        }
+        if record_type != "SRV" && self.srv_weight.is_some() {
+            bail!("--srv-weight can only be used with --type SRV");
+        }
+
+        match record_type.as_str() {
+            "CAA" => self.caa_record(),
+            "TLSA" => self.tlsa_record(),
+            "SRV" => self.srv_record(),
+            "MX" => self.mx_record(),
+            _ => {
+                let destination = self.destination.context("Missing destination")?;
+                let record = DNSRecord::new(self.hostname, record_type, destination);
+
+                Ok(match self.priority {
+                    Some(priority) => record.with_priority(priority),
+                    None => record,
+                })
+            }
+        }
+    }
+
+    fn caa_record(self) -> anyhow::Result<DNSRecord> {
+        let caa = match (self.caa_tag, self.caa_value, self.destination) {
+            (Some(tag), Some(value), None) => CaaRecord::new(tag, value)?,
+            (None, None, Some(destination)) => destination.parse()?,
+            _ => bail!("Pass either --caa-tag/--caa-value or a destination"),
+        };
+        let caa = if self.caa_critical {
+            caa.critical()
+        } else {
+            caa
+        };
+
+        Ok(caa.to_record(self.hostname))
+    }
+
+    fn tlsa_record(self) -> anyhow::Result<DNSRecord> {
+        let params = self.tlsa_params.as_deref();
+
+        let tlsa = match (
+            params,
+            self.tlsa_from_cert,
+            self.tlsa_hash,
+            self.destination,
+        ) {
+            (Some(&[usage, selector, matching]), Some(path), None, None) => {
+                TlsaRecord::from_certificate_file(usage, selector, matching, path)?
+            }
+            (Some(&[usage, selector, matching]), None, Some(hash), None) => {
+                TlsaRecord::new(usage, selector, matching, hash)?
+            }
+            (None, None, None, Some(destination)) => destination.parse()?,
+            _ => bail!("Pass --tlsa-params with --tlsa-from-cert or --tlsa-hash, or a destination"),
+        };
+
+        Ok(tlsa.to_record(self.hostname))
+    }
+
+    fn mx_record(self) -> anyhow::Result<DNSRecord> {
+        let priority = self.priority.context("MX records require --priority")?;
+        let priority = priority
+            .parse()
+            .context(format!("Invalid MX priority '{}'", priority))?;
+        let mail_host = self.destination.context("Missing mail host")?;
+
+        Ok(MxRecord::new(priority, &mail_host)?.to_record(self.hostname))
+    }
+
+    fn srv_record(self) -> anyhow::Result<DNSRecord> {
+        let priority = self.priority.context("SRV records require --priority")?;
+        let priority = priority
+            .parse()
+            .context(format!("Invalid SRV priority '{}'", priority))?;
+        let (service, protocol, name) = SrvRecord::split_hostname(&self.hostname)?;
+
+        let srv = match (
+            self.srv_weight,
+            self.srv_port,
+            self.srv_target,
+            self.destination,
+        ) {
+            (Some(weight), Some(port), Some(target), None) => {
+                SrvRecord::new(service, protocol, name, priority, weight, port, &target)?
+            }
+            (None, None, None, Some(destination)) => {
+                let record = DNSRecord::new(&self.hostname, "SRV", destination)
+                    .with_priority(priority.to_string());
+                SrvRecord::try_from(&record)?
+            }
+            _ => bail!("Pass either --srv-weight/--srv-port/--srv-target or a destination"),
+        };
+
+        Ok(srv.to_record())
+    }
+}
+
+/// Implements `record delete`.
+pub fn delete(
+    client: &NetcupAPIClient,
+    domain: &str,
+    hostname: &str,
+    record_type: &str,
+    destination: Option<&str>,
+    policy: DeletionPolicy,
+    json: bool,
+) -> anyhow::Result<()> {
+    let records: Vec<_> = client
+        .list_records(domain)?
+        .into_iter()
+        .filter(|r| {
+            r.hostname() == hostname
+                && r.record_type().eq_ignore_ascii_case(record_type)
+                && destination.is_none_or(|d| r.has_destination(d))
+        })
+        .collect();
+
+    if records.is_empty() {
+        bail!("No matching records found!");
+    }
+
+    let outcome = client.delete_records(domain, records, policy)?;
+
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({
+                "domain": domain,
+                "deleted": outcome.deleted,
+                "skipped": outcome.skipped,
+            })
+        );
+        return Ok(());
+    }
+
+    for record in &outcome.deleted {
+        println!("Deleted {}", record);
+    }
+    for record in &outcome.skipped {
+        println!("Skipped critical record {} (use --allow-critical)", record);
+    }
+
+    Ok(())
+}
+
+/// Implements `unset-by-id`: deletes the records with these ids, as `set`
+/// printed them, in one call. Ids that don't exist are reported as already
+/// absent, or with `strict` fail before anything is deleted.
+pub fn delete_by_id(
+    client: &NetcupAPIClient,
+    domain: &str,
+    ids: &[String],
+    strict: bool,
+    policy: DeletionPolicy,
+    json: bool,
+) -> anyhow::Result<()> {
+    let zone = client.list_records(domain)?;
+
+    let mut records = Vec::new();
+    for id in ids {
+        match zone.iter().find(|r| r.id() == Some(id.as_str())) {
+            Some(record) => records.push(record.clone()),
+            None if strict => {
+                return Err(NetcupError::RecordNotFound {
+                    domain: domain.into(),
+                    id: id.clone(),
+                }
+                .into())
+            }
+            None => eprintln!("Record {} is already absent", id),
+        }
+    }
+
+    let outcome = if records.is_empty() {
+        DeletionOutcome::default()
+    } else {
+        client.delete_records(domain, records, policy)?
+    };
+
+    if json {
+        println!(
+            "{}",
+            serde_json::json!({
+                "domain": domain,
+                "deleted": outcome.deleted,
+                "skipped": outcome.skipped,
+            })
+        );
+        return Ok(());
+    }
+
+    for record in &outcome.skipped {
+        eprintln!("Skipped critical record {} (use --allow-critical)", record);
+    }
+    if !client.is_dry_run() {
+        eprintln!("Removed {} records", outcome.deleted.len());
+    }
+    Ok(())
+}
+
+/// Implements `list`: the zone's records as a table sorted by hostname and
+/// type, the destination last so long TXT values don't break the columns.
+pub fn list(
+    client: &NetcupAPIClient,
+    domain: &str,
+    record_type: Option<&str>,
+    hostname: Option<&str>,
+    format: OutputFormat,
+) -> anyhow::Result<()> {
+    let mut records: Vec<_> = client
+        .list_records(domain)?
+        .into_iter()
+        .filter(|r| {
+            record_type.is_none_or(|t| r.record_type().eq_ignore_ascii_case(t))
+                && hostname.is_none_or(|h| r.hostname() == h)
+        })
+        .collect();
+    records.sort_by(|a, b| (a.hostname(), a.record_type()).cmp(&(b.hostname(), b.record_type())));
+
+    match format {
+        OutputFormat::Json => {
+            println!("{}", serde_json::to_string(&records)?);
+            return Ok(());
+        }
+        OutputFormat::Ndjson => return records.iter().try_for_each(print_line),
+        OutputFormat::Text => {}
+    }
+
+    let rows: Vec<[&str; 6]> = records
+        .iter()
+        .map(|r| {
+            [
+                r.hostname(),
+                r.record_type(),
+                r.priority().filter(|_| r.uses_priority()).unwrap_or("-"),
+                r.state().unwrap_or("-"),
+                r.id().unwrap_or("-"),
+                r.destination(),
+            ]
+        })
+        .collect();
+
+    let header = ["HOSTNAME", "TYPE", "PRIORITY", "STATE", "ID", "DESTINATION"];
+    let mut widths = header.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    for row in std::iter::once(&header).chain(&rows) {
+        let line: Vec<String> = row[..5]
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .chain(std::iter::once(row[5].to_string()))
+            .collect();
+        println!("{}", line.join("  "));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use truenas_acme_auth::testing::{self, txt, MockTransport};
+
+    use super::*;
+
+    fn ids(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn unset_by_id_deletes_only_those_records() {
+        let mock = MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_success(
+            "infoDnsRecords",
+            json!({ "dnsrecords": [txt(1, "token"), txt(2, "token")] }),
+        )
+        .respond_success(
+            "updateDnsRecords",
+            json!({ "dnsrecords": [txt(1, "token")] }),
+        );
+
+        delete_by_id(
+            &client,
+            "example.com",
+            &ids(&["2", "9"]),
+            false,
+            DeletionPolicy::default(),
+            false,
+        )
+        .unwrap();
+
+        assert_eq!(
+            mock.actions(),
+            ["login", "infoDnsRecords", "updateDnsRecords"]
+        );
+        let deletion = &mock.requests()[2]["param"]["dnsrecordset"]["dnsrecords"];
+        assert_eq!(deletion.as_array().unwrap().len(), 1);
+        assert_eq!(deletion[0]["id"], "2");
+        assert_eq!(deletion[0]["deleterecord"], true);
+    }
+
+    #[test]
+    fn strict_unset_by_id_deletes_nothing_for_a_missing_id() {
+        let mock = MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_success("infoDnsRecords", json!({ "dnsrecords": [txt(1, "token")] }));
+
+        let err = delete_by_id(
+            &client,
+            "example.com",
+            &ids(&["1", "9"]),
+            true,
+            DeletionPolicy::default(),
+            false,
+        )
+        .unwrap_err();
+
+        assert!(matches!(
+            err.downcast_ref::<NetcupError>(),
+            Some(NetcupError::RecordNotFound { id, .. }) if id == "9"
+        ));
+        assert_eq!(mock.actions(), ["login", "infoDnsRecords"]);
+    }
+}
@@ -0,0 +1,156 @@
+use std::{
+    process,
+    sync::Arc,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+    interrupt::{Interrupt, EXIT_INTERRUPTED},
+    progress::Progress,
+    wait::{wait_for_txt_record, warn_unverified, WaitArgs},
+};
+use anyhow::anyhow;
+use base64::URL_SAFE_NO_PAD;
+use clap::Args;
+use sha2::{Digest, Sha256};
+use truenas_acme_auth::{verify_absence, NetcupAPIClient, NetcupError};
+
+/// The API refused to create the record
+pub const EXIT_API_FAILURE: i32 = 10;
+/// The record didn't show up on every authoritative nameserver in time
+pub const EXIT_PROPAGATION_TIMEOUT: i32 = 11;
+/// The record could not be removed again, or is still being served
+pub const EXIT_CLEANUP_FAILURE: i32 = 12;
+
+#[derive(Debug, Args)]
+pub struct SelftestArgs {
+    pub domain: String,
+    /// Host of the test record, relative to the domain
+    #[arg(long, default_value = "_acme-challenge-selftest")]
+    hostname: String,
+    #[command(flatten)]
+    wait: WaitArgs,
+}
+
+/// Implements `selftest`, returning the exit code.
+///
+/// Once the record was created it is always removed again, also when the
+/// propagation check fails or the process is interrupted.
+pub fn selftest(
+    client: &NetcupAPIClient,
+    args: SelftestArgs,
+    progress: &Arc<Progress>,
+    interrupt: &Interrupt,
+) -> i32 {
+    let options = args.wait.options(client, progress, interrupt);
+    let value = random_value();
+
+    let start = Instant::now();
+    let created = match client.force_add_txt_record(&args.domain, &args.hostname, &value) {
+        Ok(created) => created,
+        Err(err) => {
+            eprintln!(
+                "API accept:  FAILED after {:.1?}: {:#}",
+                start.elapsed(),
+                err
+            );
+            return EXIT_API_FAILURE;
+        }
+    };
+    println!("API accept:  {:.1?}", start.elapsed());
+
+    let mut code = 0;
+
+    let propagation = wait_for_txt_record(
+        client,
+        &args.domain,
+        &args.hostname,
+        &value,
+        &options,
+        &args.wait.reassert,
+        progress,
+    );
+    progress.finish();
+    match propagation {
+        Ok((report, reassertions)) => {
+            println!(
+                "Propagation: {:.1?} ({}){}",
+                report.elapsed,
+                if report.assumed {
+                    "not verified".into()
+                } else {
+                    report.nameservers.join(", ")
+                },
+                match reassertions {
+                    0 => String::new(),
+                    1 => ", after resubmitting the record once".into(),
+                    n => format!(", after resubmitting the record {} times", n),
+                }
+            );
+            warn_unverified(&report, true);
+        }
+        Err(err) => {
+            eprintln!("Propagation: FAILED: {:#}", err);
+            code = match err.downcast_ref::<NetcupError>() {
+                Some(NetcupError::Interrupted) => EXIT_INTERRUPTED,
+                _ => EXIT_PROPAGATION_TIMEOUT,
+            };
+        }
+    }
+
+    // The cleanup's absence check may still be cut short by an interrupt.
+    // Only the created record is removed, by its id: going by the value
+    // could hit an equal record that was there before.
+    let start = Instant::now();
+    let removed = match created.id() {
+        Some(id) => client.delete_record(id, &args.domain, &args.hostname, &value),
+        None => Err(anyhow!("netcup returned the created record without an id")),
+    };
+    if let Err(err) = removed {
+        eprintln!(
+            "Cleanup:     FAILED, remove {}.{} TXT {} manually: {:#}",
+            args.hostname, args.domain, value, err
+        );
+        return EXIT_CLEANUP_FAILURE;
+    }
+
+    if code == EXIT_INTERRUPTED {
+        println!("Cleanup:     record removed");
+        return code;
+    }
+
+    let absence = verify_absence(&args.domain, &args.hostname, &value, &options);
+    progress.finish();
+    match absence {
+        Ok(report) => {
+            println!("Cleanup:     {:.1?}", start.elapsed());
+            warn_unverified(&report, false);
+        }
+        Err(err) => {
+            eprintln!(
+                "Cleanup:     record removed, but its absence was not confirmed: {:#}",
+                err
+            );
+            return match err.downcast_ref::<NetcupError>() {
+                Some(NetcupError::Interrupted) => EXIT_INTERRUPTED,
+                _ => EXIT_CLEANUP_FAILURE,
+            };
+        }
+    }
+
+    code
+}
+
+/// A value in the shape of an ACME key authorization digest.
+fn random_value() -> String {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+
+    let mut hasher = Sha256::new();
+    hasher.update(nanos.to_le_bytes());
+    hasher.update(process::id().to_le_bytes());
+
+    base64::encode_config(hasher.finalize(), URL_SAFE_NO_PAD)
+}
@@ -0,0 +1,196 @@
+use std::{env, path::PathBuf};
+
+use anyhow::{bail, Context};
+use clap::{Args, Subcommand};
+use reqwest::{blocking::Client, header::CONTENT_TYPE, Method, StatusCode};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+#[derive(Debug, Subcommand)]
+pub enum TruenasCommands {
+    /// Register this binary as an ACME DNS authenticator
+    Install(InstallArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct InstallArgs {
+    /// TrueNAS API key (Credentials > API Keys)
+    #[arg(long, env = "TRUENAS_API_KEY")]
+    truenas_api_key: String,
+    /// Base URL of the TrueNAS REST API
+    #[arg(long, default_value = "http://localhost/api/v2.0")]
+    url: String,
+    /// Name of the authenticator entry
+    #[arg(long, default_value = "netcup")]
+    name: String,
+    /// User TrueNAS runs the script as, must be able to read the config
+    #[arg(long)]
+    user: Option<String>,
+    /// Script path to register, defaults to this executable
+    #[arg(long)]
+    script: Option<PathBuf>,
+    /// Accept self-signed TrueNAS certificates
+    #[arg(long)]
+    insecure: bool,
+    /// Remove the authenticator entry instead
+    #[arg(long)]
+    uninstall: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct Authenticator {
+    id: u64,
+    authenticator: String,
+    name: String,
+    #[serde(default)]
+    attributes: Value,
+}
+
+struct TruenasApi {
+    client: Client,
+    url: String,
+    api_key: String,
+}
+
+impl TruenasApi {
+    fn request(&self, method: Method, path: &str, body: Option<&Value>) -> anyhow::Result<Value> {
+        let url = format!("{}/{}", self.url.trim_end_matches('/'), path);
+
+        let mut request = self.client.request(method, &url).bearer_auth(&self.api_key);
+        if let Some(body) = body {
+            request = request
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.to_string());
+        }
+
+        let response = request
+            .send()
+            .context(format!("Failed to reach the TrueNAS API at {}", url))?;
+
+        match response.status() {
+            StatusCode::UNAUTHORIZED => bail!("TrueNAS rejected the API key"),
+            StatusCode::FORBIDDEN => {
+                bail!("The TrueNAS API key lacks permission to manage ACME DNS authenticators")
+            }
+            status if !status.is_success() => {
+                bail!("TrueNAS API returned {}: {}", status, response.text()?)
+            }
+            _ => {}
+        }
+
+        let text = response.text()?;
+        if text.is_empty() {
+            return Ok(Value::Null);
+        }
+        serde_json::from_str(&text).context("Could not parse the TrueNAS API response")
+    }
+
+    fn find(&self, name: &str) -> anyhow::Result<Option<Authenticator>> {
+        let all: Vec<Authenticator> =
+            serde_json::from_value(self.request(Method::GET, "acme/dns/authenticator", None)?)?;
+
+        Ok(all.into_iter().find(|a| a.name == name))
+    }
+}
+
+/// Implements `truenas install`.
+pub fn install(args: InstallArgs) -> anyhow::Result<()> {
+    let api = TruenasApi {
+        client: Client::builder()
+            .danger_accept_invalid_certs(args.insecure)
+            .build()?,
+        url: args.url,
+        api_key: args.truenas_api_key,
+    };
+
+    let existing = api.find(&args.name)?;
+
+    if args.uninstall {
+        let Some(existing) = existing else {
+            println!("No authenticator named {} is registered", args.name);
+            return Ok(());
+        };
+
+        api.request(
+            Method::DELETE,
+            &format!("acme/dns/authenticator/id/{}", existing.id),
+            None,
+        )?;
+
+        if api.find(&args.name)?.is_some() {
+            bail!("Authenticator {} is still registered", args.name);
+        }
+
+        println!("Removed authenticator {} (id {})", args.name, existing.id);
+        return Ok(());
+    }
+
+    let script = match args.script {
+        Some(script) => script,
+        None => env::current_exe()?,
+    };
+    let script = script
+        .to_str()
+        .context("Script path is not valid UTF-8")?
+        .to_string();
+
+    // TrueNAS calls the script as `<script> set|unset <domain> <fqdn> <content>`,
+    // which is exactly the interface of this binary
+    let mut attributes = json!({ "script": script });
+    if let Some(user) = &args.user {
+        attributes["user"] = json!(user);
+    }
+
+    let id = match existing {
+        Some(existing) => {
+            if existing.authenticator != "shell" {
+                bail!(
+                    "Authenticator {} exists but is of type {}, not shell",
+                    args.name,
+                    existing.authenticator
+                );
+            }
+
+            api.request(
+                Method::PUT,
+                &format!("acme/dns/authenticator/id/{}", existing.id),
+                Some(&json!({ "name": args.name, "attributes": attributes })),
+            )?;
+            existing.id
+        }
+        None => {
+            let created: Authenticator = serde_json::from_value(api.request(
+                Method::POST,
+                "acme/dns/authenticator",
+                Some(&json!({
+                    "authenticator": "shell",
+                    "name": args.name,
+                    "attributes": attributes,
+                })),
+            )?)?;
+            created.id
+        }
+    };
+
+    let installed: Authenticator = serde_json::from_value(api.request(
+        Method::GET,
+        &format!("acme/dns/authenticator/id/{}", id),
+        None,
+    )?)?;
+
+    if installed.authenticator != "shell" || installed.attributes["script"] != json!(script) {
+        bail!(
+            "Authenticator {} was saved but reads back as {} with attributes {}",
+            args.name,
+            installed.authenticator,
+            installed.attributes
+        );
+    }
+
+    println!(
+        "Authenticator {} (id {}) runs {}",
+        installed.name, installed.id, script
+    );
+
+    Ok(())
+}
@@ -0,0 +1,221 @@
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use truenas_acme_auth::{DnsZone, NetcupAPIClient};
+
+/// A zone whose TTL `set --lower-ttl` lowered, remembered until the last
+/// `unset --restore-ttl` puts it back.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LoweredTtl {
+    /// The TTL before the first `set` lowered it
+    original: u32,
+    /// The challenges set since then and not yet unset, e.g. two for a
+    /// wildcard certificate and its apex
+    challenges: u32,
+}
+
+/// Implements `set --lower-ttl`: lowers the zone's TTL to `ttl` and
+/// remembers the original in `path` for the restore. If an earlier `set`
+/// already lowered it, the remembered original is kept. A TTL that already
+/// is as low is left alone.
+pub fn lower_ttl(
+    client: &NetcupAPIClient,
+    domain: &str,
+    ttl: u32,
+    path: &Path,
+) -> anyhow::Result<()> {
+    update_state(client, path, |state| {
+        let zone = client.get_zone(domain)?;
+
+        if let Some(lowered) = state.get_mut(domain) {
+            lowered.challenges += 1;
+            if zone.ttl != ttl {
+                set_ttl(client, domain, zone, ttl)?;
+            }
+            return Ok(());
+        }
+
+        if zone.ttl <= ttl {
+            eprintln!("The TTL of {} already is {}", domain, zone.ttl);
+            return Ok(());
+        }
+
+        let original = zone.ttl;
+        set_ttl(client, domain, zone, ttl)?;
+        state.insert(
+            domain.into(),
+            LoweredTtl {
+                original,
+                challenges: 1,
+            },
+        );
+        Ok(())
+    })
+}
+
+/// Implements `unset --restore-ttl`: puts back the TTL `lower_ttl`
+/// remembered, once the last challenge that lowered it is gone. Without a
+/// remembered TTL it only warns and leaves the zone alone.
+pub fn restore_ttl(client: &NetcupAPIClient, domain: &str, path: &Path) -> anyhow::Result<()> {
+    update_state(client, path, |state| {
+        let Some(lowered) = state.get_mut(domain) else {
+            eprintln!(
+                "Warning: no lowered TTL remembered for {} in {}, leaving it as it is",
+                domain,
+                path.display()
+            );
+            return Ok(());
+        };
+
+        lowered.challenges = lowered.challenges.saturating_sub(1);
+        if lowered.challenges > 0 {
+            eprintln!(
+                "Keeping the TTL of {} lowered for {} more challenges",
+                domain, lowered.challenges
+            );
+            return Ok(());
+        }
+
+        let original = lowered.original;
+        let zone = client.get_zone(domain)?;
+        if zone.ttl != original {
+            set_ttl(client, domain, zone, original)?;
+        }
+        state.remove(domain);
+        Ok(())
+    })
+}
+
+fn set_ttl(client: &NetcupAPIClient, domain: &str, zone: DnsZone, ttl: u32) -> anyhow::Result<()> {
+    let previous = zone.ttl;
+    client.update_zone(domain, &DnsZone { ttl, ..zone })?;
+
+    if client.is_dry_run() {
+        eprintln!(
+            "Dry run: would change the TTL of {} from {} to {}",
+            domain, previous, ttl
+        );
+    } else {
+        eprintln!("Changed the TTL of {} from {} to {}", domain, previous, ttl);
+    }
+    Ok(())
+}
+
+/// Reads the lowered TTLs under the file's lock, applies `f` and writes
+/// the result back if it succeeded, so a failed change is tried again by
+/// the next invocation. A dry run doesn't write.
+fn update_state(
+    client: &NetcupAPIClient,
+    path: &Path,
+    f: impl FnOnce(&mut HashMap<String, LoweredTtl>) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .context(format!("Failed to open {}", path.display()))?;
+    file.lock()
+        .context(format!("Failed to lock {}", path.display()))?;
+
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+    let mut state: HashMap<String, LoweredTtl> = if contents.trim().is_empty() {
+        HashMap::new()
+    } else {
+        // Unlike a lost cache, losing this would leave zones at the low TTL
+        serde_json::from_str(&contents).context(format!("Failed to parse {}", path.display()))?
+    };
+
+    f(&mut state)?;
+
+    if !client.is_dry_run() {
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(serde_json::to_string(&state)?.as_bytes())?;
+    }
+    file.unlock()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use truenas_acme_auth::testing::{self, MockTransport};
+
+    use super::*;
+
+    fn zone(ttl: u32) -> Value {
+        json!({
+            "name": "example.com",
+            "ttl": ttl.to_string(),
+            "serial": "2026101401",
+            "refresh": "28800",
+            "retry": "7200",
+            "expire": "1209600",
+            "dnssecstatus": false,
+        })
+    }
+
+    fn state(path: &Path) -> HashMap<String, LoweredTtl> {
+        serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn the_original_ttl_is_saved_and_restored_once() {
+        let path = testing::temp_dir("ttl").join("ttl.json");
+        let mock = MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_success("infoDnsZone", zone(86400))
+            .respond_success("updateDnsZone", zone(300));
+
+        lower_ttl(&client, "example.com", 300, &path).unwrap();
+
+        assert_eq!(mock.requests()[2]["param"]["dnszone"]["ttl"], "300");
+        assert_eq!(state(&path)["example.com"].original, 86400);
+
+        mock.respond_success("infoDnsZone", zone(300))
+            .respond_success("updateDnsZone", zone(86400));
+        restore_ttl(&client, "example.com", &path).unwrap();
+
+        assert_eq!(mock.requests()[4]["param"]["dnszone"]["ttl"], "86400");
+        assert!(state(&path).is_empty());
+
+        // Nothing remembered any more, nothing sent
+        restore_ttl(&client, "example.com", &path).unwrap();
+        assert_eq!(mock.requests().len(), 5);
+    }
+
+    #[test]
+    fn the_ttl_stays_lowered_until_the_last_challenge() {
+        let path = testing::temp_dir("ttl_challenges").join("ttl.json");
+        let mock = MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_success("infoDnsZone", zone(86400))
+            .respond_success("updateDnsZone", zone(300))
+            .respond_success("infoDnsZone", zone(300));
+
+        lower_ttl(&client, "example.com", 300, &path).unwrap();
+        lower_ttl(&client, "example.com", 300, &path).unwrap();
+        restore_ttl(&client, "example.com", &path).unwrap();
+
+        assert_eq!(
+            mock.actions(),
+            ["login", "infoDnsZone", "updateDnsZone", "infoDnsZone"]
+        );
+        assert_eq!(state(&path)["example.com"].challenges, 1);
+        assert_eq!(state(&path)["example.com"].original, 86400);
+    }
+}
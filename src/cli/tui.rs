@@ -0,0 +1,301 @@
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, List, ListItem, ListState, Paragraph, Wrap},
+    DefaultTerminal, Frame,
+};
+use truenas_acme_auth::{is_acme_challenge, DNSRecord, DeletionPolicy, NetcupAPIClient};
+
+enum Mode {
+    Browse,
+    Filter,
+    ConfirmDelete,
+    Edit(String),
+}
+
+struct App<'a> {
+    client: &'a NetcupAPIClient,
+    domain: &'a str,
+    policy: DeletionPolicy,
+    records: Vec<DNSRecord>,
+    filter: String,
+    list: ListState,
+    mode: Mode,
+    status: String,
+}
+
+/// Implements `tui`: browses the records of `domain`. Changes go through the
+/// client like those of the other commands, so the allowlist, the deletion
+/// policy and the audit log apply.
+pub fn tui(client: &NetcupAPIClient, domain: &str, policy: DeletionPolicy) -> anyhow::Result<()> {
+    let mut app = App {
+        client,
+        domain,
+        policy,
+        records: client.list_records(domain)?,
+        filter: String::new(),
+        list: ListState::default().with_selected(Some(0)),
+        mode: Mode::Browse,
+        status: String::new(),
+    };
+
+    let mut terminal = ratatui::init();
+    let result = app.run(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+impl App<'_> {
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> anyhow::Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && !self.handle_key(key) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// The records matching the filter, case-insensitively anywhere in
+    /// hostname, type or destination.
+    fn visible(&self) -> Vec<&DNSRecord> {
+        let filter = self.filter.to_lowercase();
+        self.records
+            .iter()
+            .filter(|record| record.to_string().to_lowercase().contains(&filter))
+            .collect()
+    }
+
+    fn selected(&self) -> Option<DNSRecord> {
+        let visible = self.visible();
+        self.list
+            .selected()
+            .and_then(|index| visible.get(index))
+            .map(|record| (*record).clone())
+    }
+
+    /// Returns `false` to quit.
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return false;
+        }
+
+        match &mut self.mode {
+            Mode::Browse => {
+                self.status.clear();
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => return false,
+                    KeyCode::Down | KeyCode::Char('j') => self.list.select_next(),
+                    KeyCode::Up | KeyCode::Char('k') => self.list.select_previous(),
+                    KeyCode::Char('/') => self.mode = Mode::Filter,
+                    KeyCode::Char('r') => self.refresh(),
+                    KeyCode::Char('d') if self.selected().is_some() => {
+                        self.mode = Mode::ConfirmDelete
+                    }
+                    KeyCode::Char('e') => {
+                        if let Some(record) = self.selected() {
+                            self.mode = Mode::Edit(record.destination().to_string());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Mode::Filter => match key.code {
+                KeyCode::Enter => self.mode = Mode::Browse,
+                KeyCode::Esc => {
+                    self.filter.clear();
+                    self.mode = Mode::Browse;
+                }
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                    self.list.select_first();
+                }
+                KeyCode::Char(c) => {
+                    self.filter.push(c);
+                    self.list.select_first();
+                }
+                _ => {}
+            },
+            Mode::ConfirmDelete => {
+                if key.code == KeyCode::Char('y') {
+                    self.delete();
+                } else {
+                    self.status = "Not deleted".into();
+                }
+                self.mode = Mode::Browse;
+            }
+            Mode::Edit(destination) => match key.code {
+                KeyCode::Enter => {
+                    let destination = destination.clone();
+                    self.mode = Mode::Browse;
+                    self.edit(destination);
+                }
+                KeyCode::Esc => self.mode = Mode::Browse,
+                KeyCode::Backspace => {
+                    destination.pop();
+                }
+                KeyCode::Char(c) => destination.push(c),
+                _ => {}
+            },
+        }
+
+        true
+    }
+
+    fn refresh(&mut self) {
+        match self.client.list_records(self.domain) {
+            Ok(records) => {
+                self.status = format!("Fetched {} records", records.len());
+                self.records = records;
+            }
+            Err(err) => self.status = format!("Could not fetch the zone: {:#}", err),
+        }
+    }
+
+    fn delete(&mut self) {
+        let Some(record) = self.selected() else {
+            return;
+        };
+
+        match self
+            .client
+            .delete_records(self.domain, vec![record.clone()], self.policy)
+        {
+            Ok(outcome) if outcome.skipped.is_empty() => {
+                self.refresh();
+                self.status = format!("Deleted {}", record);
+            }
+            Ok(_) => {
+                self.status = format!(
+                    "{} is protected, pass --allow-critical to delete it",
+                    record
+                )
+            }
+            Err(err) => self.status = format!("Could not delete {}: {:#}", record, err),
+        }
+    }
+
+    fn edit(&mut self, destination: String) {
+        let Some(record) = self.selected() else {
+            return;
+        };
+        if record.destination() == destination {
+            self.status = "Unchanged".into();
+            return;
+        }
+
+        let changed = record.with_destination(destination);
+        match self
+            .client
+            .update_records(self.domain, vec![changed.clone()])
+        {
+            Ok(records) => {
+                self.records = records;
+                self.status = format!("Changed to {}", changed);
+            }
+            Err(err) => self.status = format!("Could not change {}: {:#}", changed, err),
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(3), Constraint::Length(1)]).areas(frame.area());
+        let [list_area, details_area] =
+            Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)])
+                .areas(main);
+
+        let items: Vec<ListItem> = self.visible().into_iter().map(record_item).collect();
+        let title = if self.filter.is_empty() {
+            format!(" {} ({}) ", self.domain, items.len())
+        } else {
+            format!(
+                " {} ({}, filter: {}) ",
+                self.domain,
+                items.len(),
+                self.filter
+            )
+        };
+        let list = List::new(items)
+            .block(Block::bordered().title(title))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, list_area, &mut self.list);
+
+        let details = match self.selected() {
+            Some(record) => record_details(&record),
+            None => vec![Line::from("No record selected")],
+        };
+        frame.render_widget(
+            Paragraph::new(details)
+                .block(Block::bordered().title(" Details "))
+                .wrap(Wrap { trim: false }),
+            details_area,
+        );
+
+        let line = match &self.mode {
+            Mode::Browse if !self.status.is_empty() => Line::from(self.status.as_str()),
+            Mode::Browse => Line::from(
+                "j/k move  / filter  e edit destination  d delete  r refresh  q quit".dim(),
+            ),
+            Mode::Filter => Line::from(format!("/{}", self.filter)),
+            Mode::ConfirmDelete => Line::from("Delete this record? (y/n)".yellow()),
+            Mode::Edit(destination) => Line::from(format!("Destination: {}", destination)),
+        };
+        frame.render_widget(Paragraph::new(line), status);
+    }
+}
+
+fn is_pending(record: &DNSRecord) -> bool {
+    record.state().is_some_and(|state| state != "yes")
+}
+
+/// One line per record, leftover challenges in yellow and records netcup
+/// hasn't published yet in magenta.
+fn record_item(record: &DNSRecord) -> ListItem<'static> {
+    let style = if is_pending(record) {
+        Style::new().fg(Color::Magenta)
+    } else if is_acme_challenge(record) {
+        Style::new().fg(Color::Yellow)
+    } else {
+        Style::new()
+    };
+
+    ListItem::new(Line::from(vec![
+        Span::raw(format!("{:30} ", record.hostname())),
+        Span::raw(format!("{:6} ", record.record_type())),
+        Span::raw(record.destination().to_string()),
+    ]))
+    .style(style)
+}
+
+fn record_details(record: &DNSRecord) -> Vec<Line<'static>> {
+    let field = |name: &str, value: &str| {
+        Line::from(vec![
+            Span::styled(format!("{:12}", name), Style::new().bold()),
+            Span::raw(value.to_string()),
+        ])
+    };
+
+    let mut lines = vec![
+        field("Id", record.id().unwrap_or("-")),
+        field("Hostname", record.hostname()),
+        field("Type", record.record_type()),
+        field("Priority", record.priority().unwrap_or("-")),
+        field("Destination", record.destination()),
+        field("State", record.state().unwrap_or("-")),
+    ];
+
+    if is_acme_challenge(record) {
+        lines.push(Line::from(""));
+        lines.push(Line::from("ACME challenge, possibly a leftover".yellow()));
+    }
+    if is_pending(record) {
+        lines.push(Line::from(""));
+        lines.push(Line::from("Not published yet".magenta()));
+    }
+
+    lines
+}
@@ -0,0 +1,85 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+};
+
+use anyhow::bail;
+use clap::Args;
+use truenas_acme_auth::{plan_undo, undo_candidates, AuditLog, NetcupAPIClient};
+
+#[derive(Debug, Args)]
+pub struct UndoArgs {
+    /// Only undo changes of this domain
+    #[arg(long)]
+    pub domain: Option<String>,
+    /// How many changes to undo, newest first
+    #[arg(long, default_value_t = 1)]
+    steps: usize,
+    /// Apply without asking
+    #[arg(long, short)]
+    yes: bool,
+}
+
+/// Implements `undo`.
+pub fn undo(client: &NetcupAPIClient, audit_log: &AuditLog, args: UndoArgs) -> anyhow::Result<()> {
+    let entries = audit_log.read()?;
+    let candidates = undo_candidates(&entries, args.domain.as_deref(), args.steps);
+
+    if candidates.is_empty() {
+        println!("Nothing to undo");
+        return Ok(());
+    }
+
+    // Later steps are planned against the zone as the earlier ones leave it
+    let mut zones = HashMap::new();
+    let mut plans = Vec::new();
+    for entry in candidates {
+        if !zones.contains_key(&entry.domain) {
+            zones.insert(entry.domain.clone(), client.list_records(&entry.domain)?);
+        }
+        let zone = zones.get_mut(&entry.domain).expect("inserted above");
+
+        let plan = plan_undo(entry, zone);
+        plan.apply_to(zone);
+        plans.push((entry, plan));
+    }
+
+    for (entry, plan) in &plans {
+        println!("{}  {}  {}", entry.timestamp, entry.command, entry.domain);
+        for change in &plan.changes {
+            let action = if change.is_deletion() {
+                "delete"
+            } else {
+                "create"
+            };
+            println!("    {}  {}", action, change);
+        }
+        for (record, reason) in &plan.skipped {
+            println!("    skip    {} ({})", record, reason);
+        }
+    }
+
+    if !args.yes && !confirm("Apply?")? {
+        bail!("Aborted");
+    }
+
+    for (entry, plan) in plans {
+        if plan.changes.is_empty() {
+            audit_log.mark_undone(&entry.domain)?;
+        } else {
+            client.update_records(&entry.domain, plan.changes)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn confirm(question: &str) -> anyhow::Result<bool> {
+    print!("{} [y/N] ", question);
+    io::stdout().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
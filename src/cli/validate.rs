@@ -0,0 +1,407 @@
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use schemars::{schema_for, Schema};
+
+use super::config::{merge_layer, parse_layer, search_path, Config, ConfigFormat, KEY_ALIASES};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Severity {
+    Error,
+    Warning,
+}
+
+/// One finding, located by file and dotted key path where possible.
+struct Problem {
+    severity: Severity,
+    file: Option<PathBuf>,
+    key: String,
+    message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.severity {
+            Severity::Error => write!(f, "error: ")?,
+            Severity::Warning => write!(f, "warning: ")?,
+        }
+        if let Some(file) = &self.file {
+            write!(f, "{}: ", file.display())?;
+        }
+        if !self.key.is_empty() {
+            write!(f, "{}: ", self.key)?;
+        }
+        f.write_str(&self.message)
+    }
+}
+
+#[derive(Default)]
+struct Problems(Vec<Problem>);
+
+impl Problems {
+    fn error(&mut self, file: Option<&Path>, key: impl Into<String>, message: impl Into<String>) {
+        self.push(Severity::Error, file, key.into(), message.into());
+    }
+
+    fn warning(&mut self, file: Option<&Path>, key: impl Into<String>, message: impl Into<String>) {
+        self.push(Severity::Warning, file, key.into(), message.into());
+    }
+
+    fn push(&mut self, severity: Severity, file: Option<&Path>, key: String, message: String) {
+        self.0.push(Problem {
+            severity,
+            file: file.map(Path::to_path_buf),
+            key,
+            message,
+        });
+    }
+
+    fn count(&self, severity: Severity) -> usize {
+        self.0.iter().filter(|p| p.severity == severity).count()
+    }
+}
+
+/// Implements `config validate --schema`: the JSON Schema of the config
+/// format, derived from [`Config`].
+pub fn print_schema() -> anyhow::Result<()> {
+    println!("{}", serde_json::to_string_pretty(&schema_for!(Config))?);
+    Ok(())
+}
+
+/// Implements `config validate`: checks `path`, or every file on the search
+/// path, and their merged result. Prints each problem and returns the exit
+/// code, 1 if there were errors.
+pub fn validate(path: Option<&Path>) -> anyhow::Result<i32> {
+    let files = match path {
+        Some(path) => vec![path.to_path_buf()],
+        None => search_path(None)?
+            .into_iter()
+            .filter(|path| path.is_file())
+            .collect(),
+    };
+
+    let schema = schema_for!(Config);
+    let mut problems = Problems::default();
+    let mut merged = toml::value::Table::new();
+    let mut layers: Vec<(PathBuf, toml::value::Table)> = Vec::new();
+
+    for file in &files {
+        let contents = match fs::read_to_string(file) {
+            Ok(contents) => contents,
+            Err(err) => {
+                problems.error(Some(file), "", format!("Failed to read: {}", err));
+                continue;
+            }
+        };
+        let (layer, format) = match parse_layer(file, &contents) {
+            Ok(parsed) => parsed,
+            Err(err) => {
+                // The file is already named
+                problems.error(Some(file), "", err.root_cause().to_string());
+                continue;
+            }
+        };
+
+        let unknown_keys = check_keys(&schema, file, &layer, &mut problems);
+
+        // Deserializing from the text keeps the key and line in the message
+        let config = match format {
+            ConfigFormat::Toml => {
+                toml::from_str::<Config>(&contents).map_err(|err| err.to_string())
+            }
+            ConfigFormat::Json => {
+                serde_json::from_str::<Config>(&contents).map_err(|err| err.to_string())
+            }
+            ConfigFormat::Yaml => {
+                serde_yaml::from_str::<Config>(&contents).map_err(|err| err.to_string())
+            }
+        };
+        match config {
+            Ok(config) => check_values(file, &config, &mut problems),
+            // The unknown key, already reported with a suggestion
+            Err(_) if unknown_keys => {}
+            Err(err) => problems.error(Some(file), "", err),
+        }
+
+        if let Err(err) = merge_layer(&mut merged, layer.clone()) {
+            problems.error(Some(file), "", err.to_string());
+        }
+        layers.push((file.clone(), layer));
+    }
+
+    if files.is_empty() {
+        problems.warning(None, "", "No config files found");
+    }
+
+    if let Ok(config) = toml::Value::Table(merged).try_into::<Config>() {
+        check_merged(&config, &layers, &mut problems);
+    }
+
+    for problem in &problems.0 {
+        println!("{}", problem);
+    }
+
+    let errors = problems.count(Severity::Error);
+    println!(
+        "{} errors, {} warnings in {} files",
+        errors,
+        problems.count(Severity::Warning),
+        files.len()
+    );
+
+    Ok(if errors > 0 { 1 } else { 0 })
+}
+
+/// The keys the schema defines for the object at `pointer`.
+fn known_keys<'a>(schema: &'a Schema, pointer: &str) -> Vec<&'a str> {
+    schema
+        .as_value()
+        .pointer(pointer)
+        .and_then(|properties| properties.as_object())
+        .map(|properties| properties.keys().map(String::as_str).collect())
+        .unwrap_or_default()
+}
+
+/// Flags keys the config format doesn't know, at the top level and in
+/// profiles, with the closest known key as a suggestion. Returns whether
+/// there were any.
+fn check_keys(
+    schema: &Schema,
+    file: &Path,
+    layer: &toml::value::Table,
+    problems: &mut Problems,
+) -> bool {
+    let aliases = KEY_ALIASES.iter().map(|(alias, _)| *alias);
+    let mut top_level = known_keys(schema, "/properties");
+    top_level.extend(aliases.clone());
+    let mut profile = known_keys(schema, "/$defs/Profile/properties");
+    profile.extend(aliases);
+    let before = problems.0.len();
+
+    for key in layer.keys() {
+        if !top_level.contains(&key.as_str()) {
+            problems.error(Some(file), key, unknown_key(key, &top_level));
+        }
+    }
+
+    if let Some(toml::Value::Table(profiles)) = layer.get("profiles") {
+        for (name, value) in profiles {
+            let toml::Value::Table(table) = value else {
+                continue;
+            };
+            for key in table.keys() {
+                if !profile.contains(&key.as_str()) {
+                    problems.error(
+                        Some(file),
+                        format!("profiles.{}.{}", name, key),
+                        unknown_key(key, &profile),
+                    );
+                }
+            }
+        }
+    }
+
+    problems.0.len() > before
+}
+
+fn unknown_key(key: &str, known: &[&str]) -> String {
+    let closest = known
+        .iter()
+        .map(|candidate| {
+            let distance = if candidate.eq_ignore_ascii_case(key) {
+                0
+            } else {
+                edit_distance(&key.to_lowercase(), &candidate.to_lowercase()).max(1)
+            };
+            (distance, candidate)
+        })
+        .min();
+
+    match closest {
+        Some((distance, candidate)) if distance <= 2 || distance <= key.len() / 3 => {
+            format!("Unknown key, did you mean {}?", candidate)
+        }
+        _ => "Unknown key".into(),
+    }
+}
+
+/// The Levenshtein distance between `a` and `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+/// Value formats within one file.
+fn check_values(file: &Path, config: &Config, problems: &mut Problems) {
+    let mut customer_numbers: Vec<(String, &str)> = Vec::new();
+    if let Some(customer_id) = &config.customer_id {
+        customer_numbers.push(("CID".into(), customer_id));
+    }
+    for (name, profile) in &config.profiles {
+        customer_numbers.push((format!("profiles.{}.CID", name), &profile.customer_id));
+    }
+    for (key, customer_id) in customer_numbers {
+        if customer_id.is_empty() || !customer_id.chars().all(|c| c.is_ascii_digit()) {
+            problems.error(Some(file), key, "The customer number has to be numeric");
+        }
+    }
+
+    let mut secrets = vec![(
+        String::new(),
+        config.api_password.is_some() && config.api_password_file.is_some(),
+        config.api_key.is_some() && config.api_key_file.is_some(),
+    )];
+    for (name, profile) in &config.profiles {
+        secrets.push((
+            format!("profiles.{}.", name),
+            profile.api_password.is_some() && profile.api_password_file.is_some(),
+            profile.api_key.is_some() && profile.api_key_file.is_some(),
+        ));
+    }
+    for (prefix, both_passwords, both_keys) in secrets {
+        for (both, key) in [(both_passwords, "API_PW"), (both_keys, "API_KEY")] {
+            if both {
+                problems.error(
+                    Some(file),
+                    format!("{}{}_FILE", prefix, key),
+                    format!("Set either {} or {}_FILE, not both", key, key),
+                );
+            }
+        }
+    }
+
+    if let Some(endpoint) = &config.endpoint {
+        match reqwest::Url::parse(endpoint) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+            Ok(url) => problems.error(
+                Some(file),
+                "endpoint",
+                format!("Expected an http or https URL, not {}", url.scheme()),
+            ),
+            Err(err) => problems.error(Some(file), "endpoint", format!("Invalid URL: {}", err)),
+        }
+    }
+
+    if let Some(proxy) = &config.proxy {
+        // Without a scheme the proxy is taken to be http
+        let url = reqwest::Url::parse(proxy)
+            .ok()
+            .filter(|url| url.has_host())
+            .or_else(|| reqwest::Url::parse(&format!("http://{}", proxy)).ok());
+        match url {
+            Some(url) if matches!(url.scheme(), "http" | "https") => {}
+            _ => problems.error(
+                Some(file),
+                "proxy",
+                "Expected an http or https URL like http://proxy:3128",
+            ),
+        }
+    }
+
+    if config.timeout == Some(0) {
+        problems.error(Some(file), "timeout", "Has to be at least 1 second");
+    }
+    if config.batch_size == Some(0) {
+        problems.error(Some(file), "batch_size", "Has to be at least 1");
+    }
+    if config.retry_attempts == Some(0) {
+        problems.error(Some(file), "retry_attempts", "Has to be at least 1");
+    }
+
+    for (index, pattern) in config.allowed_domains.iter().flatten().enumerate() {
+        if pattern.trim_start_matches("*.").contains('*') {
+            problems.error(
+                Some(file),
+                format!("allowed_domains[{}]", index),
+                format!("{} never matches, only a leading *. is supported", pattern),
+            );
+        }
+    }
+}
+
+/// Rules across keys, which may be spread over several files.
+fn check_merged(
+    config: &Config,
+    layers: &[(PathBuf, toml::value::Table)],
+    problems: &mut Problems,
+) {
+    // The file that set a key last, which is where it has to be fixed
+    let origin = |path: &[&str]| {
+        layers
+            .iter()
+            .rev()
+            .find(|(_, layer)| {
+                let mut value = layer.get(path[0]);
+                for key in &path[1..] {
+                    value = value.and_then(|value| value.get(key));
+                }
+                value.is_some()
+            })
+            .map(|(file, _)| file.as_path())
+    };
+
+    let mut suffixes: Vec<_> = config.domains.iter().collect();
+    suffixes.sort();
+    for (suffix, name) in suffixes {
+        if !config.profiles.contains_key(name) {
+            problems.error(
+                origin(&["domains", suffix]),
+                format!("domains.{}", suffix),
+                format!("Profile {} is not defined in [profiles]", name),
+            );
+        }
+    }
+
+    if let Some(name) = &config.default_profile {
+        if !config.profiles.contains_key(name) {
+            problems.error(
+                origin(&["default_profile"]),
+                "default_profile",
+                format!("Profile {} is not defined in [profiles]", name),
+            );
+        }
+    }
+
+    let mut profiles: Vec<_> = config.profiles.keys().collect();
+    profiles.sort();
+    for name in profiles {
+        let used = config.default_profile.as_ref() == Some(name)
+            || config.domains.values().any(|profile| profile == name);
+        if !used {
+            problems.warning(
+                origin(&["profiles", name]),
+                format!("profiles.{}", name),
+                "Not used by [domains] or default_profile",
+            );
+        }
+    }
+
+    if config.ipv4_only && config.ipv6_only {
+        problems.error(
+            origin(&["ipv6_only"]),
+            "ipv6_only",
+            "ipv4_only and ipv6_only can't both be set",
+        );
+    }
+
+    if config.customer_id.is_none() && config.default_profile.is_none() {
+        problems.warning(
+            None,
+            "",
+            "No default credentials, commands for zones outside [domains] need them on the command line",
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use clap::Args;
+use truenas_acme_auth::{
+    verify_propagation, NetcupAPIClient, NetcupError, PropagationOptions, PropagationReport, Quorum,
+};
+
+use super::{interrupt::Interrupt, progress::Progress};
+
+/// How to wait for records on the nameservers.
+#[derive(Debug, Args)]
+pub struct WaitArgs {
+    /// How long to wait for the nameservers
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "10m")]
+    wait_timeout: Duration,
+    /// How often to ask the nameservers
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "10s")]
+    poll_interval: Duration,
+    /// How many nameservers have to agree: all, a number, or a percentage
+    /// like 66%
+    #[arg(long, default_value_t = Quorum::All)]
+    propagation_quorum: Quorum,
+    /// If no nameserver can be queried at all, e.g. with port 53 blocked,
+    /// succeed after this long instead of failing
+    #[arg(long, value_parser = humantime::parse_duration)]
+    assume_propagated_after: Option<Duration>,
+    #[command(flatten)]
+    pub reassert: ReassertArgs,
+}
+
+impl WaitArgs {
+    /// The options for a wait that reports progress and stops on Ctrl-C.
+    pub fn options(
+        &self,
+        client: &NetcupAPIClient,
+        progress: &Arc<Progress>,
+        interrupt: &Interrupt,
+    ) -> PropagationOptions {
+        PropagationOptions {
+            timeout: self.wait_timeout,
+            poll_interval: self.poll_interval,
+            cancel: Some(interrupt.flag()),
+            progress: Some(progress.propagation()),
+            family: client.address_family(),
+            quorum: self.propagation_quorum,
+            assume_propagated_after: self.assume_propagated_after,
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct ReassertArgs {
+    /// Resubmit the record if some nameservers still lack it after this long
+    #[arg(long, value_parser = humantime::parse_duration)]
+    reassert_after: Option<Duration>,
+    /// How often to resubmit the record at most
+    #[arg(long, default_value_t = 3, requires = "reassert_after")]
+    max_reassertions: u32,
+}
+
+/// Waits for a TXT record like [`verify_propagation`]. With
+/// `--reassert-after`, each time that passes with nameservers still lacking
+/// the record it is resubmitted and polled for again, within the overall
+/// timeout. Returns how many reassertions were needed.
+pub fn wait_for_txt_record(
+    client: &NetcupAPIClient,
+    domain: &str,
+    hostname: &str,
+    content: &str,
+    options: &PropagationOptions,
+    reassert: &ReassertArgs,
+    progress: &Progress,
+) -> anyhow::Result<(PropagationReport, u32)> {
+    let start = Instant::now();
+    let mut reassertions = 0;
+
+    loop {
+        let remaining = options.timeout.saturating_sub(start.elapsed());
+        let after = reassert
+            .reassert_after
+            .filter(|after| reassertions < reassert.max_reassertions && *after < remaining);
+
+        let result = verify_propagation(
+            domain,
+            hostname,
+            content,
+            &PropagationOptions {
+                timeout: after.unwrap_or(remaining),
+                ..options.clone()
+            },
+        );
+
+        let missing = match &result {
+            Err(err) if after.is_some() => match err.downcast_ref::<NetcupError>() {
+                Some(NetcupError::PropagationTimeout { nameservers, .. }) => {
+                    Some(nameservers.join(", "))
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+
+        let Some(missing) = missing else {
+            return result.map(|report| {
+                let elapsed = start.elapsed();
+                (PropagationReport { elapsed, ..report }, reassertions)
+            });
+        };
+
+        reassertions += 1;
+        progress.finish();
+        eprintln!(
+            "{} is still missing on {}, resubmitting it ({}/{})",
+            hostname, missing, reassertions, reassert.max_reassertions
+        );
+        client.reassert_txt_record(domain, hostname, content)?;
+    }
+}
+
+/// Names the nameservers a quorum left behind, or says that nothing could
+/// be checked at all. `present` tells whether the record was waited for or
+/// its removal.
+pub fn warn_unverified(report: &PropagationReport, present: bool) {
+    if report.assumed {
+        eprintln!(
+            "Warning:     no nameserver could be queried, assuming the record is {} after {:.1?} without checking",
+            if present { "served" } else { "gone" },
+            report.elapsed
+        );
+    } else if !report.stragglers.is_empty() {
+        eprintln!(
+            "Warning:     {} of {} nameservers {}: {}",
+            report.stragglers.len(),
+            report.nameservers.len(),
+            if present {
+                "still lack the record"
+            } else {
+                "still serve the record"
+            },
+            report.stragglers.join(", ")
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use std::time::{Duration, SystemTime};
+
+use super::{
+    interrupt::Interrupt,
+    output::{print_line, OutputFormat},
+    progress::Progress,
+};
+use truenas_acme_auth::{diff_snapshots, NetcupAPIClient, RecordChange};
+
+/// Implements `records watch`. Failed polls back off exponentially and
+/// refresh the session, since long watches outlive netcup's sessions.
+pub fn watch(
+    client: &mut NetcupAPIClient,
+    api_password: &str,
+    domain: &str,
+    interval: Duration,
+    format: OutputFormat,
+    progress: &Progress,
+    interrupt: &Interrupt,
+) -> anyhow::Result<()> {
+    const MAX_BACKOFF: Duration = Duration::from_secs(600);
+
+    let mut snapshot = client.list_records(domain)?;
+    let mut failures = 0;
+
+    eprintln!(
+        "Watching {} ({} records), press Ctrl-C to stop",
+        domain,
+        snapshot.len()
+    );
+
+    loop {
+        let delay = (interval * 2u32.pow(failures.min(5))).min(MAX_BACKOFF.max(interval));
+        let waited = if failures > 0 {
+            progress.wait(
+                delay,
+                &format!("Poll failed {} times, retrying in", failures),
+                interrupt,
+            )
+        } else {
+            interrupt.sleep(delay)
+        };
+        if !waited {
+            return Ok(());
+        }
+
+        let timestamp = humantime::format_rfc3339_seconds(SystemTime::now());
+
+        match client.list_records(domain) {
+            Ok(records) => {
+                failures = 0;
+
+                for change in diff_snapshots(&snapshot, &records) {
+                    if format != OutputFormat::Text {
+                        let mut event = serde_json::to_value(&change)?;
+                        event["timestamp"] = timestamp.to_string().into();
+                        print_line(&event)?;
+                    } else {
+                        match change {
+                            RecordChange::Added { record } => {
+                                println!("{} added    {}", timestamp, record)
+                            }
+                            RecordChange::Removed { record } => {
+                                println!("{} removed  {}", timestamp, record)
+                            }
+                            RecordChange::Modified { old, new } => {
+                                println!("{} modified {} -> {}", timestamp, old, new)
+                            }
+                        }
+                    }
+                }
+
+                snapshot = records;
+            }
+            Err(err) => {
+                failures += 1;
+                eprintln!("{} polling failed: {:#}", timestamp, err);
+
+                if let Err(err) = client.relogin(api_password) {
+                    eprintln!("{} could not refresh the session: {:#}", timestamp, err);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use clap::Subcommand;
+use serde_json::json;
+use truenas_acme_auth::{DnsZone, NetcupAPIClient};
+
+#[derive(Debug, Subcommand)]
+pub enum ZoneCommands {
+    /// Print the zone settings
+    #[command(visible_alias = "info")]
+    Show { domain: String },
+    /// Change the TTL of every record in the zone
+    SetTtl { domain: String, seconds: u32 },
+}
+
+impl ZoneCommands {
+    pub fn domain(&self) -> &str {
+        match self {
+            ZoneCommands::Show { domain } | ZoneCommands::SetTtl { domain, .. } => domain,
+        }
+    }
+}
+
+/// Implements `zone`.
+pub fn zone(client: &NetcupAPIClient, command: ZoneCommands, json: bool) -> anyhow::Result<()> {
+    let zone = match command {
+        ZoneCommands::Show { domain } => client.get_zone(&domain)?,
+        ZoneCommands::SetTtl { domain, seconds } => {
+            let zone = client.get_zone(&domain)?;
+            if zone.ttl == seconds {
+                eprintln!("The TTL already is {}", seconds);
+                zone
+            } else {
+                let updated = client.update_zone(
+                    &domain,
+                    &DnsZone {
+                        ttl: seconds,
+                        ..zone.clone()
+                    },
+                )?;
+                if client.is_dry_run() {
+                    eprintln!(
+                        "Dry run: would change the TTL from {} to {}",
+                        zone.ttl, seconds
+                    );
+                } else {
+                    eprintln!("Changed the TTL from {} to {}", zone.ttl, updated.ttl);
+                }
+                updated
+            }
+        }
+    };
+
+    if json {
+        println!("{}", json!(zone));
+    } else {
+        println!("zone     {}", zone.name);
+        println!("ttl      {}", zone.ttl);
+        println!("serial   {}", zone.serial);
+        println!("refresh  {}", zone.refresh);
+        println!("retry    {}", zone.retry);
+        println!("expire   {}", zone.expire);
+        println!("dnssec   {}", if zone.dnssecstatus { "on" } else { "off" });
+    }
+
+    Ok(())
+}
@@ -0,0 +1,200 @@
+use std::{
+    sync::{atomic::AtomicBool, Arc},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+
+use crate::{propagation::sleep, DNSRecord, NetcupAPIClient, NetcupError};
+
+/// The state netcup reports for a record its nameservers publish.
+const PUBLISHED: &str = "yes";
+
+/// How [`NetcupAPIClient::confirm_records`] polls the zone.
+#[derive(Debug, Clone)]
+pub struct ConfirmOptions {
+    pub timeout: Duration,
+    pub poll_interval: Duration,
+    /// Set to stop polling early with [`NetcupError::Interrupted`]
+    pub cancel: Option<Arc<AtomicBool>>,
+}
+
+impl Default for ConfirmOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(300),
+            poll_interval: Duration::from_secs(5),
+            cancel: None,
+        }
+    }
+}
+
+impl NetcupAPIClient {
+    /// Waits until netcup reports each of `records` with the state `yes`,
+    /// i.e. handed to its nameservers, by reading the zone every
+    /// [`poll_interval`](ConfirmOptions::poll_interval). Unlike
+    /// [`verify_propagation`](crate::verify_propagation) this asks the API,
+    /// not the nameservers. Returns the records as last read.
+    ///
+    /// The records are matched by id. Times out with a
+    /// [`NetcupError::ConfirmationTimeout`] holding those still pending,
+    /// and fails if one of them disappears from the zone.
+    pub fn confirm_records(
+        &self,
+        domain: &str,
+        records: &[DNSRecord],
+        options: &ConfirmOptions,
+    ) -> anyhow::Result<Vec<DNSRecord>> {
+        let ids = records
+            .iter()
+            .map(|record| record.id.clone().context("Record has no id!"))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let start = Instant::now();
+
+        loop {
+            let zone = self.list_records(domain)?;
+            let current = ids
+                .iter()
+                .map(|id| {
+                    zone.iter()
+                        .find(|r| r.id.as_ref() == Some(id))
+                        .cloned()
+                        .ok_or_else(|| NetcupError::RecordNotFound {
+                            domain: domain.into(),
+                            id: id.clone(),
+                        })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let pending: Vec<DNSRecord> = current
+                .iter()
+                .filter(|r| r.state.as_deref() != Some(PUBLISHED))
+                .cloned()
+                .collect();
+            if pending.is_empty() {
+                return Ok(current);
+            }
+
+            let remaining = options.timeout.saturating_sub(start.elapsed());
+            if remaining.is_zero() {
+                return Err(NetcupError::ConfirmationTimeout {
+                    domain: domain.into(),
+                    pending,
+                }
+                .into());
+            }
+            log::debug!(
+                domain;
+                "{} of {} records not published by netcup yet",
+                pending.len(),
+                ids.len()
+            );
+            sleep(
+                options.poll_interval.min(remaining),
+                options.cancel.as_deref(),
+                |_| {},
+            )?;
+        }
+    }
+
+    /// [`add_txt_records`](Self::add_txt_records), then
+    /// [`confirm_records`](Self::confirm_records) for them. A dry run
+    /// doesn't wait.
+    pub fn add_txt_records_confirmed(
+        &self,
+        domain: &str,
+        hostname: &str,
+        contents: &[&str],
+        options: &ConfirmOptions,
+    ) -> anyhow::Result<Vec<DNSRecord>> {
+        let records = self.add_txt_records(domain, hostname, contents)?;
+        if self.dry_run {
+            return Ok(records);
+        }
+        self.confirm_records(domain, &records, options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::testing::{self, MockTransport};
+
+    fn with_state(state: &str) -> Value {
+        let mut record = testing::txt(1, "token");
+        record["state"] = state.into();
+        json!({ "dnsrecords": [record] })
+    }
+
+    fn added() -> DNSRecord {
+        serde_json::from_value(testing::txt(1, "token")).unwrap()
+    }
+
+    fn options(timeout: Duration) -> ConfirmOptions {
+        ConfirmOptions {
+            timeout,
+            poll_interval: Duration::ZERO,
+            cancel: None,
+        }
+    }
+
+    #[test]
+    fn polls_until_the_record_is_published() {
+        let mock = MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_success("infoDnsRecords", with_state("unknown"))
+            .respond_success("infoDnsRecords", with_state("unknown"))
+            .respond_success("infoDnsRecords", with_state("yes"));
+
+        let confirmed = client
+            .confirm_records("example.com", &[added()], &options(Duration::from_secs(60)))
+            .unwrap();
+
+        assert_eq!(confirmed[0].state(), Some("yes"));
+        assert_eq!(
+            mock.actions(),
+            [
+                "login",
+                "infoDnsRecords",
+                "infoDnsRecords",
+                "infoDnsRecords"
+            ]
+        );
+    }
+
+    #[test]
+    fn times_out_with_the_last_state_seen() {
+        let mock = MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_success("infoDnsRecords", with_state("unknown"));
+
+        let err = client
+            .confirm_records("example.com", &[added()], &options(Duration::ZERO))
+            .unwrap_err();
+
+        match err.downcast_ref() {
+            Some(NetcupError::ConfirmationTimeout { pending, .. }) => {
+                assert_eq!(pending[0].state(), Some("unknown"))
+            }
+            other => panic!("expected a confirmation timeout, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn a_record_gone_from_the_zone_fails() {
+        let mock = MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_success("infoDnsRecords", json!({ "dnsrecords": [] }));
+
+        let err = client
+            .confirm_records("example.com", &[added()], &options(Duration::from_secs(60)))
+            .unwrap_err();
+
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(NetcupError::RecordNotFound { id, .. }) if id == "1"
+        ));
+    }
+}
@@ -0,0 +1,343 @@
+//! Lenient deserializers for the loosely typed fields of netcup responses.
+//!
+//! These visit the input directly instead of buffering it in a
+//! `serde_json::Value` first, which matters for `responsedata` on large zones.
+
+use std::{fmt, marker::PhantomData, str::FromStr};
+
+use serde::{
+    de::{
+        value::MapAccessDeserializer, value::SeqAccessDeserializer, Error, MapAccess, SeqAccess,
+        Visitor,
+    },
+    Deserialize, Deserializer,
+};
+
+/// Accepts a JSON number or a numeric string (netcup sends both, e.g.
+/// `"statuscode": 2000` and `"statuscode": "2000"`).
+///
+/// Anything else falls back to the default value with a warning instead of
+/// failing the whole response.
+pub(crate) fn number_or_string<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr + TryFrom<u64> + Default + fmt::Display,
+{
+    struct NumberVisitor<T>(PhantomData<T>);
+
+    impl<T> NumberVisitor<T>
+    where
+        T: Default + fmt::Display,
+    {
+        fn fallback<E>(&self, got: impl fmt::Display) -> Result<T, E> {
+            let fallback = T::default();
+            log::warn!("Expected a number but got {}, using {}", got, fallback);
+            Ok(fallback)
+        }
+    }
+
+    impl<'de, T> Visitor<'de> for NumberVisitor<T>
+    where
+        T: FromStr + TryFrom<u64> + Default + fmt::Display,
+    {
+        type Value = T;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a number or a numeric string")
+        }
+
+        fn visit_u64<E: Error>(self, n: u64) -> Result<T, E> {
+            match T::try_from(n) {
+                Ok(n) => Ok(n),
+                Err(_) => self.fallback(n),
+            }
+        }
+
+        fn visit_i64<E: Error>(self, n: i64) -> Result<T, E> {
+            match u64::try_from(n) {
+                Ok(n) => self.visit_u64(n),
+                Err(_) => self.fallback(n),
+            }
+        }
+
+        fn visit_f64<E: Error>(self, n: f64) -> Result<T, E> {
+            self.fallback(n)
+        }
+
+        fn visit_str<E: Error>(self, string: &str) -> Result<T, E> {
+            match string.trim().parse() {
+                Ok(n) => Ok(n),
+                Err(_) => self.fallback(format_args!("\"{}\"", string)),
+            }
+        }
+
+        fn visit_bool<E: Error>(self, b: bool) -> Result<T, E> {
+            self.fallback(b)
+        }
+
+        fn visit_unit<E: Error>(self) -> Result<T, E> {
+            self.fallback("null")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<T, A::Error> {
+            serde::de::IgnoredAny::deserialize(SeqAccessDeserializer::new(seq))?;
+            self.fallback("an array")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<T, A::Error> {
+            serde::de::IgnoredAny::deserialize(MapAccessDeserializer::new(map))?;
+            self.fallback("an object")
+        }
+    }
+
+    deserializer.deserialize_any(NumberVisitor(PhantomData))
+}
+
+/// Accepts a string, a number or null, keeping the value as a string (netcup
+/// returns `"priority": "10"` as well as `"priority": 10`).
+pub(crate) fn optional_string_or_number<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct StringVisitor;
+
+    impl StringVisitor {
+        fn ignore<E>(got: impl fmt::Display) -> Result<Option<String>, E> {
+            log::warn!("Expected a string or number but got {}, ignoring it", got);
+            Ok(None)
+        }
+    }
+
+    impl<'de> Visitor<'de> for StringVisitor {
+        type Value = Option<String>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a string, a number or null")
+        }
+
+        fn visit_str<E: Error>(self, string: &str) -> Result<Self::Value, E> {
+            Ok(Some(string.into()))
+        }
+
+        fn visit_string<E: Error>(self, string: String) -> Result<Self::Value, E> {
+            Ok(Some(string))
+        }
+
+        fn visit_u64<E: Error>(self, n: u64) -> Result<Self::Value, E> {
+            Ok(Some(n.to_string()))
+        }
+
+        fn visit_i64<E: Error>(self, n: i64) -> Result<Self::Value, E> {
+            Ok(Some(n.to_string()))
+        }
+
+        fn visit_f64<E: Error>(self, n: f64) -> Result<Self::Value, E> {
+            Ok(Some(n.to_string()))
+        }
+
+        fn visit_unit<E: Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_none<E: Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_some<D: Deserializer<'de>>(self, d: D) -> Result<Self::Value, D::Error> {
+            d.deserialize_any(self)
+        }
+
+        fn visit_bool<E: Error>(self, b: bool) -> Result<Self::Value, E> {
+            Self::ignore(b)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+            serde::de::IgnoredAny::deserialize(SeqAccessDeserializer::new(seq))?;
+            Self::ignore("an array")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+            serde::de::IgnoredAny::deserialize(MapAccessDeserializer::new(map))?;
+            Self::ignore("an object")
+        }
+    }
+
+    deserializer.deserialize_any(StringVisitor)
+}
+
+/// Accepts a boolean, `"true"`/`"false"` (also `"yes"`/`"no"`, `"1"`/`"0"`)
+/// or `1`/`0`, as netcup sends flags like `deleterecord` and
+/// `dnssecstatus` either way. Null and anything else are the default, the
+/// latter with a warning.
+pub(crate) fn bool_or_string<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: From<bool> + Default,
+{
+    struct BoolVisitor<T>(PhantomData<T>);
+
+    impl<T: Default> BoolVisitor<T> {
+        fn fallback<E>(&self, got: impl fmt::Display) -> Result<T, E> {
+            log::warn!("Expected a boolean but got {}, ignoring it", got);
+            Ok(T::default())
+        }
+    }
+
+    impl<'de, T: From<bool> + Default> Visitor<'de> for BoolVisitor<T> {
+        type Value = T;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a boolean or \"true\"/\"false\"")
+        }
+
+        fn visit_bool<E: Error>(self, b: bool) -> Result<T, E> {
+            Ok(b.into())
+        }
+
+        fn visit_u64<E: Error>(self, n: u64) -> Result<T, E> {
+            match n {
+                0 => Ok(false.into()),
+                1 => Ok(true.into()),
+                _ => self.fallback(n),
+            }
+        }
+
+        fn visit_i64<E: Error>(self, n: i64) -> Result<T, E> {
+            match u64::try_from(n) {
+                Ok(n) => self.visit_u64(n),
+                Err(_) => self.fallback(n),
+            }
+        }
+
+        fn visit_f64<E: Error>(self, n: f64) -> Result<T, E> {
+            self.fallback(n)
+        }
+
+        fn visit_str<E: Error>(self, string: &str) -> Result<T, E> {
+            match string.trim().to_ascii_lowercase().as_str() {
+                "true" | "yes" | "1" => Ok(true.into()),
+                "false" | "no" | "0" => Ok(false.into()),
+                "" => Ok(T::default()),
+                _ => self.fallback(format_args!("\"{}\"", string)),
+            }
+        }
+
+        fn visit_unit<E: Error>(self) -> Result<T, E> {
+            Ok(T::default())
+        }
+
+        fn visit_none<E: Error>(self) -> Result<T, E> {
+            Ok(T::default())
+        }
+
+        fn visit_some<D: Deserializer<'de>>(self, d: D) -> Result<T, D::Error> {
+            d.deserialize_any(self)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<T, A::Error> {
+            serde::de::IgnoredAny::deserialize(SeqAccessDeserializer::new(seq))?;
+            self.fallback("an array")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<T, A::Error> {
+            serde::de::IgnoredAny::deserialize(MapAccessDeserializer::new(map))?;
+            self.fallback("an object")
+        }
+    }
+
+    deserializer.deserialize_any(BoolVisitor(PhantomData))
+}
+
+/// Treats `""` and `null` as absent, netcup uses both for "no data".
+///
+/// Objects and arrays are handed to `T` as they are read.
+pub(crate) fn empty_string_as_none<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    struct OptionVisitor<T>(PhantomData<T>);
+
+    impl<'de, T: Deserialize<'de>> Visitor<'de> for OptionVisitor<T> {
+        type Value = Option<T>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("response data, an empty string or null")
+        }
+
+        fn visit_unit<E: Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_none<E: Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+
+        fn visit_str<E: Error>(self, string: &str) -> Result<Self::Value, E> {
+            if string.is_empty() {
+                Ok(None)
+            } else {
+                T::deserialize(serde::de::value::StrDeserializer::new(string)).map(Some)
+            }
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+            T::deserialize(MapAccessDeserializer::new(map)).map(Some)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+            T::deserialize(SeqAccessDeserializer::new(seq)).map(Some)
+        }
+    }
+
+    deserializer.deserialize_any(OptionVisitor(PhantomData))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        testing::{self, MockTransport},
+        RecordType,
+    };
+
+    /// Lists the zone from a captured `infoDnsRecords` response.
+    fn list(fixture: &str) -> Vec<crate::DNSRecord> {
+        let mock = MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_raw(200, fixture);
+        client.list_records("example.de").unwrap()
+    }
+
+    #[test]
+    fn numbers_come_as_numbers_or_strings() {
+        let records = list(include_str!("../tests/fixtures/number_or_string.json"));
+
+        let ids: Vec<_> = records.iter().map(|r| r.id().unwrap()).collect();
+        assert_eq!(ids, ["40123401", "40123402", "40123403"]);
+        let priorities: Vec<_> = records.iter().map(|r| r.priority()).collect();
+        assert_eq!(priorities, [Some("10"), Some("20"), None]);
+    }
+
+    #[test]
+    fn flags_come_as_booleans_or_strings() {
+        let records = list(include_str!("../tests/fixtures/bool_or_string.json"));
+
+        // "maybe" is only a warning
+        assert_eq!(records.len(), 4);
+        assert!(records.iter().all(|r| !r.is_deletion()));
+    }
+
+    #[test]
+    fn status_and_types_in_any_case() {
+        let mock = MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_raw(200, include_str!("../tests/fixtures/odd_casing.json"));
+
+        let records = client
+            .find_records("example.de", "_acme-challenge", RecordType::Txt)
+            .unwrap();
+
+        let values: Vec<_> = records.iter().map(|r| r.destination()).collect();
+        assert_eq!(values, ["lower", "mixed"]);
+    }
+}
@@ -0,0 +1,141 @@
+use serde::Serialize;
+
+use crate::{record::unquoted, DNSRecord, RecordType};
+
+/// How a zone differs from the records it should have, from
+/// [`compute_zone_diff`].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ZoneDiff {
+    /// Desired records missing from the zone, without ids
+    pub create: Vec<DNSRecord>,
+    /// Records of the zone that are not desired
+    pub delete: Vec<DNSRecord>,
+    /// Records of the zone that are desired as they are
+    pub unchanged: Vec<DNSRecord>,
+}
+
+impl ZoneDiff {
+    /// Whether the zone has exactly the desired records.
+    pub fn is_empty(&self) -> bool {
+        self.create.is_empty() && self.delete.is_empty()
+    }
+}
+
+/// Compares the records of a zone with the desired ones by hostname (case
+/// insensitive, `@` for the apex), type, destination and priority, for
+/// types that use one. Ids of either side don't matter.
+///
+/// Each current record satisfies at most one desired record, so a value
+/// desired once but present twice has its duplicate in
+/// [`delete`](ZoneDiff::delete). Repeated desired records count once.
+pub fn compute_zone_diff(current: &[DNSRecord], desired: &[DNSRecord]) -> ZoneDiff {
+    let mut remaining: Vec<&DNSRecord> = current.iter().collect();
+    let mut diff = ZoneDiff::default();
+
+    for (index, record) in desired.iter().enumerate() {
+        if desired[..index].iter().any(|r| same_record(r, record)) {
+            continue;
+        }
+
+        match remaining.iter().position(|r| same_record(r, record)) {
+            Some(position) => diff.unchanged.push(remaining.remove(position).clone()),
+            None => diff.create.push(DNSRecord {
+                id: None,
+                record_type: record.record_type.to_ascii_uppercase(),
+                deleterecord: None,
+                state: None,
+                ..record.clone()
+            }),
+        }
+    }
+
+    diff.delete = remaining.into_iter().cloned().collect();
+    diff
+}
+
+fn same_record(a: &DNSRecord, b: &DNSRecord) -> bool {
+    let same_destination = if a.record_type.eq_ignore_ascii_case(RecordType::Txt.as_str()) {
+        unquoted(&a.destination) == unquoted(&b.destination)
+    } else {
+        // Names may be given with or without the trailing dot
+        a.destination
+            .trim_end_matches('.')
+            .eq_ignore_ascii_case(b.destination.trim_end_matches('.'))
+    };
+    let uses_priority = a.record_type.parse().is_ok_and(RecordType::uses_priority);
+    let same_priority = !uses_priority
+        || a.priority.as_deref().map(str::trim) == b.priority.as_deref().map(str::trim);
+
+    hostname(a).eq_ignore_ascii_case(hostname(b))
+        && a.record_type.eq_ignore_ascii_case(&b.record_type)
+        && same_destination
+        && same_priority
+}
+
+fn hostname(record: &DNSRecord) -> &str {
+    match record.hostname.trim_end_matches('.') {
+        "" => "@",
+        hostname => hostname,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: &str, hostname: &str, record_type: &str, destination: &str) -> DNSRecord {
+        DNSRecord {
+            id: Some(id.into()),
+            ..DNSRecord::new(hostname, record_type, destination)
+        }
+    }
+
+    #[test]
+    fn hostnames_and_types_compare_case_insensitively() {
+        let current = [record("1", "WWW", "CNAME", "example.de.")];
+        let desired = [DNSRecord::new("www", "cname", "example.de")];
+
+        let diff = compute_zone_diff(&current, &desired);
+
+        assert!(diff.is_empty());
+        assert_eq!(diff.unchanged[0].id(), Some("1"));
+    }
+
+    #[test]
+    fn duplicate_in_the_zone_is_deleted() {
+        let current = [
+            record("1", "_acme-challenge", "TXT", "token"),
+            record("2", "_acme-challenge", "TXT", "\"token\""),
+        ];
+        let desired = [
+            DNSRecord::new("_acme-challenge", "TXT", "token"),
+            DNSRecord::new("_acme-challenge", "TXT", "token"),
+        ];
+
+        let diff = compute_zone_diff(&current, &desired);
+
+        assert!(diff.create.is_empty());
+        assert_eq!(diff.unchanged[0].id(), Some("1"));
+        assert_eq!(diff.delete[0].id(), Some("2"));
+    }
+
+    #[test]
+    fn priority_only_matters_where_it_is_used() {
+        let current = [
+            record("1", "@", "MX", "mail.example.de").with_priority("10"),
+            record("2", "@", "A", "192.0.2.1").with_priority("0"),
+        ];
+        let desired = [
+            DNSRecord::new("@", "MX", "mail.example.de").with_priority("20"),
+            DNSRecord::new("@", "A", "192.0.2.1"),
+        ];
+
+        let diff = compute_zone_diff(&current, &desired);
+
+        assert_eq!(diff.create.len(), 1);
+        assert_eq!(diff.create[0].priority(), Some("20"));
+        assert_eq!(diff.create[0].id(), None);
+        assert_eq!(diff.delete[0].id(), Some("1"));
+        assert_eq!(diff.unchanged[0].id(), Some("2"));
+    }
+}
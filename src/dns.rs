@@ -0,0 +1,297 @@
+//! A minimal DNS client, just enough to ask nameservers about a zone.
+//!
+//! Pulling in a full resolver would bring an async runtime along, while the
+//! propagation check only needs a handful of plain queries.
+
+use std::{
+    fs,
+    io::{Read, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, UdpSocket},
+    process,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Context};
+
+use crate::AddressFamily;
+
+pub(crate) const TYPE_A: u16 = 1;
+pub(crate) const TYPE_NS: u16 = 2;
+pub(crate) const TYPE_CNAME: u16 = 5;
+pub(crate) const TYPE_TXT: u16 = 16;
+pub(crate) const TYPE_AAAA: u16 = 28;
+
+const QUERY_TIMEOUT: Duration = Duration::from_secs(3);
+const ATTEMPTS: usize = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum RecordData {
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    Ns(String),
+    Cname(String),
+    /// The character-strings of the record, joined
+    Txt(String),
+    Other,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Answer {
+    pub(crate) rcode: u8,
+    pub(crate) records: Vec<(String, RecordData)>,
+}
+
+impl Answer {
+    /// Whether the server answered, even if the name doesn't exist.
+    pub(crate) fn is_authoritative_answer(&self) -> bool {
+        matches!(self.rcode, 0 | 3)
+    }
+}
+
+/// The first nameserver of `/etc/resolv.conf`, of `family` if given.
+pub(crate) fn system_nameserver(family: Option<AddressFamily>) -> anyhow::Result<IpAddr> {
+    let resolv =
+        fs::read_to_string("/etc/resolv.conf").context("Failed to read /etc/resolv.conf")?;
+
+    resolv
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("nameserver"))
+        .filter_map(|addr| addr.trim().split('%').next()?.parse().ok())
+        .find(|addr| family.is_none_or(|family| family.matches(*addr)))
+        .with_context(|| match family {
+            Some(family) => format!("No {} nameserver configured in /etc/resolv.conf", family),
+            None => "No nameserver configured in /etc/resolv.conf".into(),
+        })
+}
+
+/// Sends a single query, falling back to TCP if the answer was truncated.
+pub(crate) fn query(
+    server: IpAddr,
+    name: &str,
+    record_type: u16,
+    recursive: bool,
+) -> anyhow::Result<Answer> {
+    let server = SocketAddr::new(server, 53);
+    let id = query_id();
+    let message = encode_query(id, name, record_type, recursive)?;
+
+    let mut last_error = None;
+    for _ in 0..ATTEMPTS {
+        match query_udp(server, &message) {
+            Ok(response) => {
+                // Truncated, ask again over TCP
+                if response.len() > 2 && response[2] & 0x02 != 0 {
+                    let response = query_tcp(server, &message)?;
+                    return decode_response(id, &response);
+                }
+                return decode_response(id, &response);
+            }
+            Err(err) => last_error = Some(err),
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| anyhow::anyhow!("No attempts made"))).context(format!(
+        "Query for {} to {} failed",
+        name,
+        server.ip()
+    ))
+}
+
+fn query_udp(server: SocketAddr, message: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let bind: SocketAddr = match server {
+        SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    };
+
+    let socket = UdpSocket::bind(bind)?;
+    socket.set_read_timeout(Some(QUERY_TIMEOUT))?;
+    socket.connect(server)?;
+    socket.send(message)?;
+
+    let mut buf = vec![0; 4096];
+    let len = socket.recv(&mut buf)?;
+    buf.truncate(len);
+
+    Ok(buf)
+}
+
+fn query_tcp(server: SocketAddr, message: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut stream = TcpStream::connect_timeout(&server, QUERY_TIMEOUT)?;
+    stream.set_read_timeout(Some(QUERY_TIMEOUT))?;
+
+    stream.write_all(&(message.len() as u16).to_be_bytes())?;
+    stream.write_all(message)?;
+
+    let mut len = [0; 2];
+    stream.read_exact(&mut len)?;
+    let mut buf = vec![0; u16::from_be_bytes(len) as usize];
+    stream.read_exact(&mut buf)?;
+
+    Ok(buf)
+}
+
+fn query_id() -> u16 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or_default();
+
+    (nanos ^ process::id()) as u16
+}
+
+fn encode_query(id: u16, name: &str, record_type: u16, recursive: bool) -> anyhow::Result<Vec<u8>> {
+    let mut message = Vec::with_capacity(512);
+
+    message.extend_from_slice(&id.to_be_bytes());
+    message.extend_from_slice(&(if recursive { 0x0100u16 } else { 0 }).to_be_bytes());
+    // One question, no other sections
+    message.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            bail!("Invalid domain name {}", name);
+        }
+        message.push(label.len() as u8);
+        message.extend_from_slice(label.as_bytes());
+    }
+    message.push(0);
+
+    message.extend_from_slice(&record_type.to_be_bytes());
+    // Class IN
+    message.extend_from_slice(&1u16.to_be_bytes());
+
+    Ok(message)
+}
+
+fn decode_response(id: u16, message: &[u8]) -> anyhow::Result<Answer> {
+    let mut reader = Reader { message, pos: 0 };
+
+    if reader.u16()? != id {
+        bail!("DNS response has a mismatched id");
+    }
+    let flags = reader.u16()?;
+    let questions = reader.u16()?;
+    let answers = reader.u16()?;
+    let authorities = reader.u16()?;
+    let additionals = reader.u16()?;
+
+    for _ in 0..questions {
+        reader.name()?;
+        reader.skip(4)?;
+    }
+
+    // Referrals carry the NS records in the authority section
+    let mut records = Vec::new();
+    for _ in 0..(answers as usize + authorities as usize + additionals as usize) {
+        let name = reader.name()?;
+        let record_type = reader.u16()?;
+        reader.skip(6)?;
+        let len = reader.u16()? as usize;
+        let end = reader.pos + len;
+        if end > message.len() {
+            bail!("DNS response is truncated");
+        }
+
+        let data = match record_type {
+            TYPE_A if len == 4 => RecordData::A(Ipv4Addr::new(
+                message[reader.pos],
+                message[reader.pos + 1],
+                message[reader.pos + 2],
+                message[reader.pos + 3],
+            )),
+            TYPE_AAAA if len == 16 => {
+                let mut octets = [0; 16];
+                octets.copy_from_slice(&message[reader.pos..end]);
+                RecordData::Aaaa(octets.into())
+            }
+            TYPE_NS => RecordData::Ns(reader.clone().name()?),
+            TYPE_CNAME => RecordData::Cname(reader.clone().name()?),
+            TYPE_TXT => {
+                let mut text = Vec::new();
+                let mut pos = reader.pos;
+                while pos < end {
+                    let chunk = message[pos] as usize;
+                    text.extend_from_slice(
+                        message
+                            .get(pos + 1..pos + 1 + chunk)
+                            .context("Malformed TXT record")?,
+                    );
+                    pos += 1 + chunk;
+                }
+                RecordData::Txt(String::from_utf8_lossy(&text).into_owned())
+            }
+            _ => RecordData::Other,
+        };
+
+        reader.pos = end;
+        records.push((name, data));
+    }
+
+    Ok(Answer {
+        rcode: (flags & 0x000f) as u8,
+        records,
+    })
+}
+
+#[derive(Clone)]
+struct Reader<'a> {
+    message: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn skip(&mut self, len: usize) -> anyhow::Result<()> {
+        if self.pos + len > self.message.len() {
+            bail!("DNS response is truncated");
+        }
+        self.pos += len;
+        Ok(())
+    }
+
+    fn u16(&mut self) -> anyhow::Result<u16> {
+        let bytes = self
+            .message
+            .get(self.pos..self.pos + 2)
+            .context("DNS response is truncated")?;
+        self.pos += 2;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// Reads a possibly compressed name.
+    fn name(&mut self) -> anyhow::Result<String> {
+        let mut labels = Vec::new();
+        let mut pos = self.pos;
+        let mut jumped = false;
+
+        // Bounds the number of pointers followed, so loops can't hang us
+        for _ in 0..128 {
+            let len = *self.message.get(pos).context("DNS response is truncated")? as usize;
+
+            if len & 0xc0 == 0xc0 {
+                let low = *self
+                    .message
+                    .get(pos + 1)
+                    .context("DNS response is truncated")? as usize;
+                if !jumped {
+                    self.pos = pos + 2;
+                    jumped = true;
+                }
+                pos = ((len & 0x3f) << 8) | low;
+            } else if len == 0 {
+                if !jumped {
+                    self.pos = pos + 1;
+                }
+                return Ok(labels.join("."));
+            } else {
+                let label = self
+                    .message
+                    .get(pos + 1..pos + 1 + len)
+                    .context("DNS response is truncated")?;
+                labels.push(String::from_utf8_lossy(label).to_lowercase());
+                pos += 1 + len;
+            }
+        }
+
+        bail!("DNS response contains a compression loop")
+    }
+}
@@ -0,0 +1,333 @@
+use std::{fmt, time::Duration};
+
+use crate::DNSRecord;
+
+/// Errors callers may want to handle specifically.
+///
+/// The client's methods return [`anyhow::Result`]; these can be retrieved
+/// with [`anyhow::Error::downcast_ref`].
+#[derive(Debug)]
+pub enum NetcupError {
+    /// Netcup rejected the credentials or the session
+    AuthenticationFailed {
+        status_code: u32,
+        message: String,
+        server_request_id: String,
+        client_request_id: String,
+    },
+    /// Too many requests, retry later
+    RateLimited {
+        status_code: u32,
+        message: String,
+        server_request_id: String,
+        client_request_id: String,
+    },
+    /// The zone is not managed by this account
+    DomainNotFound {
+        domain: String,
+        status_code: u32,
+        message: String,
+        server_request_id: String,
+        client_request_id: String,
+    },
+    /// Netcup refused the request as invalid
+    Validation {
+        status_code: u32,
+        message: String,
+        server_request_id: String,
+        client_request_id: String,
+    },
+    /// Any other error reported by the API
+    Api {
+        status_code: u32,
+        message: String,
+        server_request_id: String,
+        client_request_id: String,
+    },
+    /// No record with this id exists in the zone
+    RecordNotFound { domain: String, id: String },
+    /// None of the TXT records at this name has these values
+    TxtRecordNotFound {
+        domain: String,
+        hostname: String,
+        contents: Vec<String>,
+    },
+    /// Several TXT records at this name have the value, e.g. a stale
+    /// duplicate, so picking one by value would be a guess
+    AmbiguousRecord {
+        domain: String,
+        hostname: String,
+        content: String,
+        ids: Vec<String>,
+    },
+    /// The zone is outside the configured allowlist
+    DomainNotAllowed { domain: String },
+    /// The configuration or the credentials are missing or invalid, before
+    /// any request was made. Only the command line raises it.
+    Config { message: String },
+    /// These nameservers didn't serve the expected record in time
+    PropagationTimeout {
+        name: String,
+        nameservers: Vec<String>,
+        /// Those of `nameservers` that couldn't be queried
+        unreachable: Vec<String>,
+    },
+    /// Netcup still reported these records as not published (a `state`
+    /// other than `yes`) when the wait for them timed out
+    ConfirmationTimeout {
+        domain: String,
+        pending: Vec<DNSRecord>,
+    },
+    /// A wait was cancelled
+    Interrupted,
+    /// Too many consecutive failures, requests are refused until the
+    /// cool-down has passed
+    CircuitOpen { retry_after: Duration },
+    /// The request didn't get through, or the API answered with an HTTP
+    /// error. Attached as context, the underlying error is its cause.
+    Transport { message: String },
+    /// The API didn't answer within the timeout given to the builder. Retried
+    /// like other transport errors.
+    Timeout { message: String },
+    /// No connection to the API could be established, so the request never
+    /// reached it. Retried even for changes, unlike other transport errors.
+    Connect { message: String },
+    /// The API answered with something that isn't a response we understand
+    UnexpectedResponse { message: String },
+    /// The response echoed another `clientrequestid` than the request
+    /// carried, so it may answer a different request
+    ClientRequestIdMismatch { sent: String, received: String },
+    /// A change split into several calls failed part way: `applied` are in
+    /// the zone, `pending` (starting with the failed call) are not
+    PartiallyApplied {
+        applied: Vec<DNSRecord>,
+        pending: Vec<DNSRecord>,
+    },
+}
+
+impl NetcupError {
+    /// A stable identifier for scripts, unlike the message.
+    pub fn code(&self) -> &'static str {
+        match self {
+            NetcupError::AuthenticationFailed { .. } => "auth_failed",
+            NetcupError::RateLimited { .. } => "rate_limited",
+            NetcupError::DomainNotFound { .. } => "zone_not_in_account",
+            NetcupError::Validation { .. } => "validation_error",
+            NetcupError::Api { .. } => "api_error",
+            NetcupError::RecordNotFound { .. } | NetcupError::TxtRecordNotFound { .. } => {
+                "record_not_found"
+            }
+            NetcupError::AmbiguousRecord { .. } => "ambiguous_record",
+            NetcupError::DomainNotAllowed { .. } => "domain_not_allowed",
+            NetcupError::Config { .. } => "config_error",
+            NetcupError::PropagationTimeout { .. } => "propagation_timeout",
+            NetcupError::ConfirmationTimeout { .. } => "confirmation_timeout",
+            NetcupError::Interrupted => "interrupted",
+            NetcupError::CircuitOpen { .. } => "circuit_open",
+            NetcupError::Transport { .. } => "transport_error",
+            NetcupError::Timeout { .. } => "timeout",
+            NetcupError::Connect { .. } => "connect_error",
+            NetcupError::UnexpectedResponse { .. } => "unexpected_response",
+            NetcupError::ClientRequestIdMismatch { .. } => "client_request_id_mismatch",
+            NetcupError::PartiallyApplied { .. } => "partially_applied",
+        }
+    }
+
+    /// The `statuscode` of the response, for errors reported by the API.
+    pub fn status_code(&self) -> Option<u32> {
+        match self {
+            NetcupError::AuthenticationFailed { status_code, .. }
+            | NetcupError::RateLimited { status_code, .. }
+            | NetcupError::DomainNotFound { status_code, .. }
+            | NetcupError::Validation { status_code, .. }
+            | NetcupError::Api { status_code, .. } => Some(*status_code),
+            _ => None,
+        }
+    }
+
+    /// The `serverrequestid` of the response, for errors reported by the API.
+    pub fn server_request_id(&self) -> Option<&str> {
+        match self {
+            NetcupError::AuthenticationFailed {
+                server_request_id, ..
+            }
+            | NetcupError::RateLimited {
+                server_request_id, ..
+            }
+            | NetcupError::DomainNotFound {
+                server_request_id, ..
+            }
+            | NetcupError::Validation {
+                server_request_id, ..
+            }
+            | NetcupError::Api {
+                server_request_id, ..
+            } => Some(server_request_id),
+            _ => None,
+        }
+    }
+
+    /// The `clientrequestid` the request was sent with, for errors reported
+    /// by the API.
+    pub fn client_request_id(&self) -> Option<&str> {
+        match self {
+            NetcupError::AuthenticationFailed {
+                client_request_id, ..
+            }
+            | NetcupError::RateLimited {
+                client_request_id, ..
+            }
+            | NetcupError::DomainNotFound {
+                client_request_id, ..
+            }
+            | NetcupError::Validation {
+                client_request_id, ..
+            }
+            | NetcupError::Api {
+                client_request_id, ..
+            } => Some(client_request_id),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for NetcupError {
+    /// Errors reported by the API end with the `serverrequestid`, which
+    /// netcup's support asks for, and the `clientrequestid` of the log.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetcupError::AuthenticationFailed {
+                status_code,
+                message,
+                ..
+            } => write!(f, "Authentication failed ({}): {}", status_code, message),
+            NetcupError::RateLimited {
+                status_code,
+                message,
+                ..
+            } => write!(f, "Rate limited ({}): {}", status_code, message),
+            NetcupError::DomainNotFound {
+                domain,
+                status_code,
+                message,
+                ..
+            } => write!(
+                f,
+                "{} is not managed by this account ({}): {}",
+                domain, status_code, message
+            ),
+            NetcupError::Validation {
+                status_code,
+                message,
+                ..
+            } => write!(f, "Request rejected ({}): {}", status_code, message),
+            NetcupError::Api {
+                status_code,
+                message,
+                ..
+            } => write!(f, "API error ({}): {}", status_code, message),
+            NetcupError::RecordNotFound { domain, id } => {
+                write!(f, "Record {} not found in {}", id, domain)
+            }
+            NetcupError::TxtRecordNotFound {
+                domain,
+                hostname,
+                contents,
+            } => write!(
+                f,
+                "Could not find record {} TXT {} in {}!",
+                hostname,
+                contents.join(", "),
+                domain
+            ),
+            NetcupError::AmbiguousRecord {
+                domain,
+                hostname,
+                content,
+                ids,
+            } => write!(
+                f,
+                "{} TXT {} in {} matches several records: {}",
+                hostname,
+                content,
+                domain,
+                ids.join(", ")
+            ),
+            NetcupError::DomainNotAllowed { domain } => {
+                write!(f, "{} is not in the allowed domains", domain)
+            }
+            NetcupError::PropagationTimeout {
+                name,
+                nameservers,
+                unreachable,
+            } => {
+                write!(
+                    f,
+                    "Timed out waiting for {} on {}",
+                    name,
+                    nameservers.join(", ")
+                )?;
+                if !unreachable.is_empty() {
+                    write!(f, " ({} could not be queried)", unreachable.join(", "))?;
+                }
+                Ok(())
+            }
+            NetcupError::ConfirmationTimeout { domain, pending } => {
+                let pending: Vec<_> = pending
+                    .iter()
+                    .map(|record| match record.state() {
+                        Some(state) => format!("{} is still {}", record, state),
+                        None => format!("{} has no state", record),
+                    })
+                    .collect();
+                write!(
+                    f,
+                    "Timed out waiting for netcup to publish the records in {}: {}",
+                    domain,
+                    pending.join(", ")
+                )
+            }
+            NetcupError::Interrupted => write!(f, "Interrupted"),
+            NetcupError::CircuitOpen { retry_after } => write!(
+                f,
+                "Too many consecutive API failures, not retrying for {}s",
+                retry_after.as_secs()
+            ),
+            NetcupError::Config { message }
+            | NetcupError::Transport { message }
+            | NetcupError::Timeout { message }
+            | NetcupError::Connect { message } => {
+                write!(f, "{}", message)
+            }
+            NetcupError::UnexpectedResponse { message } => {
+                write!(f, "Unexpected API response: {}", message)
+            }
+            NetcupError::ClientRequestIdMismatch { sent, received } => write!(
+                f,
+                "The response is for client request id '{}', not '{}'",
+                received, sent
+            ),
+            NetcupError::PartiallyApplied { applied, pending } => write!(
+                f,
+                "Only {} of {} records were applied, the rest failed",
+                applied.len(),
+                applied.len() + pending.len()
+            ),
+        }?;
+
+        let server = self.server_request_id().filter(|id| !id.is_empty());
+        let client = self.client_request_id().filter(|id| !id.is_empty());
+        match (server, client) {
+            (Some(server), Some(client)) => write!(
+                f,
+                " (server request id {}, client request id {})",
+                server, client
+            ),
+            (Some(server), None) => write!(f, " (server request id {})", server),
+            (None, Some(client)) => write!(f, " (client request id {})", client),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+impl std::error::Error for NetcupError {}
@@ -0,0 +1,522 @@
+use std::{
+    collections::hash_map::RandomState,
+    fmt,
+    hash::{BuildHasher, Hasher},
+    sync::atomic::{AtomicU64, Ordering},
+    thread,
+};
+
+use anyhow::Context;
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+use crate::{
+    ratelimit::{RateLimiter, SharedRateLimit},
+    redact::{excerpt, redacted, redacted_body},
+    retry::{CircuitBreaker, FailureClass, RetryPolicy},
+    transport::NetcupTransport,
+    AddressFamily, NetcupError, ResponseMessage,
+};
+
+/// Sends payloads to the netcup endpoint. Every request of a client goes
+/// through here, so headers, rate limiting and retries apply uniformly.
+#[derive(Debug)]
+pub(crate) struct Http {
+    pub(crate) transport: Box<dyn NetcupTransport>,
+    pub(crate) endpoint: String,
+    pub(crate) family: Option<AddressFamily>,
+    pub(crate) rate_limiter: RateLimiter,
+    pub(crate) shared_rate_limit: Option<SharedRateLimit>,
+    pub(crate) retry_policy: RetryPolicy,
+    pub(crate) circuit_breaker: CircuitBreaker,
+    pub(crate) request_ids: RequestIds,
+}
+
+impl Http {
+    /// Posts `payload` once and returns the raw response body.
+    pub(crate) fn send(&self, payload: &impl Serialize) -> anyhow::Result<String> {
+        let call = self.prepare(payload)?;
+        let response = self.send_once(&call.body).map_err(Failure::into_error)?;
+        log::debug!(
+            action = call.action.as_str(),
+            domain = call.domain.as_str(),
+            client_request_id = call.client_request_id.as_str();
+            "Received {}", redacted_body(&response)
+        );
+        Ok(response)
+    }
+
+    /// Serializes `payload` with a new `clientrequestid`, which stays the
+    /// same across the retries of the call.
+    fn prepare(&self, payload: &impl Serialize) -> anyhow::Result<Call> {
+        let mut payload = serde_json::to_value(payload)?;
+        let field = |value: &Value| value.as_str().unwrap_or_default().to_string();
+        let action = field(&payload["action"]);
+        let domain = field(&payload["param"]["domainname"]);
+        let client_request_id = self.request_ids.next(&action);
+        let idempotent = idempotent(&payload);
+
+        if let Some(param) = payload.get_mut("param").and_then(Value::as_object_mut) {
+            param.insert("clientrequestid".into(), client_request_id.clone().into());
+        }
+
+        log::debug!(
+            action = action.as_str(),
+            domain = domain.as_str(),
+            client_request_id = client_request_id.as_str();
+            "Sending {}", redacted(&payload)
+        );
+
+        Ok(Call {
+            body: payload.to_string(),
+            action,
+            domain,
+            client_request_id,
+            idempotent,
+        })
+    }
+
+    fn send_once(&self, body: &str) -> Result<String, Failure> {
+        self.rate_limiter.wait();
+        if let Some(shared) = &self.shared_rate_limit {
+            shared.acquire().map_err(Failure::NotSent)?;
+        }
+
+        let response = self
+            .transport
+            .post(&self.endpoint, body.to_string())
+            .map_err(|err| {
+                // Already typed by the transport, which knows its timeouts
+                if let Some(NetcupError::Timeout { .. } | NetcupError::Connect { .. }) =
+                    err.downcast_ref::<NetcupError>()
+                {
+                    return Failure::Transient(FailureClass::Transport, err);
+                }
+
+                let message = match self.family {
+                    Some(family) => format!("Request to the API over {} failed", family),
+                    None => "Request to the API failed".into(),
+                };
+                Failure::Transient(
+                    FailureClass::Transport,
+                    err.context(NetcupError::Transport { message }),
+                )
+            })?;
+
+        // A proxy in front of the API may throttle with a plain 429
+        if response.status == 429 {
+            return Err(Failure::Transient(
+                FailureClass::RateLimited,
+                NetcupError::Transport {
+                    message: "The API responded with HTTP 429".into(),
+                }
+                .into(),
+            ));
+        }
+
+        if (500..600).contains(&response.status) {
+            return Err(Failure::Transient(
+                FailureClass::ServerError,
+                NetcupError::Transport {
+                    message: format!("The API responded with HTTP {}", response.status),
+                }
+                .into(),
+            ));
+        }
+
+        Ok(response.body)
+    }
+
+    /// Posts `payload` and parses the response, retrying transient failures
+    /// according to the retry policy.
+    pub(crate) fn call<D: DeserializeOwned>(
+        &self,
+        payload: &impl Serialize,
+    ) -> anyhow::Result<ResponseMessage<D>> {
+        let call = self.prepare(payload)?;
+        let mut attempt = 1;
+
+        loop {
+            self.circuit_breaker.before_attempt()?;
+
+            // Every attempt that was sent decides about the breaker, or a
+            // failed half-open probe would keep it from ever closing
+            let (class, err) = match self.call_once(&call) {
+                Ok(response) => {
+                    self.circuit_breaker.success();
+                    return Ok(response);
+                }
+                Err(Failure::NotSent(err)) => {
+                    self.circuit_breaker.release();
+                    return Err(err);
+                }
+                Err(Failure::Answered(err)) => {
+                    self.circuit_breaker.success();
+                    return Err(err);
+                }
+                Err(Failure::Transient(class, err)) => (class, err),
+            };
+
+            self.circuit_breaker.failure(class);
+
+            if attempt >= self.retry_policy.max_attempts {
+                return Err(err);
+            }
+
+            // A change that may have been applied would be applied twice
+            if !call.idempotent && !unapplied(&err) {
+                log::warn!(
+                    action = call.action.as_str(),
+                    domain = call.domain.as_str(),
+                    client_request_id = call.client_request_id.as_str();
+                    "Not sending {} again, it may have been applied: {:#}", call.action, err
+                );
+                return Err(err);
+            }
+
+            let delay = self.retry_policy.delay(attempt);
+            log::info!(
+                action = call.action.as_str(),
+                domain = call.domain.as_str(),
+                client_request_id = call.client_request_id.as_str(),
+                attempt;
+                "{} failed, retrying in {:.1?}: {:#}", call.action, delay, err
+            );
+            thread::sleep(delay);
+            attempt += 1;
+        }
+    }
+
+    fn call_once<D: DeserializeOwned>(&self, call: &Call) -> Result<ResponseMessage<D>, Failure> {
+        let body = self.send_once(&call.body)?;
+
+        let response: ResponseMessage<D> = serde_json::from_str(&body)
+            .context(NetcupError::UnexpectedResponse {
+                message: format!("Could not parse the API response ({})", excerpt(&body)),
+            })
+            .map_err(|err| {
+                log::debug!(
+                    action = call.action.as_str(),
+                    domain = call.domain.as_str(),
+                    client_request_id = call.client_request_id.as_str();
+                    "Could not parse {}", redacted_body(&body)
+                );
+                Failure::Answered(err)
+            })?;
+
+        log::debug!(
+            action = call.action.as_str(),
+            domain = call.domain.as_str(),
+            client_request_id = call.client_request_id.as_str(),
+            status = String::from(response.status.clone()).as_str(),
+            status_code = response.status_code,
+            server_request_id = response.server_request_id.as_str();
+            "Received {}", redacted_body(&body)
+        );
+
+        // An echo of another request's id means the answer isn't ours. Some
+        // errors come without one, which says nothing either way.
+        if let Some(received) = response
+            .client_request_id
+            .as_ref()
+            .filter(|id| !id.is_empty())
+        {
+            if *received != call.client_request_id {
+                return Err(Failure::Answered(
+                    NetcupError::ClientRequestIdMismatch {
+                        sent: call.client_request_id.clone(),
+                        received: received.clone(),
+                    }
+                    .into(),
+                ));
+            }
+        }
+
+        // Other API errors are for the caller to interpret
+        match response.error(None) {
+            Some(err @ NetcupError::RateLimited { .. }) => {
+                Err(Failure::Transient(FailureClass::RateLimited, err.into()))
+            }
+            _ => Ok(response),
+        }
+    }
+}
+
+/// How an attempt failed, which decides whether it is retried and what the
+/// circuit breaker makes of it.
+enum Failure {
+    /// Nothing was sent, e.g. the shared rate limit's state file is unusable
+    NotSent(anyhow::Error),
+    /// The API answered, but with something the call can't use, like a
+    /// malformed body; it is up, but retrying won't help
+    Answered(anyhow::Error),
+    /// Retried according to the policy, counted by the breaker
+    Transient(FailureClass, anyhow::Error),
+}
+
+impl Failure {
+    fn into_error(self) -> anyhow::Error {
+        match self {
+            Failure::NotSent(err) | Failure::Answered(err) | Failure::Transient(_, err) => err,
+        }
+    }
+}
+
+/// A serialized payload, with what its log lines are tagged with.
+struct Call {
+    body: String,
+    action: String,
+    domain: String,
+    client_request_id: String,
+    /// Whether sending it twice does no more than sending it once
+    idempotent: bool,
+}
+
+/// Whether `payload` can be sent again without changing more than once.
+/// Updates and deletions of records name them by id, but a record without
+/// one is created anew every time.
+fn idempotent(payload: &Value) -> bool {
+    payload["param"]["dnsrecordset"]["dnsrecords"]
+        .as_array()
+        .is_none_or(|records| {
+            records
+                .iter()
+                .all(|record| record.get("id").is_some() || record["deleterecord"] == true)
+        })
+}
+
+/// Whether the failed attempt provably changed nothing: it never reached
+/// the API, or netcup refused it for the rate limit.
+fn unapplied(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<NetcupError>(),
+        Some(NetcupError::Connect { .. } | NetcupError::RateLimited { .. })
+    )
+}
+
+/// Makes the `clientrequestid` of each call from its action.
+pub(crate) struct RequestIds(pub(crate) Box<dyn Fn(&str) -> String + Send + Sync>);
+
+impl RequestIds {
+    fn next(&self, action: &str) -> String {
+        (self.0)(action)
+    }
+}
+
+impl Default for RequestIds {
+    /// 16 random hex digits and the action, e.g. `3f9c0a17d2b4e865-login`.
+    fn default() -> Self {
+        Self(Box::new(|action| {
+            static COUNTER: AtomicU64 = AtomicU64::new(0);
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+            format!("{:016x}-{}", hasher.finish(), action)
+        }))
+    }
+}
+
+impl fmt::Debug for RequestIds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RequestIds")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use crate::{
+        testing::{self, txt},
+        CircuitState, DNSRecord, NetcupError, RecordType, RetryPolicy,
+    };
+
+    fn retrying() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+        }
+    }
+
+    #[test]
+    fn retries_transient_failures_until_success() {
+        let mock = testing::MockTransport::new();
+        let client = testing::login(&mock, testing::builder().retry_policy(retrying()));
+        mock.respond_raw(503, "")
+            .fail("connection reset")
+            .respond_success("infoDnsRecords", json!({ "dnsrecords": [txt(1, "a")] }));
+
+        assert_eq!(client.list_records("example.com").unwrap().len(), 1);
+        assert_eq!(
+            mock.actions(),
+            [
+                "login",
+                "infoDnsRecords",
+                "infoDnsRecords",
+                "infoDnsRecords"
+            ]
+        );
+
+        // The same clientrequestid across the retries
+        let ids: Vec<_> = mock.requests()[1..]
+            .iter()
+            .map(|r| r["param"]["clientrequestid"].clone())
+            .collect();
+        assert!(ids.iter().all(|id| *id == ids[0]));
+    }
+
+    #[test]
+    fn retries_netcups_rate_limit() {
+        let mock = testing::MockTransport::new();
+        let client = testing::login(&mock, testing::builder().retry_policy(retrying()));
+        mock.respond_error("infoDnsRecords", 4013, "more than 180 requests per minute")
+            .respond_success("infoDnsRecords", json!({ "dnsrecords": [] }));
+
+        assert!(client.list_records("example.com").is_ok());
+        assert_eq!(mock.remaining(), 0);
+    }
+
+    #[test]
+    fn gives_up_after_max_attempts() {
+        let mock = testing::MockTransport::new();
+        let client = testing::login(&mock, testing::builder().retry_policy(retrying()));
+        mock.respond_raw(500, "")
+            .respond_raw(502, "")
+            .respond_raw(503, "");
+
+        let err = client.list_records("example.com").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<NetcupError>(),
+            Some(NetcupError::Transport { .. })
+        ));
+        assert_eq!(mock.requests().len(), 4);
+    }
+
+    #[test]
+    fn doesnt_retry_api_errors() {
+        let mock = testing::MockTransport::new();
+        let client = testing::login(&mock, testing::builder().retry_policy(retrying()));
+        mock.respond_error("infoDnsRecords", 5029, "Domain not found");
+
+        let err = client.list_records("example.com").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<NetcupError>(),
+            Some(NetcupError::DomainNotFound { .. })
+        ));
+        assert_eq!(mock.requests().len(), 2);
+    }
+
+    #[test]
+    fn doesnt_resend_an_addition_that_may_have_been_applied() {
+        let mock = testing::MockTransport::new();
+        let client = testing::login(&mock, testing::builder().retry_policy(retrying()));
+        mock.fail("operation timed out");
+
+        let records = [DNSRecord::new("_acme-challenge", RecordType::Txt, "a")];
+        assert!(client
+            .update_records("example.com", records.to_vec())
+            .is_err());
+        assert_eq!(mock.actions(), ["login", "updateDnsRecords"]);
+
+        mock.respond_raw(502, "");
+        assert!(client
+            .update_records("example.com", records.to_vec())
+            .is_err());
+        assert_eq!(mock.requests().len(), 3);
+    }
+
+    #[test]
+    fn resends_an_addition_that_never_arrived() {
+        let mock = testing::MockTransport::new();
+        let client = testing::login(&mock, testing::builder().retry_policy(retrying()));
+        mock.fail_to_connect("Could not connect to the API")
+            .respond_error(
+                "updateDnsRecords",
+                4013,
+                "more than 180 requests per minute",
+            )
+            .respond_success("updateDnsRecords", json!({ "dnsrecords": [txt(1, "a")] }));
+
+        let records = vec![DNSRecord::new("_acme-challenge", RecordType::Txt, "a")];
+        assert!(client.update_records("example.com", records).is_ok());
+        assert_eq!(mock.remaining(), 0);
+    }
+
+    #[test]
+    fn resends_updates_and_deletions() {
+        let mock = testing::MockTransport::new();
+        let client = testing::login(&mock, testing::builder().retry_policy(retrying()));
+        mock.fail("operation timed out")
+            .respond_success("updateDnsRecords", json!({ "dnsrecords": [txt(1, "b")] }));
+
+        let deletion = serde_json::from_value::<DNSRecord>(txt(2, "a"))
+            .unwrap()
+            .into_deletion();
+        let update = serde_json::from_value::<DNSRecord>(txt(1, "b")).unwrap();
+        assert!(client
+            .update_records("example.com", vec![update, deletion])
+            .is_ok());
+        assert_eq!(mock.remaining(), 0);
+    }
+
+    #[test]
+    fn malformed_probe_response_closes_the_breaker() {
+        let mock = testing::MockTransport::new();
+        let client = testing::login(&mock, testing::builder().circuit_breaker(1, Duration::ZERO));
+
+        mock.fail("connection reset");
+        assert!(client.list_records("example.com").is_err());
+        assert!(matches!(client.circuit_state(), CircuitState::Open { .. }));
+
+        // The probe gets an answer, just not one that parses
+        mock.respond_raw(200, "<html>Maintenance</html>");
+        let err = client.list_records("example.com").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<NetcupError>(),
+            Some(NetcupError::UnexpectedResponse { .. })
+        ));
+        assert_eq!(
+            client.circuit_state(),
+            CircuitState::Closed {
+                consecutive_failures: 0
+            }
+        );
+
+        mock.respond_success("infoDnsRecords", serde_json::json!({ "dnsrecords": [] }));
+        assert!(client.list_records("example.com").unwrap().is_empty());
+    }
+
+    #[test]
+    fn mismatched_request_id_probe_closes_the_breaker() {
+        let mock = testing::MockTransport::new();
+        let client = testing::login(&mock, testing::builder().circuit_breaker(1, Duration::ZERO));
+
+        mock.respond_raw(503, "");
+        assert!(client.list_records("example.com").is_err());
+
+        mock.respond_raw(
+            200,
+            serde_json::json!({
+                "serverrequestid": "mock",
+                "clientrequestid": "someone-else",
+                "action": "infoDnsRecords",
+                "status": "success",
+                "statuscode": 2000,
+                "shortmessage": "",
+                "responsedata": { "dnsrecords": [] },
+            })
+            .to_string(),
+        );
+        let err = client.list_records("example.com").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<NetcupError>(),
+            Some(NetcupError::ClientRequestIdMismatch { .. })
+        ));
+        assert!(matches!(
+            client.circuit_state(),
+            CircuitState::Closed { .. }
+        ));
+    }
+}
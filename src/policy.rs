@@ -0,0 +1,144 @@
+use crate::DNSRecord;
+
+/// Decides which records a destructive operation may remove.
+///
+/// Every batch deletion in the library goes through a policy, so all
+/// frontends get the same protection against deleting the zone's
+/// delegation or mail routing by accident.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DeletionPolicy {
+    /// Skip NS, SOA and apex MX records (the default)
+    #[default]
+    ProtectCritical,
+    /// Delete whatever was requested, including critical records
+    AllowCritical,
+}
+
+impl DeletionPolicy {
+    pub fn from_allow_critical(allow_critical: bool) -> Self {
+        if allow_critical {
+            Self::AllowCritical
+        } else {
+            Self::ProtectCritical
+        }
+    }
+
+    /// NS and SOA records anywhere, and MX records at the zone apex.
+    pub fn is_critical(record: &DNSRecord) -> bool {
+        let apex = record.hostname.is_empty() || record.hostname == "@";
+
+        match record.record_type.to_ascii_uppercase().as_str() {
+            "NS" | "SOA" => true,
+            "MX" => apex,
+            _ => false,
+        }
+    }
+
+    pub fn allows(&self, record: &DNSRecord) -> bool {
+        *self == Self::AllowCritical || !Self::is_critical(record)
+    }
+
+    /// Splits `records` into the ones that may be deleted and the ones that
+    /// have to be skipped.
+    pub fn partition(&self, records: Vec<DNSRecord>) -> (Vec<DNSRecord>, Vec<DNSRecord>) {
+        records.into_iter().partition(|record| self.allows(record))
+    }
+}
+
+/// Result of a batch deletion.
+#[derive(Debug, Default)]
+pub struct DeletionOutcome {
+    pub deleted: Vec<DNSRecord>,
+    /// Critical records the policy refused to delete
+    pub skipped: Vec<DNSRecord>,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::testing::{self, txt, MockTransport};
+
+    fn record(id: u32, hostname: &str, record_type: &str) -> Value {
+        json!({
+            "id": id.to_string(),
+            "hostname": hostname,
+            "type": record_type,
+            "priority": "10",
+            "destination": "ns1.example.de",
+        })
+    }
+
+    /// The zone a prune would empty: delegation, apex and subdomain mail
+    /// routing and a challenge.
+    fn zone() -> Value {
+        json!([
+            record(1, "@", "NS"),
+            record(2, "@", "soa"),
+            record(3, "@", "MX"),
+            record(4, "mail", "MX"),
+            record(5, "sub", "NS"),
+            txt(6, "token"),
+        ])
+    }
+
+    /// The ids `policy` lets a prune of the whole zone delete, and the ids
+    /// it sent to netcup.
+    fn prune(policy: DeletionPolicy) -> (Vec<String>, Vec<String>) {
+        let mock = MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_success("infoDnsRecords", json!({ "dnsrecords": zone() }))
+            .respond_success("updateDnsRecords", json!({ "dnsrecords": [] }));
+
+        let records = client.list_records("example.de").unwrap();
+        let outcome = client
+            .delete_records("example.de", records, policy)
+            .unwrap();
+
+        let sent = mock
+            .requests()
+            .iter()
+            .filter(|request| request["action"] == "updateDnsRecords")
+            .flat_map(|request| {
+                request["param"]["dnsrecordset"]["dnsrecords"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|record| record["id"].as_str().unwrap().to_string())
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        let skipped = outcome
+            .skipped
+            .iter()
+            .map(|record| record.id().unwrap().to_string())
+            .collect();
+        (skipped, sent)
+    }
+
+    #[test]
+    fn prune_skips_delegation_and_apex_mail_by_default() {
+        let (skipped, sent) = prune(DeletionPolicy::default());
+
+        assert_eq!(skipped, ["1", "2", "3", "5"]);
+        assert_eq!(sent, ["4", "6"]);
+    }
+
+    #[test]
+    fn allow_critical_prunes_everything() {
+        let (skipped, sent) = prune(DeletionPolicy::from_allow_critical(true));
+
+        assert!(skipped.is_empty());
+        assert_eq!(sent, ["1", "2", "3", "4", "5", "6"]);
+    }
+
+    #[test]
+    fn apex_is_the_empty_name_or_at() {
+        let mx = |hostname| DNSRecord::new(hostname, crate::RecordType::Mx, "mail.example.de");
+
+        assert!(DeletionPolicy::is_critical(&mx("")));
+        assert!(DeletionPolicy::is_critical(&mx("@")));
+        assert!(!DeletionPolicy::is_critical(&mx("www")));
+    }
+}
@@ -0,0 +1,648 @@
+use std::{
+    fmt,
+    net::IpAddr,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context};
+
+use crate::{
+    dns::{self, RecordData},
+    normalize_dns_name, AddressFamily, NetcupError,
+};
+
+/// Answers the questions the propagation check asks, so it can be pointed at
+/// something other than the network.
+pub trait Resolver {
+    /// The authoritative nameservers of `zone`, by name and address.
+    fn nameservers(&self, zone: &str) -> anyhow::Result<Vec<(String, IpAddr)>>;
+
+    /// The TXT values `server` serves for `name`, empty if there are none.
+    fn txt(&self, server: IpAddr, name: &str) -> anyhow::Result<Vec<String>>;
+
+    /// The name `name` is a CNAME of, if it is one. Only
+    /// [`resolve_cname_chain`] asks; the default knows no CNAMEs.
+    fn cname(&self, _name: &str) -> anyhow::Result<Option<String>> {
+        Ok(None)
+    }
+}
+
+/// Resolves through the nameserver of `/etc/resolv.conf` and asks the
+/// authoritative servers directly.
+#[derive(Debug, Default)]
+pub struct SystemResolver {
+    family: Option<AddressFamily>,
+}
+
+impl SystemResolver {
+    /// Only talks to nameservers of `family`, if given. Without one, the
+    /// authoritative servers are asked over IPv4.
+    pub fn new(family: Option<AddressFamily>) -> Self {
+        Self { family }
+    }
+}
+
+impl Resolver for SystemResolver {
+    fn nameservers(&self, zone: &str) -> anyhow::Result<Vec<(String, IpAddr)>> {
+        let recursive = dns::system_nameserver(self.family)?;
+
+        let answer = dns::query(recursive, zone, dns::TYPE_NS, true)?;
+        if !answer.is_authoritative_answer() {
+            anyhow::bail!(
+                "{} failed to look up the nameservers of {}",
+                recursive,
+                zone
+            );
+        }
+
+        let names: Vec<String> = answer
+            .records
+            .into_iter()
+            .filter_map(|(_, data)| match data {
+                RecordData::Ns(name) => Some(name),
+                _ => None,
+            })
+            .collect();
+
+        if names.is_empty() {
+            anyhow::bail!("No nameservers found for {}", zone);
+        }
+
+        let family = self.family.unwrap_or(AddressFamily::Ipv4);
+        let record_type = match family {
+            AddressFamily::Ipv4 => dns::TYPE_A,
+            AddressFamily::Ipv6 => dns::TYPE_AAAA,
+        };
+
+        names
+            .into_iter()
+            .map(|name| {
+                let addr = dns::query(recursive, &name, record_type, true)?
+                    .records
+                    .into_iter()
+                    .find_map(|(_, data)| match data {
+                        RecordData::A(addr) => Some(IpAddr::V4(addr)),
+                        RecordData::Aaaa(addr) => Some(IpAddr::V6(addr)),
+                        _ => None,
+                    })
+                    .context(format!("Nameserver {} has no {} address", name, family))?;
+
+                Ok((name, addr))
+            })
+            .collect()
+    }
+
+    fn txt(&self, server: IpAddr, name: &str) -> anyhow::Result<Vec<String>> {
+        let answer = dns::query(server, name, dns::TYPE_TXT, false)?;
+        if !answer.is_authoritative_answer() {
+            anyhow::bail!("{} refused to answer for {}", server, name);
+        }
+        let name = name.trim_end_matches('.').to_lowercase();
+
+        Ok(answer
+            .records
+            .into_iter()
+            .filter(|(owner, _)| *owner == name)
+            .filter_map(|(_, data)| match data {
+                RecordData::Txt(text) => Some(text),
+                _ => None,
+            })
+            .collect())
+    }
+
+    fn cname(&self, name: &str) -> anyhow::Result<Option<String>> {
+        let recursive = dns::system_nameserver(self.family)?;
+
+        let answer = dns::query(recursive, name, dns::TYPE_CNAME, true)?;
+        if !answer.is_authoritative_answer() {
+            anyhow::bail!("{} failed to look up the CNAME of {}", recursive, name);
+        }
+        let name = name.trim_end_matches('.').to_lowercase();
+
+        Ok(answer
+            .records
+            .into_iter()
+            .find_map(|(owner, data)| match data {
+                RecordData::Cname(target) if owner == name => Some(target),
+                _ => None,
+            }))
+    }
+}
+
+/// How many CNAMEs [`resolve_cname_chain`] follows before giving up.
+pub const MAX_CNAME_DEPTH: usize = 8;
+
+/// `name` followed by the names its CNAMEs point to, in order, up to the
+/// first name that isn't one. A name without a CNAME is the whole chain.
+///
+/// Fails on a loop or after [`MAX_CNAME_DEPTH`] CNAMEs, showing the chain.
+pub fn resolve_cname_chain(resolver: &dyn Resolver, name: &str) -> anyhow::Result<Vec<String>> {
+    let mut chain = vec![normalize_dns_name(name)?];
+
+    loop {
+        let last = &chain[chain.len() - 1];
+        let Some(target) = resolver.cname(last)? else {
+            return Ok(chain);
+        };
+
+        let target = normalize_dns_name(&target)?;
+        let looped = chain.contains(&target);
+        chain.push(target);
+        if looped {
+            anyhow::bail!("The CNAMEs of {} form a loop: {}", name, chain.join(" -> "));
+        }
+        if chain.len() > MAX_CNAME_DEPTH + 1 {
+            anyhow::bail!(
+                "The CNAMEs of {} go on for more than {} hops: {}",
+                name,
+                MAX_CNAME_DEPTH,
+                chain.join(" -> ")
+            );
+        }
+    }
+}
+
+/// Passed to [`PropagationOptions::progress`] after every round of queries.
+#[derive(Debug, Clone)]
+pub struct PropagationProgress {
+    pub elapsed: Duration,
+    /// Nameservers that answer as expected
+    pub confirmed: usize,
+    /// How many have to confirm, see [`PropagationOptions::quorum`]
+    pub required: usize,
+    pub total: usize,
+    /// Nameservers that couldn't be queried in this round
+    pub unreachable: usize,
+    pub next_poll: Duration,
+}
+
+/// How many of the nameservers have to agree before a wait succeeds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Quorum {
+    #[default]
+    All,
+    Count(usize),
+    /// Rounded up, so 50% of 3 servers is 2
+    Percent(u8),
+}
+
+impl Quorum {
+    /// How many of `total` servers are enough: at most all of them, and at
+    /// least one if there are any.
+    pub fn required(self, total: usize) -> usize {
+        let required = match self {
+            Quorum::All => total,
+            Quorum::Count(count) => count,
+            Quorum::Percent(percent) => (total * percent as usize).div_ceil(100),
+        };
+        required.max(1).min(total)
+    }
+}
+
+impl FromStr for Quorum {
+    type Err = anyhow::Error;
+
+    /// `all`, a number of servers like `2` or a share like `66%`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.eq_ignore_ascii_case("all") {
+            return Ok(Quorum::All);
+        }
+
+        let quorum = match s.strip_suffix('%') {
+            Some(percent) => match percent.trim().parse::<u8>() {
+                Ok(percent @ 1..=100) => Quorum::Percent(percent),
+                _ => return Err(anyhow!("{} is not a percentage from 1% to 100%", s)),
+            },
+            None => match s.parse::<usize>() {
+                Ok(count @ 1..) => Quorum::Count(count),
+                _ => {
+                    return Err(anyhow!(
+                        "Expected all, a number of servers or a percentage, got {}",
+                        s
+                    ))
+                }
+            },
+        };
+        Ok(quorum)
+    }
+}
+
+impl fmt::Display for Quorum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Quorum::All => write!(f, "all"),
+            Quorum::Count(count) => write!(f, "{}", count),
+            Quorum::Percent(percent) => write!(f, "{}%", percent),
+        }
+    }
+}
+
+/// Receives [`PropagationProgress`] updates.
+pub type ProgressCallback = Arc<dyn Fn(&PropagationProgress) + Send + Sync>;
+
+/// How long and how often to poll the nameservers.
+#[derive(Clone)]
+pub struct PropagationOptions {
+    pub timeout: Duration,
+    pub poll_interval: Duration,
+    /// Aborts the wait with [`NetcupError::Interrupted`] once set
+    pub cancel: Option<Arc<AtomicBool>>,
+    /// Called while waiting for the next poll
+    pub progress: Option<ProgressCallback>,
+    /// Only queries nameservers over this IP version
+    pub family: Option<AddressFamily>,
+    /// How many nameservers have to answer as expected, all by default
+    pub quorum: Quorum,
+    /// If no nameserver can be queried at all (network errors only, never a
+    /// negative answer), succeed after this long instead of timing out.
+    /// Only takes effect if shorter than the timeout.
+    pub assume_propagated_after: Option<Duration>,
+}
+
+impl Default for PropagationOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(10 * 60),
+            poll_interval: Duration::from_secs(30),
+            cancel: None,
+            progress: None,
+            family: None,
+            quorum: Quorum::All,
+            assume_propagated_after: None,
+        }
+    }
+}
+
+impl fmt::Debug for PropagationOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PropagationOptions")
+            .field("timeout", &self.timeout)
+            .field("poll_interval", &self.poll_interval)
+            .field("cancel", &self.cancel)
+            .field("progress", &self.progress.is_some())
+            .field("family", &self.family)
+            .field("quorum", &self.quorum)
+            .field("assume_propagated_after", &self.assume_propagated_after)
+            .finish()
+    }
+}
+
+/// The result of a successful propagation check.
+#[derive(Debug, Clone)]
+pub struct PropagationReport {
+    pub elapsed: Duration,
+    /// The nameservers that were asked
+    pub nameservers: Vec<String>,
+    /// Nameservers that didn't answer as expected yet when the quorum was
+    /// reached; always empty with [`Quorum::All`]
+    pub stragglers: Vec<String>,
+    /// Nameservers the last round of queries failed for, as opposed to
+    /// those that answered without the expected value
+    pub unreachable: Vec<String>,
+    /// Nothing was verified: no nameserver could be queried, and the wait
+    /// ended after [`PropagationOptions::assume_propagated_after`]
+    pub assumed: bool,
+}
+
+/// Waits until every authoritative nameserver of `domain`, or as many as
+/// [`PropagationOptions::quorum`] asks for, serves `content` as a TXT record
+/// of `hostname` (relative to the domain, `@` for the apex).
+///
+/// Times out with [`NetcupError::PropagationTimeout`].
+pub fn verify_propagation(
+    domain: &str,
+    hostname: &str,
+    content: &str,
+    options: &PropagationOptions,
+) -> anyhow::Result<PropagationReport> {
+    verify_propagation_with(
+        &SystemResolver::new(options.family),
+        domain,
+        hostname,
+        content,
+        options,
+    )
+}
+
+/// [`verify_propagation`] with a custom [`Resolver`].
+pub fn verify_propagation_with(
+    resolver: &dyn Resolver,
+    domain: &str,
+    hostname: &str,
+    content: &str,
+    options: &PropagationOptions,
+) -> anyhow::Result<PropagationReport> {
+    wait_for(resolver, domain, hostname, content, true, options)
+}
+
+/// Waits until no authoritative nameserver of `domain` serves `content` as a
+/// TXT record of `hostname` anymore, or the quorum of them no longer does.
+pub fn verify_absence(
+    domain: &str,
+    hostname: &str,
+    content: &str,
+    options: &PropagationOptions,
+) -> anyhow::Result<PropagationReport> {
+    verify_absence_with(
+        &SystemResolver::new(options.family),
+        domain,
+        hostname,
+        content,
+        options,
+    )
+}
+
+/// [`verify_absence`] with a custom [`Resolver`].
+pub fn verify_absence_with(
+    resolver: &dyn Resolver,
+    domain: &str,
+    hostname: &str,
+    content: &str,
+    options: &PropagationOptions,
+) -> anyhow::Result<PropagationReport> {
+    wait_for(resolver, domain, hostname, content, false, options)
+}
+
+fn wait_for(
+    resolver: &dyn Resolver,
+    domain: &str,
+    hostname: &str,
+    content: &str,
+    present: bool,
+    options: &PropagationOptions,
+) -> anyhow::Result<PropagationReport> {
+    let start = Instant::now();
+    let name = fqdn(domain, hostname);
+    let expected = unquote(content);
+
+    let mut nameservers = None;
+    // Whether any server gave an answer, which rules out the fallback
+    let mut answered = false;
+
+    loop {
+        if nameservers.is_none() {
+            match resolver.nameservers(domain) {
+                Ok(found) => nameservers = Some(found),
+                Err(err) if options.assume_propagated_after.is_some() && is_query_failure(&err) => {
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        let servers = nameservers.as_deref().unwrap_or_default();
+        let required = options.quorum.required(servers.len());
+
+        // A server that can't be asked counts as not there yet
+        let mut pending = Vec::new();
+        let mut unreachable = Vec::new();
+        for (ns, addr) in servers {
+            match resolver.txt(*addr, &name) {
+                Ok(values) => {
+                    answered = true;
+                    let serves = values.iter().any(|value| unquote(value) == expected);
+                    if serves != present {
+                        pending.push(ns.clone());
+                    }
+                }
+                Err(_) => {
+                    pending.push(ns.clone());
+                    unreachable.push(ns.clone());
+                }
+            }
+        }
+
+        let confirmed = servers.len() - pending.len();
+        if nameservers.is_some() && confirmed >= required {
+            return Ok(PropagationReport {
+                elapsed: start.elapsed(),
+                nameservers: servers.iter().map(|(ns, _)| ns.clone()).collect(),
+                stragglers: pending,
+                unreachable,
+                assumed: false,
+            });
+        }
+
+        let progress = |remaining| {
+            if let Some(progress) = &options.progress {
+                progress(&PropagationProgress {
+                    elapsed: start.elapsed(),
+                    confirmed,
+                    required,
+                    total: servers.len(),
+                    unreachable: unreachable.len(),
+                    next_poll: remaining,
+                });
+            }
+        };
+
+        if let Some(after) = options.assume_propagated_after.filter(|_| !answered) {
+            if start.elapsed() + options.poll_interval > after {
+                sleep(
+                    after.saturating_sub(start.elapsed()),
+                    options.cancel.as_deref(),
+                    progress,
+                )?;
+                return Ok(PropagationReport {
+                    elapsed: start.elapsed(),
+                    nameservers: servers.iter().map(|(ns, _)| ns.clone()).collect(),
+                    stragglers: pending,
+                    unreachable,
+                    assumed: true,
+                });
+            }
+        }
+
+        if start.elapsed() + options.poll_interval > options.timeout {
+            return Err(NetcupError::PropagationTimeout {
+                name,
+                nameservers: pending,
+                unreachable,
+            }
+            .into());
+        }
+
+        sleep(options.poll_interval, options.cancel.as_deref(), progress)?;
+    }
+}
+
+/// Whether looking up the nameservers failed on the network rather than
+/// with an answer, e.g. all queries timing out.
+fn is_query_failure(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| cause.is::<std::io::Error>())
+}
+
+/// Sleeps in short steps so a cancellation is noticed quickly, calling
+/// `tick` with the remaining time after each.
+pub(crate) fn sleep(
+    duration: Duration,
+    cancel: Option<&AtomicBool>,
+    tick: impl Fn(Duration),
+) -> Result<(), NetcupError> {
+    let deadline = Instant::now() + duration;
+
+    loop {
+        if cancel.is_some_and(|cancel| cancel.load(Ordering::SeqCst)) {
+            return Err(NetcupError::Interrupted);
+        }
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Ok(());
+        }
+        tick(remaining);
+        thread::sleep(remaining.min(Duration::from_millis(200)));
+    }
+}
+
+/// The name a record of `hostname` in `domain` is served under.
+fn fqdn(domain: &str, hostname: &str) -> String {
+    match hostname {
+        "" | "@" => domain.to_lowercase(),
+        _ => format!("{}.{}", hostname, domain).to_lowercase(),
+    }
+}
+
+/// Resolvers and the API may both hand out the value with quotes around it.
+fn unquote(value: &str) -> &str {
+    let value = value.trim();
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+    use crate::testing::StubResolver;
+
+    const NAME: &str = "_acme-challenge.example.de";
+    const NS1: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+    const NS2: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+
+    fn resolver() -> StubResolver {
+        StubResolver::new()
+            .nameserver("example.de", "ns1.example.net", NS1)
+            .nameserver("example.de", "ns2.example.net", NS2)
+    }
+
+    /// A single round of queries: polling again would pass the timeout.
+    fn once() -> PropagationOptions {
+        PropagationOptions {
+            timeout: Duration::ZERO,
+            poll_interval: Duration::from_millis(1),
+            ..Default::default()
+        }
+    }
+
+    fn wait(
+        resolver: &StubResolver,
+        options: &PropagationOptions,
+    ) -> anyhow::Result<PropagationReport> {
+        verify_propagation_with(resolver, "example.de", "_acme-challenge", "token", options)
+    }
+
+    /// The nameservers of a timeout, and those of them that failed.
+    fn timed_out(result: anyhow::Result<PropagationReport>) -> (Vec<String>, Vec<String>) {
+        match result.unwrap_err().downcast() {
+            Ok(NetcupError::PropagationTimeout {
+                nameservers,
+                unreachable,
+                ..
+            }) => (nameservers, unreachable),
+            other => panic!("expected a propagation timeout, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn propagated_once_every_nameserver_serves_the_value() {
+        let resolver =
+            resolver()
+                .txt(NS1, NAME, &["\"token\""])
+                .txt(NS2, NAME, &["other", "token"]);
+
+        let report = wait(&resolver, &once()).unwrap();
+
+        assert_eq!(report.nameservers, ["ns1.example.net", "ns2.example.net"]);
+        assert!(report.stragglers.is_empty());
+        assert!(!report.assumed);
+    }
+
+    #[test]
+    fn timeout_names_the_nameservers_without_the_value() {
+        let resolver = resolver()
+            .txt(NS1, NAME, &["token"])
+            .txt(NS2, NAME, &["stale"]);
+
+        let (nameservers, unreachable) = timed_out(wait(&resolver, &once()));
+
+        assert_eq!(nameservers, ["ns2.example.net"]);
+        assert!(unreachable.is_empty());
+    }
+
+    #[test]
+    fn failed_queries_are_told_apart_from_negative_answers() {
+        let resolver = resolver().txt_failure(NS1, NAME, "timed out");
+
+        let (nameservers, unreachable) = timed_out(wait(&resolver, &once()));
+
+        assert_eq!(nameservers, ["ns1.example.net", "ns2.example.net"]);
+        assert_eq!(unreachable, ["ns1.example.net"]);
+    }
+
+    #[test]
+    fn only_unanswered_queries_are_assumed_propagated() {
+        let options = PropagationOptions {
+            assume_propagated_after: Some(Duration::ZERO),
+            ..once()
+        };
+
+        let unanswered =
+            resolver()
+                .txt_failure(NS1, NAME, "timed out")
+                .txt_failure(NS2, NAME, "timed out");
+        let report = wait(&unanswered, &options).unwrap();
+        assert!(report.assumed);
+        assert_eq!(report.unreachable.len(), 2);
+
+        // ns2 answering without the record is a real result, not masked
+        let negative = resolver().txt_failure(NS1, NAME, "timed out");
+        let (nameservers, unreachable) = timed_out(wait(&negative, &options));
+        assert_eq!(nameservers.len(), 2);
+        assert_eq!(unreachable, ["ns1.example.net"]);
+    }
+
+    #[test]
+    fn absence_waits_for_the_value_to_go() {
+        let resolver = resolver()
+            .txt(NS1, NAME, &["other"])
+            .txt(NS2, NAME, &["token"]);
+
+        let result =
+            verify_absence_with(&resolver, "example.de", "_acme-challenge", "token", &once());
+        let (nameservers, _) = timed_out(result);
+        assert_eq!(nameservers, ["ns2.example.net"]);
+
+        let options = PropagationOptions {
+            quorum: Quorum::Count(1),
+            ..once()
+        };
+        let report = verify_absence_with(
+            &resolver,
+            "example.de",
+            "_acme-challenge",
+            "token",
+            &options,
+        )
+        .unwrap();
+        assert_eq!(report.stragglers, ["ns2.example.net"]);
+    }
+}
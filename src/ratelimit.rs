@@ -0,0 +1,172 @@
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+
+/// Netcup's documented limit, 180 requests per minute or 3 per second. Set a
+/// lower [`rate_limit`](crate::NetcupAPIClientBuilder::rate_limit) to leave
+/// room for other clients of the account.
+pub const DEFAULT_REQUESTS_PER_MINUTE: u32 = 180;
+
+/// Enforces a minimum interval between requests of one client.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    min_interval: Duration,
+    last_request: Mutex<Option<Instant>>,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(requests_per_minute: u32) -> Self {
+        Self {
+            min_interval: Duration::from_secs(60) / requests_per_minute.max(1),
+            last_request: Mutex::new(None),
+        }
+    }
+
+    /// Blocks until the next request may be sent.
+    pub(crate) fn wait(&self) {
+        let mut last_request = self
+            .last_request
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        if let Some(last) = *last_request {
+            let elapsed = last.elapsed();
+            if elapsed < self.min_interval {
+                let delay = self.min_interval - elapsed;
+                log::debug!("Delaying the request by {:?} for the rate limit", delay);
+                thread::sleep(delay);
+            }
+        }
+
+        *last_request = Some(Instant::now());
+    }
+}
+
+/// A request budget shared by all processes using the same state file.
+///
+/// Each request is recorded with its timestamp under the customer number, so
+/// separate invocations (e.g. several authenticator runs within a minute)
+/// collectively stay below netcup's limit. The file is locked while it is
+/// updated; the OS releases the lock if a process crashes while holding it.
+#[derive(Debug)]
+pub(crate) struct SharedRateLimit {
+    pub(crate) path: PathBuf,
+    pub(crate) customer_number: String,
+    pub(crate) requests_per_minute: u32,
+}
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+impl SharedRateLimit {
+    /// Records a request, waiting first if the shared window is exhausted.
+    pub(crate) fn acquire(&self) -> anyhow::Result<()> {
+        loop {
+            match self.try_acquire()? {
+                None => return Ok(()),
+                Some(wait) => {
+                    log::debug!(
+                        "Delaying the request by {:?}, other invocations used up the budget of {} per minute",
+                        wait,
+                        self.requests_per_minute
+                    );
+                    thread::sleep(wait)
+                }
+            }
+        }
+    }
+
+    /// Returns how long to wait if no request may be sent right now.
+    fn try_acquire(&self) -> anyhow::Result<Option<Duration>> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&self.path)
+            .context(format!("Failed to open {}", self.path.display()))?;
+        file.lock()
+            .context(format!("Failed to lock {}", self.path.display()))?;
+
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+
+        // A corrupt file only costs us the recorded history
+        let mut state: HashMap<String, Vec<u64>> =
+            serde_json::from_str(&contents).unwrap_or_default();
+
+        let now = unix_millis(SystemTime::now());
+        let window_start = now.saturating_sub(WINDOW.as_millis() as u64);
+
+        state.retain(|_, requests| {
+            requests.retain(|&at| at > window_start);
+            !requests.is_empty()
+        });
+
+        let requests = state.entry(self.customer_number.clone()).or_default();
+
+        let wait = if requests.len() >= self.requests_per_minute as usize {
+            let oldest = requests.iter().min().copied().unwrap_or(now);
+            Some(Duration::from_millis(
+                oldest + WINDOW.as_millis() as u64 - now,
+            ))
+        } else {
+            requests.push(now);
+            None
+        };
+
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(serde_json::to_string(&state)?.as_bytes())?;
+        file.unlock()?;
+
+        Ok(wait)
+    }
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::{self, MockTransport};
+
+    #[test]
+    fn calls_are_spaced_by_the_rate_limit() {
+        let requests_per_minute = 600;
+        let mock = MockTransport::new();
+        let client = testing::login(&mock, testing::builder().rate_limit(requests_per_minute));
+        for _ in 0..3 {
+            mock.respond_success("infoDnsRecords", json!({ "dnsrecords": [] }));
+        }
+
+        for _ in 0..3 {
+            client.list_records("example.com").unwrap();
+        }
+
+        let min_interval = Duration::from_secs(60) / requests_per_minute;
+        let sent_at = mock.sent_at();
+        assert_eq!(sent_at.len(), 4);
+        for pair in sent_at.windows(2) {
+            let gap = pair[1] - pair[0];
+            assert!(gap >= min_interval, "{:?} < {:?}", gap, min_interval);
+        }
+    }
+}
@@ -0,0 +1,308 @@
+use std::{
+    fmt,
+    net::{Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::de;
+
+mod caa;
+mod mx;
+mod srv;
+mod tlsa;
+
+pub use caa::{CaaRecord, CaaTag};
+pub use mx::MxRecord;
+pub use srv::SrvRecord;
+pub use tlsa::TlsaRecord;
+
+/// The record types netcup supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RecordType {
+    A,
+    Aaaa,
+    Cname,
+    Mx,
+    Srv,
+    Txt,
+    Caa,
+    Tlsa,
+    Ns,
+    Ds,
+    Sshfp,
+    Openpgpkey,
+    Smimea,
+}
+
+impl RecordType {
+    pub const ALL: [RecordType; 13] = [
+        RecordType::A,
+        RecordType::Aaaa,
+        RecordType::Cname,
+        RecordType::Mx,
+        RecordType::Srv,
+        RecordType::Txt,
+        RecordType::Caa,
+        RecordType::Tlsa,
+        RecordType::Ns,
+        RecordType::Ds,
+        RecordType::Sshfp,
+        RecordType::Openpgpkey,
+        RecordType::Smimea,
+    ];
+
+    /// The name netcup uses, e.g. `AAAA`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RecordType::A => "A",
+            RecordType::Aaaa => "AAAA",
+            RecordType::Cname => "CNAME",
+            RecordType::Mx => "MX",
+            RecordType::Srv => "SRV",
+            RecordType::Txt => "TXT",
+            RecordType::Caa => "CAA",
+            RecordType::Tlsa => "TLSA",
+            RecordType::Ns => "NS",
+            RecordType::Ds => "DS",
+            RecordType::Sshfp => "SSHFP",
+            RecordType::Openpgpkey => "OPENPGPKEY",
+            RecordType::Smimea => "SMIMEA",
+        }
+    }
+
+    /// Whether records of this type need a priority, which netcup keeps
+    /// apart from the destination.
+    pub fn uses_priority(self) -> bool {
+        matches!(self, RecordType::Mx | RecordType::Srv)
+    }
+}
+
+impl FromStr for RecordType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RecordType::ALL
+            .into_iter()
+            .find(|record_type| record_type.as_str().eq_ignore_ascii_case(s))
+            .context(format!("Unknown record type '{}'", s))
+    }
+}
+
+impl fmt::Display for RecordType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<RecordType> for String {
+    fn from(record_type: RecordType) -> Self {
+        record_type.as_str().into()
+    }
+}
+
+/// A record as netcup's API sends and receives it. Unset fields are left out
+/// of the JSON rather than sent as `null`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DNSRecord {
+    #[serde(
+        default,
+        deserialize_with = "de::optional_string_or_number",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub(crate) id: Option<String>,
+    pub(crate) hostname: String,
+    #[serde(rename = "type")]
+    pub(crate) record_type: String,
+    #[serde(
+        default,
+        deserialize_with = "de::optional_string_or_number",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub(crate) priority: Option<String>,
+    pub(crate) destination: String,
+    #[serde(
+        default,
+        deserialize_with = "de::bool_or_string",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub(crate) deleterecord: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) state: Option<String>,
+}
+
+impl DNSRecord {
+    /// A new record, not yet known to netcup (no id). The type is a
+    /// [`RecordType`] or its name.
+    pub fn new(
+        hostname: impl Into<String>,
+        record_type: impl Into<String>,
+        destination: impl Into<String>,
+    ) -> Self {
+        Self {
+            id: None,
+            hostname: hostname.into(),
+            record_type: record_type.into().to_ascii_uppercase(),
+            priority: None,
+            destination: destination.into(),
+            deleterecord: None,
+            state: None,
+        }
+    }
+
+    pub fn with_priority(mut self, priority: impl Into<String>) -> Self {
+        self.priority = Some(priority.into());
+        self
+    }
+
+    /// The same record (keeping its id) with another destination, e.g. to
+    /// change it with [`update_records`](crate::NetcupAPIClient::update_records).
+    pub fn with_destination(mut self, destination: impl Into<String>) -> Self {
+        self.destination = destination.into();
+        self
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    pub fn hostname(&self) -> &str {
+        &self.hostname
+    }
+
+    pub fn record_type(&self) -> &str {
+        &self.record_type
+    }
+
+    pub fn priority(&self) -> Option<&str> {
+        self.priority.as_deref()
+    }
+
+    pub fn destination(&self) -> &str {
+        &self.destination
+    }
+
+    pub fn state(&self) -> Option<&str> {
+        self.state.as_deref()
+    }
+
+    /// Turns a record fetched from netcup into a request to delete it.
+    pub(crate) fn into_deletion(self) -> Self {
+        Self {
+            deleterecord: Some(true),
+            ..self
+        }
+    }
+
+    /// Whether the record is marked for deletion in an update.
+    pub fn is_deletion(&self) -> bool {
+        self.deleterecord == Some(true)
+    }
+
+    /// Whether the record type carries a meaningful priority.
+    pub fn uses_priority(&self) -> bool {
+        matches!(self.record_type.as_str(), "MX" | "SRV")
+    }
+
+    /// Checks what netcup would otherwise reject (or silently misinterpret).
+    pub(crate) fn validate(&self) -> anyhow::Result<()> {
+        let record_type: RecordType = self.record_type.parse()?;
+
+        match record_type {
+            RecordType::A if self.destination.parse::<Ipv4Addr>().is_err() => bail!(
+                "Invalid IPv4 address '{}' for A record '{}'",
+                self.destination,
+                self.hostname
+            ),
+            RecordType::Aaaa if self.destination.parse::<Ipv6Addr>().is_err() => bail!(
+                "Invalid IPv6 address '{}' for AAAA record '{}'",
+                self.destination,
+                self.hostname
+            ),
+            _ => {}
+        }
+
+        // Netcup reports 0 for types without a priority
+        if !record_type.uses_priority()
+            && self
+                .priority
+                .as_deref()
+                .is_some_and(|priority| !matches!(priority.trim(), "" | "0"))
+        {
+            bail!(
+                "{} record '{}' doesn't take a priority",
+                self.record_type,
+                self.hostname
+            );
+        }
+
+        if record_type.uses_priority() {
+            let priority = self.priority.as_deref().context(format!(
+                "{} record '{}' requires a priority",
+                self.record_type, self.hostname
+            ))?;
+
+            if priority.trim().parse::<u16>().is_err() {
+                bail!(
+                    "Invalid priority '{}' for {} record '{}'",
+                    priority,
+                    self.record_type,
+                    self.hostname
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether `other` describes the same hostname, type and destination
+    /// (and priority, for types that use one).
+    pub(crate) fn matches(&self, other: &DNSRecord) -> bool {
+        let same_priority = !self.uses_priority()
+            || self.priority.as_deref().map(str::trim) == other.priority.as_deref().map(str::trim);
+
+        self.hostname == other.hostname
+            && self.record_type == other.record_type
+            && self.has_destination(&other.destination)
+            && same_priority
+    }
+
+    /// Whether the destination is `destination`. TXT values are compared
+    /// without surrounding quotes, which netcup sometimes adds.
+    pub fn has_destination(&self, destination: &str) -> bool {
+        if self.record_type == RecordType::Txt.as_str() {
+            unquoted(&self.destination) == unquoted(destination)
+        } else {
+            self.destination == destination
+        }
+    }
+}
+
+impl fmt::Display for DNSRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.hostname, self.record_type)?;
+
+        if self.uses_priority() {
+            if let Some(priority) = &self.priority {
+                write!(f, " {}", priority)?;
+            }
+        }
+
+        write!(f, " {}", self.destination)?;
+
+        if let Some(id) = &self.id {
+            write!(f, " (id {})", id)?;
+        }
+
+        Ok(())
+    }
+}
+
+pub(crate) fn unquoted(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+}
@@ -0,0 +1,213 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{anyhow, bail, Context};
+
+use super::DNSRecord;
+
+/// The property tag of a CAA record (RFC 8659).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaaTag {
+    Issue,
+    IssueWild,
+    Iodef,
+}
+
+impl CaaTag {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CaaTag::Issue => "issue",
+            CaaTag::IssueWild => "issuewild",
+            CaaTag::Iodef => "iodef",
+        }
+    }
+}
+
+impl fmt::Display for CaaTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for CaaTag {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "issue" => Ok(CaaTag::Issue),
+            "issuewild" => Ok(CaaTag::IssueWild),
+            "iodef" => Ok(CaaTag::Iodef),
+            _ => Err(anyhow!(
+                "Unsupported CAA tag '{}' (expected issue, issuewild or iodef)",
+                s
+            )),
+        }
+    }
+}
+
+/// A CAA record, rendered into netcup's `flags tag "value"` destination format,
+/// e.g. `0 issue "letsencrypt.org"`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaaRecord {
+    flags: u8,
+    tag: CaaTag,
+    value: String,
+}
+
+impl CaaRecord {
+    pub fn new(tag: CaaTag, value: impl Into<String>) -> anyhow::Result<Self> {
+        let value = value.into();
+
+        if value.contains('"') {
+            bail!("CAA value must not contain quotes: {}", value);
+        }
+
+        match tag {
+            CaaTag::Issue | CaaTag::IssueWild => {
+                // Either ";" (forbid issuance) or an issuer domain, optionally
+                // followed by "; key=value" parameters.
+                let issuer = value.split(';').next().unwrap_or_default().trim();
+                if !issuer.is_empty()
+                    && !issuer
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+                {
+                    bail!("Invalid CAA issuer domain '{}'", issuer);
+                }
+            }
+            CaaTag::Iodef => {
+                if !(value.starts_with("mailto:")
+                    || value.starts_with("https://")
+                    || value.starts_with("http://"))
+                {
+                    bail!(
+                        "CAA iodef value must be a mailto:, http:// or https:// URL, got '{}'",
+                        value
+                    );
+                }
+            }
+        }
+
+        Ok(Self {
+            flags: 0,
+            tag,
+            value,
+        })
+    }
+
+    /// Sets the issuer-critical flag (128).
+    pub fn critical(mut self) -> Self {
+        self.flags = 128;
+        self
+    }
+
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    pub fn tag(&self) -> CaaTag {
+        self.tag
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    pub fn to_record(&self, hostname: impl Into<String>) -> DNSRecord {
+        DNSRecord::new(hostname, "CAA", self.to_string())
+    }
+}
+
+impl fmt::Display for CaaRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} \"{}\"", self.flags, self.tag, self.value)
+    }
+}
+
+impl FromStr for CaaRecord {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().splitn(3, char::is_whitespace);
+
+        let flags = parts
+            .next()
+            .filter(|flags| !flags.is_empty())
+            .context("CAA record is empty")?;
+        let flags: u8 = flags
+            .parse()
+            .context(format!("Invalid CAA flags '{}'", flags))?;
+        let tag: CaaTag = parts.next().context("CAA record has no tag")?.parse()?;
+        let value = parts.next().context("CAA record has no value")?.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or(value);
+
+        let record = Self::new(tag, value)?;
+        Ok(Self { flags, ..record })
+    }
+}
+
+impl TryFrom<&DNSRecord> for CaaRecord {
+    type Error = anyhow::Error;
+
+    fn try_from(record: &DNSRecord) -> Result<Self, Self::Error> {
+        if !record.record_type.eq_ignore_ascii_case("CAA") {
+            bail!("Not a CAA record: {}", record.record_type);
+        }
+
+        record.destination.parse()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn destination_is_flags_tag_and_quoted_value() {
+        let caa = CaaRecord::new(CaaTag::Issue, "letsencrypt.org").unwrap();
+        assert_eq!(caa.to_string(), r#"0 issue "letsencrypt.org""#);
+
+        let caa = CaaRecord::new(CaaTag::Iodef, "mailto:security@example.de")
+            .unwrap()
+            .critical();
+        let record = caa.to_record("@");
+        assert_eq!(record.record_type(), "CAA");
+        assert_eq!(
+            record.destination(),
+            r#"128 iodef "mailto:security@example.de""#
+        );
+    }
+
+    #[test]
+    fn destination_parses_back_with_or_without_quotes() {
+        let caa: CaaRecord = r#"0 issuewild "letsencrypt.org; validationmethods=dns-01""#
+            .parse()
+            .unwrap();
+        assert_eq!(caa.tag(), CaaTag::IssueWild);
+        assert_eq!(caa.value(), "letsencrypt.org; validationmethods=dns-01");
+        assert_eq!(caa.to_string().parse::<CaaRecord>().unwrap(), caa);
+
+        let caa: CaaRecord = "128 ISSUE ;".parse().unwrap();
+        assert_eq!(
+            (caa.flags(), caa.tag(), caa.value()),
+            (128, CaaTag::Issue, ";")
+        );
+    }
+
+    #[test]
+    fn unsupported_tags_and_values_are_refused() {
+        let err = "tbs".parse::<CaaTag>().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unsupported CAA tag 'tbs' (expected issue, issuewild or iodef)"
+        );
+        assert!(r#"0 contactemail "a@example.de""#.parse::<CaaRecord>().is_err());
+
+        assert!(CaaRecord::new(CaaTag::Issue, "lets encrypt").is_err());
+        assert!(CaaRecord::new(CaaTag::Issue, "\"quoted\"").is_err());
+        assert!(CaaRecord::new(CaaTag::Iodef, "security@example.de").is_err());
+        assert!("x issue \"letsencrypt.org\"".parse::<CaaRecord>().is_err());
+    }
+}
@@ -0,0 +1,72 @@
+use std::{fmt, net::IpAddr};
+
+use anyhow::{bail, Context};
+
+use super::DNSRecord;
+
+/// An MX record. Netcup keeps the preference in the record's priority field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MxRecord {
+    priority: u16,
+    mail_host: String,
+}
+
+impl MxRecord {
+    pub fn new(priority: u16, mail_host: &str) -> anyhow::Result<Self> {
+        let mail_host = mail_host.trim_end_matches('.');
+
+        if mail_host.is_empty() {
+            bail!("MX mail host must not be empty");
+        }
+        if mail_host.parse::<IpAddr>().is_ok() {
+            bail!(
+                "MX mail host must be a hostname, not an IP address: {}",
+                mail_host
+            );
+        }
+
+        Ok(Self {
+            priority,
+            mail_host: mail_host.to_string(),
+        })
+    }
+
+    pub fn priority(&self) -> u16 {
+        self.priority
+    }
+
+    pub fn mail_host(&self) -> &str {
+        &self.mail_host
+    }
+
+    pub fn to_record(&self, hostname: impl Into<String>) -> DNSRecord {
+        DNSRecord::new(hostname, "MX", &self.mail_host).with_priority(self.priority.to_string())
+    }
+}
+
+impl fmt::Display for MxRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.priority, self.mail_host)
+    }
+}
+
+impl TryFrom<&DNSRecord> for MxRecord {
+    type Error = anyhow::Error;
+
+    fn try_from(record: &DNSRecord) -> Result<Self, Self::Error> {
+        if !record.record_type.eq_ignore_ascii_case("MX") {
+            bail!("Not an MX record: {}", record.record_type);
+        }
+
+        let priority = record
+            .priority
+            .as_deref()
+            .context("MX record has no priority")?;
+        let priority = priority
+            .trim()
+            .parse()
+            .context(format!("Invalid MX priority '{}'", priority))?;
+
+        Self::new(priority, &record.destination)
+    }
+}
@@ -0,0 +1,271 @@
+use std::{fmt, net::IpAddr};
+
+use anyhow::{bail, Context};
+
+use super::DNSRecord;
+
+/// An SRV record (RFC 2782).
+///
+/// Netcup keeps the priority in the record's priority field and packs
+/// `weight port target` into the destination.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SrvRecord {
+    service: String,
+    protocol: String,
+    name: String,
+    priority: u16,
+    weight: u16,
+    port: u16,
+    target: String,
+}
+
+impl SrvRecord {
+    /// `service` and `protocol` may be given with or without the leading
+    /// underscore, `name` is relative to the zone (`@` for the apex).
+    pub fn new(
+        service: &str,
+        protocol: &str,
+        name: &str,
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: &str,
+    ) -> anyhow::Result<Self> {
+        let service = service.trim_start_matches('_');
+        let protocol = protocol.trim_start_matches('_').to_ascii_lowercase();
+        let target = target.trim_end_matches('.');
+
+        if service.is_empty()
+            || !service
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-')
+        {
+            bail!("Invalid SRV service '{}'", service);
+        }
+        if protocol.is_empty() || !protocol.chars().all(|c| c.is_ascii_alphanumeric()) {
+            bail!("Invalid SRV protocol '{}'", protocol);
+        }
+
+        // A target of "." means the service is explicitly not available
+        if target.is_empty() {
+            if port != 0 {
+                bail!("SRV records without a target must use port 0");
+            }
+        } else {
+            if port == 0 {
+                bail!("SRV port must be between 1 and 65535");
+            }
+            if target.parse::<IpAddr>().is_ok() {
+                bail!(
+                    "SRV target must be a hostname, not an IP address: {}",
+                    target
+                );
+            }
+        }
+
+        Ok(Self {
+            service: service.to_string(),
+            protocol,
+            name: name.to_string(),
+            priority,
+            weight,
+            port,
+            target: target.to_string(),
+        })
+    }
+
+    /// Splits a hostname like `_sip._tcp.office` into service, protocol and
+    /// name (`@` if the record sits directly below the zone).
+    pub fn split_hostname(hostname: &str) -> anyhow::Result<(&str, &str, &str)> {
+        let mut labels = hostname.splitn(3, '.');
+
+        let service = labels
+            .next()
+            .and_then(|label| label.strip_prefix('_'))
+            .context(format!(
+                "SRV hostname must start with _service: {}",
+                hostname
+            ))?;
+        let protocol = labels
+            .next()
+            .and_then(|label| label.strip_prefix('_'))
+            .context(format!("SRV hostname must contain _protocol: {}", hostname))?;
+        let name = labels.next().unwrap_or("@");
+
+        Ok((service, protocol, name))
+    }
+
+    pub fn service(&self) -> &str {
+        &self.service
+    }
+
+    pub fn protocol(&self) -> &str {
+        &self.protocol
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn priority(&self) -> u16 {
+        self.priority
+    }
+
+    pub fn weight(&self) -> u16 {
+        self.weight
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// `_service._proto.name`, or `_service._proto` at the apex
+    pub fn hostname(&self) -> String {
+        match self.name.as_str() {
+            "@" | "" => format!("_{}._{}", self.service, self.protocol),
+            name => format!("_{}._{}.{}", self.service, self.protocol, name),
+        }
+    }
+
+    /// `weight port target`
+    pub fn destination(&self) -> String {
+        let target = if self.target.is_empty() {
+            "."
+        } else {
+            &self.target
+        };
+
+        format!("{} {} {}", self.weight, self.port, target)
+    }
+
+    pub fn to_record(&self) -> DNSRecord {
+        DNSRecord::new(self.hostname(), "SRV", self.destination())
+            .with_priority(self.priority.to_string())
+    }
+}
+
+impl fmt::Display for SrvRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.priority, self.destination())
+    }
+}
+
+impl TryFrom<&DNSRecord> for SrvRecord {
+    type Error = anyhow::Error;
+
+    fn try_from(record: &DNSRecord) -> Result<Self, Self::Error> {
+        if !record.record_type.eq_ignore_ascii_case("SRV") {
+            bail!("Not an SRV record: {}", record.record_type);
+        }
+
+        let (service, protocol, name) = Self::split_hostname(&record.hostname)?;
+
+        let priority = record
+            .priority
+            .as_deref()
+            .context("SRV record has no priority")?;
+        let priority = priority
+            .trim()
+            .parse()
+            .context(format!("Invalid SRV priority '{}'", priority))?;
+
+        let mut parts = record.destination.split_whitespace();
+        let mut next_number = |name: &str| -> anyhow::Result<u16> {
+            let part = parts
+                .next()
+                .context(format!("SRV record has no {}", name))?;
+            part.parse()
+                .context(format!("Invalid SRV {} '{}'", name, part))
+        };
+
+        let weight = next_number("weight")?;
+        let port = next_number("port")?;
+        let target = parts.next().context("SRV record has no target")?;
+
+        Self::new(service, protocol, name, priority, weight, port, target)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn builder_packs_weight_port_and_target_into_the_destination() {
+        let srv =
+            SrvRecord::new("_sip", "_TCP", "office", 10, 60, 5060, "sip.example.de.").unwrap();
+        let record = srv.to_record();
+
+        assert_eq!(record.hostname(), "_sip._tcp.office");
+        assert_eq!(record.record_type(), "SRV");
+        assert_eq!(record.priority(), Some("10"));
+        assert_eq!(record.destination(), "60 5060 sip.example.de");
+        assert_eq!(srv.to_string(), "10 60 5060 sip.example.de");
+    }
+
+    #[test]
+    fn listed_record_parses_and_serializes_back() {
+        for (hostname, destination) in [
+            ("_sip._tcp.office", "60 5060 sip.example.de"),
+            ("_imaps._tcp", "0 993 mail.example.de"),
+            ("_xmpp._udp", "0 0 ."),
+        ] {
+            let listed: DNSRecord = serde_json::from_value(json!({
+                "id": "3",
+                "hostname": hostname,
+                "type": "srv",
+                "priority": "20",
+                "destination": destination,
+            }))
+            .unwrap();
+
+            let srv = SrvRecord::try_from(&listed).unwrap();
+            let record = srv.to_record();
+
+            assert_eq!(record.hostname(), hostname);
+            assert_eq!(record.priority(), Some("20"));
+            assert_eq!(record.destination(), destination);
+            assert_eq!(SrvRecord::try_from(&record).unwrap(), srv);
+        }
+    }
+
+    #[test]
+    fn invalid_records_are_refused() {
+        let new = |port, target| SrvRecord::new("sip", "tcp", "@", 0, 0, port, target);
+        assert!(new(0, "sip.example.de").is_err());
+        assert!(new(5060, "192.0.2.1").is_err());
+        assert!(new(5060, ".").is_err());
+        assert!(SrvRecord::new("s p", "tcp", "@", 0, 0, 1, "x.de").is_err());
+
+        let listed = |hostname: &str, priority: Option<&str>, destination: &str| {
+            let record = DNSRecord::new(hostname, "SRV", destination);
+            let record = match priority {
+                Some(priority) => record.with_priority(priority),
+                None => record,
+            };
+            SrvRecord::try_from(&record).map_err(|err| err.to_string())
+        };
+        assert_eq!(
+            listed("sip.tcp", Some("0"), "0 5060 x.de").unwrap_err(),
+            "SRV hostname must start with _service: sip.tcp"
+        );
+        assert_eq!(
+            listed("_sip._tcp", None, "0 5060 x.de").unwrap_err(),
+            "SRV record has no priority"
+        );
+        assert_eq!(
+            listed("_sip._tcp", Some("0"), "0 70000 x.de").unwrap_err(),
+            "Invalid SRV port '70000'"
+        );
+        assert_eq!(
+            listed("_sip._tcp", Some("0"), "0 5060").unwrap_err(),
+            "SRV record has no target"
+        );
+    }
+}
@@ -0,0 +1,284 @@
+use std::{fmt, fs, path::Path, str::FromStr};
+
+use anyhow::{bail, Context};
+use sha2::{Digest, Sha256, Sha512};
+
+use super::DNSRecord;
+
+/// A TLSA record for DANE (RFC 6698), rendered as
+/// `usage selector matching-type data`, e.g. `3 1 1 0123abcd...`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsaRecord {
+    usage: u8,
+    selector: u8,
+    matching_type: u8,
+    data: String,
+}
+
+impl TlsaRecord {
+    /// Creates a record from a precomputed hex digest (or the full hex data
+    /// for matching type 0).
+    pub fn new(
+        usage: u8,
+        selector: u8,
+        matching_type: u8,
+        data: impl Into<String>,
+    ) -> anyhow::Result<Self> {
+        validate_params(usage, selector, matching_type)?;
+
+        let data = data.into().to_ascii_lowercase();
+
+        if data.is_empty() || data.len() % 2 != 0 || !data.chars().all(|c| c.is_ascii_hexdigit()) {
+            bail!("TLSA data must be a non-empty hex string");
+        }
+
+        let expected_len = match matching_type {
+            1 => Some(64),
+            2 => Some(128),
+            _ => None,
+        };
+
+        if let Some(expected_len) = expected_len {
+            if data.len() != expected_len {
+                bail!(
+                    "TLSA data for matching type {} must be {} hex characters, got {}",
+                    matching_type,
+                    expected_len,
+                    data.len()
+                );
+            }
+        }
+
+        Ok(Self {
+            usage,
+            selector,
+            matching_type,
+            data,
+        })
+    }
+
+    /// Computes the association data from a PEM or DER encoded certificate.
+    ///
+    /// Selector 1 hashes the SubjectPublicKeyInfo, which is also accepted
+    /// directly as a PEM `PUBLIC KEY` file.
+    pub fn from_certificate_file(
+        usage: u8,
+        selector: u8,
+        matching_type: u8,
+        path: impl AsRef<Path>,
+    ) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = fs::read(path).context(format!("Failed to read {}", path.display()))?;
+
+        Self::from_certificate(usage, selector, matching_type, &contents).context(format!(
+            "Failed to compute TLSA data from {}",
+            path.display()
+        ))
+    }
+
+    /// Same as [`from_certificate_file`](Self::from_certificate_file) but for
+    /// in-memory PEM or DER data.
+    pub fn from_certificate(
+        usage: u8,
+        selector: u8,
+        matching_type: u8,
+        contents: &[u8],
+    ) -> anyhow::Result<Self> {
+        validate_params(usage, selector, matching_type)?;
+
+        let (label, der) = decode_pem(contents)?;
+
+        let selected = match (selector, label.as_deref()) {
+            (0, Some("PUBLIC KEY")) => bail!("Selector 0 requires a certificate, not a public key"),
+            (0, _) => der,
+            (_, Some("PUBLIC KEY")) => der,
+            (_, _) => subject_public_key_info(&der)?.to_vec(),
+        };
+
+        let data = match matching_type {
+            0 => hex(&selected),
+            1 => hex(&Sha256::digest(&selected)),
+            _ => hex(&Sha512::digest(&selected)),
+        };
+
+        Self::new(usage, selector, matching_type, data)
+    }
+
+    pub fn usage(&self) -> u8 {
+        self.usage
+    }
+
+    pub fn selector(&self) -> u8 {
+        self.selector
+    }
+
+    pub fn matching_type(&self) -> u8 {
+        self.matching_type
+    }
+
+    pub fn data(&self) -> &str {
+        &self.data
+    }
+
+    /// `hostname` is usually of the form `_port._proto.host`, e.g. `_25._tcp.mail`.
+    pub fn to_record(&self, hostname: impl Into<String>) -> DNSRecord {
+        DNSRecord::new(hostname, "TLSA", self.to_string())
+    }
+}
+
+fn validate_params(usage: u8, selector: u8, matching_type: u8) -> anyhow::Result<()> {
+    if usage > 3 {
+        bail!("TLSA usage must be between 0 and 3, got {}", usage);
+    }
+    if selector > 1 {
+        bail!("TLSA selector must be 0 or 1, got {}", selector);
+    }
+    if matching_type > 2 {
+        bail!(
+            "TLSA matching type must be between 0 and 2, got {}",
+            matching_type
+        );
+    }
+
+    Ok(())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Returns the PEM label (if the input was PEM) and the DER bytes.
+fn decode_pem(contents: &[u8]) -> anyhow::Result<(Option<String>, Vec<u8>)> {
+    let Ok(text) = std::str::from_utf8(contents) else {
+        return Ok((None, contents.to_vec()));
+    };
+
+    let Some(begin) = text.find("-----BEGIN ") else {
+        return Ok((None, contents.to_vec()));
+    };
+
+    let rest = &text[begin + "-----BEGIN ".len()..];
+    let (label, rest) = rest.split_once("-----").context("Malformed PEM header")?;
+    let end = rest
+        .find(&format!("-----END {}-----", label))
+        .context("Missing PEM footer")?;
+
+    let base64: String = rest[..end].split_whitespace().collect();
+    let der = base64::decode(base64).context("Invalid base64 in PEM")?;
+
+    Ok((Some(label.to_string()), der))
+}
+
+/// One DER TLV and whatever follows it.
+struct DerElement<'a> {
+    tag: u8,
+    /// Header and content
+    element: &'a [u8],
+    content: &'a [u8],
+    rest: &'a [u8],
+}
+
+fn der_element(input: &[u8]) -> anyhow::Result<DerElement<'_>> {
+    let tag = *input.first().context("Unexpected end of DER data")?;
+    let first_len = *input.get(1).context("Unexpected end of DER data")?;
+
+    let (len, header_len) = if first_len & 0x80 == 0 {
+        (first_len as usize, 2)
+    } else {
+        let len_bytes = (first_len & 0x7f) as usize;
+        if len_bytes == 0 || len_bytes > 4 {
+            bail!("Unsupported DER length encoding");
+        }
+        let bytes = input
+            .get(2..2 + len_bytes)
+            .context("Unexpected end of DER data")?;
+        let len = bytes.iter().fold(0usize, |acc, b| (acc << 8) | *b as usize);
+        (len, 2 + len_bytes)
+    };
+
+    let element = input
+        .get(..header_len + len)
+        .context("DER element exceeds input")?;
+
+    Ok(DerElement {
+        tag,
+        element,
+        content: &element[header_len..],
+        rest: &input[header_len + len..],
+    })
+}
+
+/// Extracts the SubjectPublicKeyInfo from a DER certificate.
+fn subject_public_key_info(certificate: &[u8]) -> anyhow::Result<&[u8]> {
+    const SEQUENCE: u8 = 0x30;
+    const EXPLICIT_VERSION: u8 = 0xa0;
+
+    let certificate = der_element(certificate)?;
+    if certificate.tag != SEQUENCE {
+        bail!("Not a DER certificate");
+    }
+
+    let tbs_certificate = der_element(certificate.content)?;
+
+    let mut rest = tbs_certificate.content;
+    if rest.first() == Some(&EXPLICIT_VERSION) {
+        rest = der_element(rest)?.rest;
+    }
+
+    // serialNumber, signature, issuer, validity, subject
+    for _ in 0..5 {
+        rest = der_element(rest)?.rest;
+    }
+
+    let spki = der_element(rest)?;
+    if spki.tag != SEQUENCE {
+        bail!("Could not find the certificate's public key");
+    }
+
+    Ok(spki.element)
+}
+
+impl fmt::Display for TlsaRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}",
+            self.usage, self.selector, self.matching_type, self.data
+        )
+    }
+}
+
+impl FromStr for TlsaRecord {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        let mut next_param = |name: &str| -> anyhow::Result<u8> {
+            let part = parts
+                .next()
+                .context(format!("TLSA record has no {}", name))?;
+            part.parse()
+                .context(format!("Invalid TLSA {} '{}'", name, part))
+        };
+
+        let usage = next_param("usage")?;
+        let selector = next_param("selector")?;
+        let matching_type = next_param("matching type")?;
+        // Long hex data is sometimes split into several chunks
+        let data: String = parts.collect();
+
+        Self::new(usage, selector, matching_type, data)
+    }
+}
+
+impl TryFrom<&DNSRecord> for TlsaRecord {
+    type Error = anyhow::Error;
+
+    fn try_from(record: &DNSRecord) -> Result<Self, Self::Error> {
+        if !record.record_type.eq_ignore_ascii_case("TLSA") {
+            bail!("Not a TLSA record: {}", record.record_type);
+        }
+
+        record.destination.parse()
+    }
+}
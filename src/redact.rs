@@ -0,0 +1,143 @@
+//! Keeps credentials out of logged payloads.
+
+use serde::Serialize;
+use serde_json::Value;
+
+/// The fields of requests and responses that are as good as the password.
+const SECRET_FIELDS: [&str; 3] = ["apipassword", "apikey", "apisessionid"];
+
+/// `payload` as JSON, with every secret field at any depth replaced by
+/// `***`.
+pub(crate) fn redacted(payload: &impl Serialize) -> Value {
+    let mut value = serde_json::to_value(payload).unwrap_or(Value::Null);
+    redact(&mut value);
+    value
+}
+
+/// A response body for the log, redacted like [`redacted`]; bodies that
+/// aren't JSON are logged as they are.
+pub(crate) fn redacted_body(body: &str) -> String {
+    match serde_json::from_str::<Value>(body) {
+        Ok(mut value) => {
+            redact(&mut value);
+            value.to_string()
+        }
+        Err(_) => body.into(),
+    }
+}
+
+/// How much of a response body goes into an error message.
+const EXCERPT_LENGTH: usize = 300;
+
+/// The start of a response body for an error message, redacted like
+/// [`redacted_body`].
+pub(crate) fn excerpt(body: &str) -> String {
+    let body = redacted_body(body.trim());
+    match body.char_indices().nth(EXCERPT_LENGTH) {
+        Some((end, _)) => format!("{}...", &body[..end]),
+        None => body,
+    }
+}
+
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if SECRET_FIELDS.contains(&key.as_str()) {
+                    *value = Value::String("***".into());
+                } else {
+                    redact(value);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{Mutex, Once},
+        thread::{self, ThreadId},
+    };
+
+    use serde_json::json;
+
+    use crate::{
+        testing::{self, MockTransport},
+        NetcupAPIClient,
+    };
+
+    /// Every line logged by any test, with the thread that logged it.
+    static LINES: Mutex<Vec<(ThreadId, String)>> = Mutex::new(Vec::new());
+
+    struct Capture;
+
+    impl log::Log for Capture {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            let line = record.args().to_string();
+            LINES.lock().unwrap().push((thread::current().id(), line));
+        }
+
+        fn flush(&self) {}
+    }
+
+    /// The lines this thread logged at debug level.
+    fn captured(run: impl FnOnce()) -> Vec<String> {
+        static INSTALL: Once = Once::new();
+        INSTALL.call_once(|| {
+            log::set_logger(&Capture).unwrap();
+            log::set_max_level(log::LevelFilter::Debug);
+        });
+
+        run();
+        let id = thread::current().id();
+        LINES
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(thread, _)| *thread == id)
+            .map(|(_, line)| line.clone())
+            .collect()
+    }
+
+    #[test]
+    fn debug_log_masks_the_credentials() {
+        let lines = captured(|| {
+            let mock = MockTransport::new();
+            mock.respond_success("login", json!({ "apisessionid": "secret-session" }))
+                .respond_success("infoDnsRecords", json!({ "dnsrecords": [] }));
+            let client: NetcupAPIClient = testing::builder()
+                .transport(mock.clone())
+                .login(
+                    "12345".into(),
+                    "secret-password".into(),
+                    "secret-key".into(),
+                )
+                .unwrap();
+            client.list_records("example.de").unwrap();
+        });
+
+        let log = lines.join("\n");
+        assert!(!log.contains("secret-"), "{}", log);
+        for field in ["apipassword", "apikey", "apisessionid"] {
+            assert!(log.contains(&format!(r#""{}":"***""#, field)), "{}", log);
+        }
+    }
+
+    #[test]
+    fn nested_fields_and_plain_bodies() {
+        let value = super::redacted(&json!({ "responsedata": [{ "apisessionid": "s" }] }));
+        assert_eq!(
+            value,
+            json!({ "responsedata": [{ "apisessionid": "***" }] })
+        );
+
+        assert_eq!(super::redacted_body("<html>"), "<html>");
+    }
+}
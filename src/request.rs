@@ -0,0 +1,334 @@
+//! The request side of the wire format. Every call is an envelope of the
+//! action and its parameters, all values sent as strings.
+
+use serde::Serialize;
+
+use crate::{DNSRecord, DnsZone};
+
+#[derive(Debug, Serialize)]
+pub(crate) struct Request<T> {
+    pub(crate) action: &'static str,
+    pub(crate) param: T,
+}
+
+impl<T> Request<T> {
+    pub(crate) fn new(action: &'static str, param: T) -> Self {
+        Self { action, param }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct LoginRequest<'a> {
+    pub(crate) apikey: &'a str,
+    pub(crate) apipassword: &'a str,
+    pub(crate) customernumber: &'a str,
+}
+
+/// The parameters every call after login carries.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub(crate) struct Session<'a> {
+    pub(crate) apikey: &'a str,
+    pub(crate) apisessionid: &'a str,
+    pub(crate) customernumber: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct InfoDnsRecordsRequest<'a> {
+    #[serde(flatten)]
+    pub(crate) session: Session<'a>,
+    pub(crate) domainname: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct InfoDnsZoneRequest<'a> {
+    #[serde(flatten)]
+    pub(crate) session: Session<'a>,
+    pub(crate) domainname: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct UpdateDnsZoneRequest<'a> {
+    #[serde(flatten)]
+    pub(crate) session: Session<'a>,
+    pub(crate) domainname: &'a str,
+    pub(crate) dnszone: DnsZoneRequest<'a>,
+}
+
+/// A [`DnsZone`] as sent in an update, the numbers as strings.
+#[derive(Debug, Serialize)]
+pub(crate) struct DnsZoneRequest<'a> {
+    name: &'a str,
+    ttl: String,
+    serial: &'a str,
+    refresh: String,
+    retry: String,
+    expire: String,
+    dnssecstatus: bool,
+}
+
+impl<'a> From<&'a DnsZone> for DnsZoneRequest<'a> {
+    fn from(zone: &'a DnsZone) -> Self {
+        Self {
+            name: &zone.name,
+            ttl: zone.ttl.to_string(),
+            serial: &zone.serial,
+            refresh: zone.refresh.to_string(),
+            retry: zone.retry.to_string(),
+            expire: zone.expire.to_string(),
+            dnssecstatus: zone.dnssecstatus,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct UpdateDnsRecordsRequest<'a> {
+    #[serde(flatten)]
+    pub(crate) session: Session<'a>,
+    pub(crate) domainname: &'a str,
+    pub(crate) dnsrecordset: DnsRecordSet<'a>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct DnsRecordSet<'a> {
+    pub(crate) dnsrecords: Vec<DnsRecordRequest<'a>>,
+}
+
+impl<'a> DnsRecordSet<'a> {
+    pub(crate) fn new(records: &'a [DNSRecord]) -> Self {
+        Self {
+            dnsrecords: records.iter().map(DnsRecordRequest::from).collect(),
+        }
+    }
+}
+
+/// A [`DNSRecord`] as sent in an update. Unset fields are left out rather
+/// than sent as `null`, which netcup does not always treat as absent, except
+/// the priority of types that don't use one: like netcup's own webclient we
+/// send "0" there, as some accounts reject records without it.
+#[derive(Debug, Serialize)]
+pub(crate) struct DnsRecordRequest<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<&'a str>,
+    hostname: &'a str,
+    #[serde(rename = "type")]
+    record_type: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    priority: Option<&'a str>,
+    destination: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    deleterecord: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    state: Option<&'a str>,
+}
+
+impl<'a> From<&'a DNSRecord> for DnsRecordRequest<'a> {
+    fn from(record: &'a DNSRecord) -> Self {
+        Self {
+            id: record.id.as_deref(),
+            hostname: &record.hostname,
+            record_type: &record.record_type,
+            priority: record
+                .priority
+                .as_deref()
+                .or((!record.uses_priority()).then_some("0")),
+            destination: &record.destination,
+            deleterecord: record.deleterecord,
+            state: record.state.as_deref(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::RecordType;
+
+    const SESSION: Session = Session {
+        apikey: "key",
+        apisessionid: "session",
+        customernumber: "12345",
+    };
+
+    fn to_json(request: impl Serialize) -> Value {
+        serde_json::to_value(request).unwrap()
+    }
+
+    fn wire(record: &DNSRecord) -> Value {
+        serde_json::to_value(DnsRecordRequest::from(record)).unwrap()
+    }
+
+    #[test]
+    fn new_record_leaves_out_unset_keys_and_sends_priority_zero() {
+        let record = DNSRecord::new("_acme-challenge", RecordType::Txt, "token");
+
+        assert_eq!(
+            wire(&record),
+            json!({
+                "hostname": "_acme-challenge",
+                "type": "TXT",
+                "priority": "0",
+                "destination": "token",
+            })
+        );
+    }
+
+    #[test]
+    fn priority_is_kept_for_types_that_use_one() {
+        let record = DNSRecord::new("@", RecordType::Mx, "mail.example.de").with_priority("10");
+        assert_eq!(wire(&record)["priority"], "10");
+
+        // An MX record needs a real one, so none is made up
+        let record = DNSRecord::new("@", RecordType::Mx, "mail.example.de");
+        assert!(wire(&record).get("priority").is_none());
+    }
+
+    #[test]
+    fn deletion_is_sent_with_its_id() {
+        let record: DNSRecord = serde_json::from_value(json!({
+            "id": "7",
+            "hostname": "_acme-challenge",
+            "type": "TXT",
+            "priority": "0",
+            "destination": "token",
+            "deleterecord": "true",
+            "state": "yes",
+        }))
+        .unwrap();
+
+        let wire = wire(&record);
+        assert_eq!(wire["id"], "7");
+        assert_eq!(wire["deleterecord"], true);
+        assert_eq!(wire["state"], "yes");
+    }
+
+    #[test]
+    fn record_round_trips_through_the_wire_format() {
+        let record = DNSRecord::new("@", RecordType::Mx, "mail.example.de").with_priority("10");
+
+        let parsed: DNSRecord = serde_json::from_value(wire(&record)).unwrap();
+
+        assert_eq!(wire(&parsed), wire(&record));
+        assert_eq!(parsed.hostname(), "@");
+        assert_eq!(parsed.priority(), Some("10"));
+    }
+
+    #[test]
+    fn login_sends_the_credentials() {
+        let login = Request::new(
+            "login",
+            LoginRequest {
+                apikey: "key",
+                apipassword: "password",
+                customernumber: "12345",
+            },
+        );
+
+        assert_eq!(
+            to_json(login),
+            json!({
+                "action": "login",
+                "param": {
+                    "apikey": "key",
+                    "apipassword": "password",
+                    "customernumber": "12345",
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn logout_sends_only_the_session() {
+        assert_eq!(
+            to_json(Request::new("logout", SESSION)),
+            json!({
+                "action": "logout",
+                "param": {
+                    "apikey": "key",
+                    "apisessionid": "session",
+                    "customernumber": "12345",
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn info_requests_flatten_the_session_next_to_the_domain() {
+        let expected = json!({
+            "apikey": "key",
+            "apisessionid": "session",
+            "customernumber": "12345",
+            "domainname": "example.de",
+        });
+
+        let records = InfoDnsRecordsRequest {
+            session: SESSION,
+            domainname: "example.de",
+        };
+        assert_eq!(to_json(records), expected);
+
+        let zone = InfoDnsZoneRequest {
+            session: SESSION,
+            domainname: "example.de",
+        };
+        assert_eq!(to_json(zone), expected);
+    }
+
+    #[test]
+    fn update_records_wraps_them_in_a_record_set() {
+        let records = [
+            DNSRecord::new("_acme-challenge", RecordType::Txt, "token"),
+            DNSRecord::new("@", RecordType::Mx, "mail.example.de").with_priority("10"),
+        ];
+        let update = UpdateDnsRecordsRequest {
+            session: SESSION,
+            domainname: "example.de",
+            dnsrecordset: DnsRecordSet::new(&records),
+        };
+
+        let json = to_json(update);
+        assert_eq!(json["domainname"], "example.de");
+        assert_eq!(json["apisessionid"], "session");
+        assert_eq!(
+            json["dnsrecordset"]["dnsrecords"],
+            json!([wire(&records[0]), wire(&records[1])])
+        );
+    }
+
+    #[test]
+    fn zone_update_round_trips_with_numbers_as_strings() {
+        let zone: DnsZone = serde_json::from_value(json!({
+            "name": "example.de",
+            "ttl": 86400,
+            "serial": "2024010101",
+            "refresh": "28800",
+            "retry": 7200,
+            "expire": "1209600",
+            "dnssecstatus": "false",
+        }))
+        .unwrap();
+        let update = UpdateDnsZoneRequest {
+            session: SESSION,
+            domainname: "example.de",
+            dnszone: DnsZoneRequest::from(&zone),
+        };
+
+        let json = to_json(update);
+        assert_eq!(
+            json["dnszone"],
+            json!({
+                "name": "example.de",
+                "ttl": "86400",
+                "serial": "2024010101",
+                "refresh": "28800",
+                "retry": "7200",
+                "expire": "1209600",
+                "dnssecstatus": false,
+            })
+        );
+
+        let parsed: DnsZone = serde_json::from_value(json["dnszone"].clone()).unwrap();
+        assert_eq!(to_json(DnsZoneRequest::from(&parsed)), json["dnszone"]);
+    }
+}
@@ -0,0 +1,300 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::NetcupError;
+
+/// How often and how patiently transient failures are retried: transport
+/// errors including timeouts, HTTP 5xx responses and netcup's rate limit.
+/// Anything else, like invalid credentials, fails immediately. Changes that
+/// create records are only sent again if the failed attempt can't have been
+/// applied, i.e. it found no connection or netcup refused it for the rate
+/// limit; otherwise a timed out addition could end up in the zone twice.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Including the first attempt
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A single attempt, no retries.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// The delay after the `attempt`th failed attempt: exponential, capped,
+    /// and jittered to between half and all of it.
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay);
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or_default();
+        let jitter = 0.5 + f64::from(nanos % 1000) / 2000.0;
+
+        delay.mul_f64(jitter)
+    }
+}
+
+/// The kinds of failure that are retried, and counted by the breaker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FailureClass {
+    Transport,
+    ServerError,
+    RateLimited,
+}
+
+/// The state of the client's circuit breaker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests go through
+    Closed { consecutive_failures: u32 },
+    /// Requests fail fast with [`NetcupError::CircuitOpen`]
+    Open { retry_after: Duration },
+    /// A single probe request is in flight
+    HalfOpen,
+}
+
+#[derive(Debug)]
+enum State {
+    Closed {
+        class: Option<FailureClass>,
+        failures: u32,
+    },
+    Open {
+        until: Instant,
+    },
+    HalfOpen,
+}
+
+/// Stops hammering an API that is down: after `threshold` consecutive
+/// failures of the same class, requests fail fast for `cool_down`, then one
+/// probe is let through to decide whether to close again.
+#[derive(Debug)]
+pub(crate) struct CircuitBreaker {
+    threshold: u32,
+    cool_down: Duration,
+    state: Mutex<State>,
+}
+
+pub const DEFAULT_CIRCUIT_THRESHOLD: u32 = 5;
+pub const DEFAULT_CIRCUIT_COOL_DOWN: Duration = Duration::from_secs(60);
+
+impl CircuitBreaker {
+    pub(crate) fn new(threshold: u32, cool_down: Duration) -> Self {
+        Self {
+            threshold: threshold.max(1),
+            cool_down,
+            state: Mutex::new(State::Closed {
+                class: None,
+                failures: 0,
+            }),
+        }
+    }
+
+    pub(crate) fn state(&self) -> CircuitState {
+        match *self.state.lock().unwrap() {
+            State::Closed { failures, .. } => CircuitState::Closed {
+                consecutive_failures: failures,
+            },
+            State::Open { until } => CircuitState::Open {
+                retry_after: until.saturating_duration_since(Instant::now()),
+            },
+            State::HalfOpen => CircuitState::HalfOpen,
+        }
+    }
+
+    /// Fails fast while open, otherwise lets the request through.
+    pub(crate) fn before_attempt(&self) -> Result<(), NetcupError> {
+        let mut state = self.state.lock().unwrap();
+
+        match *state {
+            State::Closed { .. } => Ok(()),
+            State::Open { until } if Instant::now() >= until => {
+                *state = State::HalfOpen;
+                Ok(())
+            }
+            State::Open { until } => Err(NetcupError::CircuitOpen {
+                retry_after: until.saturating_duration_since(Instant::now()),
+            }),
+            State::HalfOpen => Err(NetcupError::CircuitOpen {
+                retry_after: Duration::ZERO,
+            }),
+        }
+    }
+
+    pub(crate) fn success(&self) {
+        *self.state.lock().unwrap() = State::Closed {
+            class: None,
+            failures: 0,
+        };
+    }
+
+    /// For an attempt that was given way but not made, e.g. because the
+    /// shared rate limit failed: a half-open probe makes room for the next.
+    pub(crate) fn release(&self) {
+        let mut state = self.state.lock().unwrap();
+        if let State::HalfOpen = *state {
+            *state = State::Open {
+                until: Instant::now(),
+            };
+        }
+    }
+
+    pub(crate) fn failure(&self, failed: FailureClass) {
+        let mut state = self.state.lock().unwrap();
+
+        let failures = match *state {
+            State::Closed { class, failures } if class == Some(failed) => failures + 1,
+            State::Closed { .. } => 1,
+            // The probe failed, or a request raced it
+            State::Open { .. } | State::HalfOpen => self.threshold,
+        };
+
+        *state = if failures >= self.threshold {
+            State::Open {
+                until: Instant::now() + self.cool_down,
+            }
+        } else {
+            State::Closed {
+                class: Some(failed),
+                failures,
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delays_grow_up_to_the_maximum() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(400),
+        };
+
+        // Jittered to between half and all of 100, 200, 400 and 400 ms
+        let bounds = [(50, 100), (100, 200), (200, 400), (200, 400)];
+        for (attempt, (low, high)) in (1..).zip(bounds) {
+            let delay = policy.delay(attempt);
+            assert!(
+                delay >= Duration::from_millis(low) && delay <= Duration::from_millis(high),
+                "attempt {}: {:?}",
+                attempt,
+                delay
+            );
+        }
+    }
+
+    #[test]
+    fn opens_after_threshold_failures_of_one_class() {
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(60));
+
+        breaker.failure(FailureClass::Transport);
+        breaker.failure(FailureClass::Transport);
+        assert_eq!(
+            breaker.state(),
+            CircuitState::Closed {
+                consecutive_failures: 2
+            }
+        );
+        assert!(breaker.before_attempt().is_ok());
+
+        breaker.failure(FailureClass::Transport);
+        assert!(matches!(breaker.state(), CircuitState::Open { .. }));
+        assert!(matches!(
+            breaker.before_attempt(),
+            Err(NetcupError::CircuitOpen { .. })
+        ));
+    }
+
+    #[test]
+    fn another_class_or_a_success_resets_the_count() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+
+        breaker.failure(FailureClass::Transport);
+        breaker.failure(FailureClass::ServerError);
+        assert_eq!(
+            breaker.state(),
+            CircuitState::Closed {
+                consecutive_failures: 1
+            }
+        );
+
+        breaker.success();
+        breaker.failure(FailureClass::ServerError);
+        assert_eq!(
+            breaker.state(),
+            CircuitState::Closed {
+                consecutive_failures: 1
+            }
+        );
+    }
+
+    #[test]
+    fn lets_one_probe_through_after_the_cool_down() {
+        let breaker = CircuitBreaker::new(1, Duration::ZERO);
+        breaker.failure(FailureClass::Transport);
+
+        assert!(breaker.before_attempt().is_ok());
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert!(matches!(
+            breaker.before_attempt(),
+            Err(NetcupError::CircuitOpen { .. })
+        ));
+    }
+
+    #[test]
+    fn probe_outcome_closes_or_reopens() {
+        let breaker = CircuitBreaker::new(3, Duration::ZERO);
+        for _ in 0..3 {
+            breaker.failure(FailureClass::Transport);
+        }
+        breaker.before_attempt().unwrap();
+        breaker.failure(FailureClass::Transport);
+        assert!(matches!(breaker.state(), CircuitState::Open { .. }));
+
+        breaker.before_attempt().unwrap();
+        breaker.success();
+        assert_eq!(
+            breaker.state(),
+            CircuitState::Closed {
+                consecutive_failures: 0
+            }
+        );
+    }
+
+    #[test]
+    fn released_probe_makes_room_for_the_next() {
+        let breaker = CircuitBreaker::new(1, Duration::ZERO);
+        breaker.failure(FailureClass::Transport);
+        breaker.before_attempt().unwrap();
+
+        breaker.release();
+        assert!(matches!(breaker.state(), CircuitState::Open { .. }));
+        assert!(breaker.before_attempt().is_ok());
+    }
+}
@@ -0,0 +1,315 @@
+use std::{
+    collections::HashMap,
+    fs::{self, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use serde::{de::IgnoredAny, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    request::{InfoDnsZoneRequest, Request},
+    NetcupAPIClient, NetcupError,
+};
+
+/// Netcup ends a session after this long without requests.
+const SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
+/// What [`NetcupAPIClient::verify_session`] found out about the session.
+#[derive(Debug)]
+pub enum SessionValidity {
+    /// Netcup accepted the session
+    Valid,
+    /// Netcup rejected the session, it has to be replaced by a new login
+    Invalid(NetcupError),
+    /// The probe failed before netcup judged the session, e.g. on a
+    /// connection failure or the rate limit
+    Indeterminate(anyhow::Error),
+}
+
+impl SessionValidity {
+    pub fn is_valid(&self) -> bool {
+        matches!(self, SessionValidity::Valid)
+    }
+}
+
+impl NetcupAPIClient {
+    /// Checks whether the session is still accepted, without changing
+    /// anything and without listing a zone's records: it asks for the zone
+    /// settings of `probe_domain` with `infoDnsZone`. Netcup checks the
+    /// session first, so any other error (e.g. a domain of another account)
+    /// still means the session is valid.
+    ///
+    /// Only fails if `probe_domain` is outside the allowlist.
+    pub fn verify_session(&self, probe_domain: &str) -> anyhow::Result<SessionValidity> {
+        let probe_domain = &self.check_domain(probe_domain)?;
+
+        let payload = Request::new(
+            "infoDnsZone",
+            InfoDnsZoneRequest {
+                session: self.session(),
+                domainname: probe_domain,
+            },
+        );
+
+        let response = match self.http.call::<IgnoredAny>(&payload) {
+            Ok(response) => response,
+            Err(err) => return Ok(SessionValidity::Indeterminate(err)),
+        };
+
+        Ok(match response.error(Some(probe_domain)) {
+            None => SessionValidity::Valid,
+            Some(err @ NetcupError::AuthenticationFailed { .. }) => SessionValidity::Invalid(err),
+            Some(err @ NetcupError::RateLimited { .. }) => {
+                SessionValidity::Indeterminate(err.into())
+            }
+            Some(_) => SessionValidity::Valid,
+        })
+    }
+}
+
+/// Sessions kept between processes, by customer number, so that e.g. the
+/// `set` and `unset` of one challenge share a login. Only readable by the
+/// owner, as a session is as good as the password until it expires. The
+/// file is locked while it is updated.
+#[derive(Debug, Clone)]
+pub(crate) struct SessionCache {
+    pub(crate) path: PathBuf,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedSession {
+    /// Sessions belong to an API key as well, which is not stored itself
+    api_key_sha256: String,
+    session_id: String,
+    /// Unix seconds
+    last_used: u64,
+}
+
+impl SessionCache {
+    /// The session cached for this account if it may still be alive.
+    pub(crate) fn load(
+        &self,
+        customer_number: &str,
+        api_key: &str,
+    ) -> anyhow::Result<Option<String>> {
+        let mut found = None;
+        self.update(|sessions| {
+            found = sessions
+                .get(customer_number)
+                .filter(|cached| cached.api_key_sha256 == key_hash(api_key))
+                .map(|cached| cached.session_id.clone());
+        })?;
+        Ok(found)
+    }
+
+    pub(crate) fn store(
+        &self,
+        customer_number: &str,
+        api_key: &str,
+        session_id: &str,
+    ) -> anyhow::Result<()> {
+        self.update(|sessions| {
+            sessions.insert(
+                customer_number.into(),
+                CachedSession {
+                    api_key_sha256: key_hash(api_key),
+                    session_id: session_id.into(),
+                    last_used: unix_secs(SystemTime::now()),
+                },
+            );
+        })
+    }
+
+    pub(crate) fn remove(&self, customer_number: &str) -> anyhow::Result<()> {
+        self.update(|sessions| {
+            sessions.remove(customer_number);
+        })
+    }
+
+    /// Reads the file under its lock, drops expired sessions, applies `f`
+    /// and writes the result back.
+    fn update(&self, f: impl FnOnce(&mut HashMap<String, CachedSession>)) -> anyhow::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut options = OpenOptions::new();
+        options.read(true).write(true).create(true).truncate(false);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+
+        let mut file = options
+            .open(&self.path)
+            .context(format!("Failed to open {}", self.path.display()))?;
+        file.lock()
+            .context(format!("Failed to lock {}", self.path.display()))?;
+
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+
+        // A corrupt file only costs us a login
+        let mut sessions: HashMap<String, CachedSession> =
+            serde_json::from_str(&contents).unwrap_or_default();
+
+        let expired = unix_secs(SystemTime::now()).saturating_sub(SESSION_IDLE_TIMEOUT.as_secs());
+        sessions.retain(|_, cached| cached.last_used > expired);
+
+        f(&mut sessions);
+
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(serde_json::to_string(&sessions)?.as_bytes())?;
+        file.unlock()?;
+
+        Ok(())
+    }
+}
+
+fn key_hash(api_key: &str) -> String {
+    Sha256::digest(api_key.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing::{self, MockTransport};
+
+    fn resume(mock: &MockTransport, cache: &std::path::Path) -> NetcupAPIClient {
+        testing::builder()
+            .transport(mock.clone())
+            .session_cache(cache)
+            .resume_or_login(
+                "12345".into(),
+                "password".into(),
+                "key".into(),
+                "example.de",
+            )
+            .unwrap()
+    }
+
+    fn cached(path: &std::path::Path) -> Option<String> {
+        let cache = SessionCache { path: path.into() };
+        cache.load("12345", "key").unwrap()
+    }
+
+    #[test]
+    fn the_invalid_session_answer_is_invalid() {
+        let mock = MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_raw(200, include_str!("../tests/fixtures/invalid_session.json"));
+
+        let validity = client.verify_session("example.de").unwrap();
+
+        assert!(
+            matches!(
+                &validity,
+                SessionValidity::Invalid(NetcupError::AuthenticationFailed {
+                    status_code: 4001,
+                    ..
+                })
+            ),
+            "{:?}",
+            validity
+        );
+    }
+
+    #[test]
+    fn other_answers_dont_invalidate_the_session() {
+        let mock = MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_success("infoDnsZone", json!({ "name": "example.de" }))
+            .respond_error("infoDnsZone", 5029, "Domain not found")
+            .respond_error("infoDnsZone", 4013, "more than 180 requests per minute")
+            .fail("connection reset");
+
+        let validity: Vec<_> = (0..4)
+            .map(|_| client.verify_session("example.de").unwrap())
+            .collect();
+
+        assert!(validity[0].is_valid());
+        assert!(validity[1].is_valid());
+        assert!(matches!(validity[2], SessionValidity::Indeterminate(_)));
+        assert!(matches!(validity[3], SessionValidity::Indeterminate(_)));
+    }
+
+    #[test]
+    fn a_valid_cached_session_is_resumed() {
+        let path = testing::temp_dir("session_valid").join("session.json");
+        let cache = SessionCache { path: path.clone() };
+        cache.store("12345", "key", "cached").unwrap();
+        let mock = MockTransport::new();
+        mock.respond_success("infoDnsZone", json!({ "name": "example.de" }));
+
+        drop(resume(&mock, &path));
+
+        assert_eq!(mock.actions(), ["infoDnsZone"]);
+        assert_eq!(mock.requests()[0]["param"]["apisessionid"], "cached");
+        assert_eq!(cached(&path).as_deref(), Some("cached"));
+    }
+
+    #[test]
+    fn an_expired_session_falls_back_to_a_login() {
+        let path = testing::temp_dir("session_expired").join("session.json");
+        let cache = SessionCache { path: path.clone() };
+        cache.store("12345", "key", "expired").unwrap();
+        let mock = MockTransport::new();
+        mock.respond_error(
+            "infoDnsZone",
+            4001,
+            "The session id is not in a valid format.",
+        )
+        .respond_success("login", json!({ "apisessionid": "fresh" }));
+
+        drop(resume(&mock, &path));
+
+        assert_eq!(mock.actions(), ["infoDnsZone", "login"]);
+        assert_eq!(cached(&path).as_deref(), Some("fresh"));
+    }
+
+    #[test]
+    fn another_api_key_doesnt_get_the_session() {
+        let path = testing::temp_dir("session_key").join("session.json");
+        let cache = SessionCache { path: path.clone() };
+        cache.store("12345", "old key", "cached").unwrap();
+
+        assert_eq!(cached(&path), None);
+    }
+
+    #[test]
+    fn a_corrupt_or_unreadable_cache_only_costs_a_login() {
+        let dir = testing::temp_dir("session_corrupt");
+        let corrupt = dir.join("session.json");
+        fs::write(&corrupt, "{ not json").unwrap();
+        // A directory can't be opened as the cache file
+        let unreadable = dir.join("directory");
+        fs::create_dir(&unreadable).unwrap();
+
+        for path in [corrupt, unreadable] {
+            let mock = MockTransport::new();
+            mock.respond_success("login", json!({ "apisessionid": "fresh" }));
+
+            drop(resume(&mock, &path));
+
+            assert_eq!(mock.actions(), ["login"]);
+        }
+        assert_eq!(cached(&dir.join("session.json")).as_deref(), Some("fresh"));
+    }
+}
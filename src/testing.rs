@@ -0,0 +1,278 @@
+//! Helpers for testing code built on the client without reaching netcup,
+//! behind the `test-util` feature.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    env, fs,
+    net::IpAddr,
+    path::PathBuf,
+    process,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use anyhow::anyhow;
+use serde_json::{json, Value};
+
+use crate::{
+    NetcupAPIClient, NetcupAPIClientBuilder, NetcupError, NetcupTransport, Resolver, RetryPolicy,
+    TransportResponse,
+};
+
+/// A [`NetcupTransport`] that answers from a script and records what was
+/// sent. Clones share both, so a test keeps one to inspect after handing the
+/// other to [`NetcupAPIClientBuilder::transport`](crate::NetcupAPIClientBuilder::transport).
+///
+/// Combine it with [`RetryPolicy::none`](crate::RetryPolicy::none) to script
+/// failures without the client retrying them.
+#[derive(Debug, Clone, Default)]
+pub struct MockTransport {
+    state: Arc<Mutex<MockState>>,
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    responses: VecDeque<Scripted>,
+    requests: Vec<Value>,
+    sent_at: Vec<Instant>,
+}
+
+#[derive(Debug)]
+enum Scripted {
+    /// Answered with the request's `clientrequestid`, as netcup does
+    Envelope(Value),
+    Raw(TransportResponse),
+    Failure(String),
+    Unreachable(String),
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues a `success` response to `action` carrying `responsedata`.
+    pub fn respond_success(&self, action: &str, response_data: Value) -> &Self {
+        self.push(Scripted::Envelope(envelope(
+            action,
+            "success",
+            2000,
+            "Action successful",
+            response_data,
+        )))
+    }
+
+    /// Queues an `error` response, as netcup sends for e.g. rejected
+    /// credentials (4013) or unknown domains (5029).
+    pub fn respond_error(&self, action: &str, status_code: u32, message: &str) -> &Self {
+        self.push(Scripted::Envelope(envelope(
+            action,
+            "error",
+            status_code,
+            message,
+            json!(""),
+        )))
+    }
+
+    /// Queues an HTTP response as it is, e.g. a malformed body, a 503 or an
+    /// envelope with another `clientrequestid`.
+    pub fn respond_raw(&self, status: u16, body: impl Into<String>) -> &Self {
+        self.push(Scripted::Raw(TransportResponse {
+            status,
+            body: body.into(),
+        }))
+    }
+
+    /// Queues a request that gets no response at all, e.g. one that timed
+    /// out after netcup may have applied it.
+    pub fn fail(&self, message: impl Into<String>) -> &Self {
+        self.push(Scripted::Failure(message.into()))
+    }
+
+    /// Queues a request for which no connection could be made, as a
+    /// [`NetcupError::Connect`](crate::NetcupError::Connect).
+    pub fn fail_to_connect(&self, message: impl Into<String>) -> &Self {
+        self.push(Scripted::Unreachable(message.into()))
+    }
+
+    /// The request bodies sent so far, in order. Bodies that aren't JSON are
+    /// recorded as strings.
+    pub fn requests(&self) -> Vec<Value> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// When each request of [`requests`](Self::requests) was sent.
+    pub fn sent_at(&self) -> Vec<Instant> {
+        self.state.lock().unwrap().sent_at.clone()
+    }
+
+    /// The `action` of every request sent so far, in order.
+    pub fn actions(&self) -> Vec<String> {
+        self.requests()
+            .iter()
+            .map(|request| request["action"].as_str().unwrap_or_default().into())
+            .collect()
+    }
+
+    /// How many scripted responses haven't been used yet.
+    pub fn remaining(&self) -> usize {
+        self.state.lock().unwrap().responses.len()
+    }
+
+    fn push(&self, response: Scripted) -> &Self {
+        self.state.lock().unwrap().responses.push_back(response);
+        self
+    }
+}
+
+impl NetcupTransport for MockTransport {
+    fn post(&self, _endpoint: &str, body: String) -> anyhow::Result<TransportResponse> {
+        let request: Value = serde_json::from_str(&body).unwrap_or(Value::String(body));
+        let action = request["action"].as_str().unwrap_or_default().to_string();
+        let client_request_id = request["param"]["clientrequestid"].clone();
+
+        let mut state = self.state.lock().unwrap();
+        state.requests.push(request);
+        state.sent_at.push(Instant::now());
+        match state.responses.pop_front() {
+            Some(Scripted::Envelope(mut envelope)) => {
+                envelope["clientrequestid"] = client_request_id;
+                Ok(TransportResponse {
+                    status: 200,
+                    body: envelope.to_string(),
+                })
+            }
+            Some(Scripted::Raw(response)) => Ok(response),
+            Some(Scripted::Failure(message)) => Err(anyhow!(message)),
+            Some(Scripted::Unreachable(message)) => {
+                Err(anyhow!("Connection refused").context(NetcupError::Connect { message }))
+            }
+            None => Err(anyhow!("No scripted response left for {}", action)),
+        }
+    }
+}
+
+fn envelope(
+    action: &str,
+    status: &str,
+    status_code: u32,
+    message: &str,
+    response_data: Value,
+) -> Value {
+    json!({
+        "serverrequestid": "mock",
+        "clientrequestid": "",
+        "action": action,
+        "status": status,
+        "statuscode": status_code,
+        "shortmessage": message,
+        "longmessage": "",
+        "responsedata": response_data,
+    })
+}
+
+/// A builder for tests: no retries and no rate limit.
+pub fn builder() -> NetcupAPIClientBuilder {
+    NetcupAPIClient::builder()
+        .retry_policy(RetryPolicy::none())
+        .rate_limit(u32::MAX)
+}
+
+/// Logs in through `mock` with a scripted session, `session`.
+pub fn login(mock: &MockTransport, builder: NetcupAPIClientBuilder) -> NetcupAPIClient {
+    mock.respond_success("login", json!({ "apisessionid": "session" }));
+    builder
+        .transport(mock.clone())
+        .login("12345".into(), "password".into(), "key".into())
+        .unwrap()
+}
+
+/// A TXT record at `_acme-challenge` as `infoDnsRecords` lists it.
+pub fn txt(id: u32, content: &str) -> Value {
+    json!({
+        "id": id.to_string(),
+        "hostname": "_acme-challenge",
+        "type": "TXT",
+        "priority": "0",
+        "destination": content,
+        "deleterecord": false,
+        "state": "yes",
+    })
+}
+
+/// An empty directory of its own under the system's temp dir, for state
+/// files. Named after the process and `name`, so tests running in parallel
+/// don't share one.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir()
+        .join(format!("truenas-acme-test-{}", process::id()))
+        .join(name);
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A [`Resolver`] answering from fixed nameservers, TXT values and CNAMEs
+/// instead of the network. Names are given as the resolver is asked for
+/// them, lowercase without the trailing dot.
+#[derive(Debug, Default)]
+pub struct StubResolver {
+    nameservers: HashMap<String, Vec<(String, IpAddr)>>,
+    txt: HashMap<(IpAddr, String), Result<Vec<String>, String>>,
+    cnames: HashMap<String, String>,
+}
+
+impl StubResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an authoritative nameserver of `zone`.
+    pub fn nameserver(mut self, zone: &str, name: &str, address: IpAddr) -> Self {
+        self.nameservers
+            .entry(zone.into())
+            .or_default()
+            .push((name.into(), address));
+        self
+    }
+
+    /// What `server` answers for the TXT records of `name`; a name it isn't
+    /// given for has none.
+    pub fn txt(mut self, server: IpAddr, name: &str, values: &[&str]) -> Self {
+        let values = values.iter().map(|value| value.to_string()).collect();
+        self.txt.insert((server, name.into()), Ok(values));
+        self
+    }
+
+    /// Makes the query of `server` for `name` fail, e.g. on a timeout.
+    pub fn txt_failure(mut self, server: IpAddr, name: &str, message: &str) -> Self {
+        self.txt.insert((server, name.into()), Err(message.into()));
+        self
+    }
+
+    pub fn cname(mut self, name: &str, target: &str) -> Self {
+        self.cnames.insert(name.into(), target.into());
+        self
+    }
+}
+
+impl Resolver for StubResolver {
+    fn nameservers(&self, zone: &str) -> anyhow::Result<Vec<(String, IpAddr)>> {
+        self.nameservers
+            .get(zone)
+            .cloned()
+            .ok_or_else(|| anyhow!("No nameservers for {}", zone))
+    }
+
+    fn txt(&self, server: IpAddr, name: &str) -> anyhow::Result<Vec<String>> {
+        match self.txt.get(&(server, name.to_string())) {
+            Some(Ok(values)) => Ok(values.clone()),
+            Some(Err(message)) => Err(anyhow!("{}", message)),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    fn cname(&self, name: &str) -> anyhow::Result<Option<String>> {
+        Ok(self.cnames.get(name).cloned())
+    }
+}
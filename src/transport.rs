@@ -0,0 +1,77 @@
+//! The HTTP stack underneath [`Http`](crate::http::Http): reqwest's blocking
+//! client natively, wasi-http's outgoing handler on `wasm32-wasip2`, or any
+//! other [`NetcupTransport`] given to the builder.
+
+use std::{fmt, time::Duration};
+
+use crate::{AddressFamily, NetcupError};
+
+#[cfg(not(target_family = "wasm"))]
+mod native;
+#[cfg(not(target_family = "wasm"))]
+mod proxy;
+#[cfg(all(target_os = "wasi", feature = "wasi-http"))]
+mod wasi;
+
+#[cfg(not(target_family = "wasm"))]
+pub(crate) use native::Transport;
+#[cfg(all(target_os = "wasi", feature = "wasi-http"))]
+pub(crate) use wasi::Transport;
+
+#[cfg(all(target_family = "wasm", not(feature = "wasi-http")))]
+compile_error!("on wasm the `wasi-http` feature is required for sending requests");
+
+/// Sends a request body to the endpoint and returns the HTTP response. The
+/// client serializes the payloads, parses the responses and applies rate
+/// limiting and retries on top, so an implementation only moves bytes; e.g.
+/// `testing::MockTransport` (feature `test-util`) answers from a script.
+///
+/// Failures to get a response at all are errors, HTTP error statuses are
+/// not. A [`NetcupError::Timeout`] or [`NetcupError::Connect`] as the
+/// error's outermost context is reported as such, anything else as a
+/// [`NetcupError::Transport`]. Only `Connect` tells the client that a change
+/// can't have been applied, so it is the only failure after which changes
+/// are sent again.
+///
+/// The client is blocking whichever transport it uses, so an async
+/// application (e.g. on tokio) calls it from a blocking task, such as
+/// `tokio::task::spawn_blocking`, and may pass a transport wrapping its own
+/// HTTP client to share connection pools and settings.
+pub trait NetcupTransport: fmt::Debug + Send + Sync {
+    fn post(&self, endpoint: &str, body: String) -> anyhow::Result<TransportResponse>;
+}
+
+/// What every request of a client is sent with.
+#[derive(Debug)]
+pub(crate) struct TransportOptions<'a> {
+    pub(crate) user_agent: &'a str,
+    pub(crate) headers: &'a [(String, String)],
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) connect_timeout: Option<Duration>,
+    pub(crate) family: Option<AddressFamily>,
+    /// Instead of the proxy from the environment
+    pub(crate) proxy: Option<&'a str>,
+}
+
+/// The error for a request that never reached the API, because the
+/// connection (to it or the proxy) could not be established.
+fn not_connected(err: impl Into<anyhow::Error>, message: impl Into<String>) -> anyhow::Error {
+    err.into().context(NetcupError::Connect {
+        message: message.into(),
+    })
+}
+
+/// The error for a request that ran into `timeout`.
+fn timed_out(err: impl Into<anyhow::Error>, timeout: Option<Duration>) -> anyhow::Error {
+    let message = match timeout {
+        Some(timeout) => format!("The API did not answer within {:?}", timeout),
+        None => "The API did not answer in time".into(),
+    };
+    err.into().context(NetcupError::Timeout { message })
+}
+
+#[derive(Debug, Clone)]
+pub struct TransportResponse {
+    pub status: u16,
+    pub body: String,
+}
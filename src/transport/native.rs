@@ -0,0 +1,301 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use reqwest::{
+    blocking::Client,
+    header::{HeaderMap, HeaderName, HeaderValue},
+    Proxy, Url,
+};
+
+use super::{
+    not_connected,
+    proxy::{self, Proxies},
+    timed_out, NetcupTransport, TransportOptions, TransportResponse,
+};
+
+#[derive(Debug)]
+pub(crate) struct Transport {
+    client: Client,
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    proxies: Proxies,
+}
+
+impl Transport {
+    pub(crate) fn new(options: &TransportOptions) -> anyhow::Result<Self> {
+        let mut headers = HeaderMap::new();
+
+        for (name, value) in options.headers {
+            let name = HeaderName::from_bytes(name.as_bytes())
+                .context(format!("Invalid header name '{}'", name))?;
+            let value = HeaderValue::from_str(value)
+                .context(format!("Invalid value for header '{}'", name))?;
+
+            headers.append(name, value);
+        }
+
+        let mut client = Client::builder()
+            .user_agent(options.user_agent)
+            .default_headers(headers);
+
+        #[cfg(feature = "tls-native")]
+        {
+            client = client.use_native_tls();
+        }
+        #[cfg(feature = "tls-rustls")]
+        {
+            client = client.use_rustls_tls();
+        }
+
+        // A socket bound to 0.0.0.0 can't reach IPv6 addresses and vice versa
+        if let Some(family) = options.family {
+            client = client.local_address(family.unspecified());
+        }
+
+        if let Some(timeout) = options.timeout {
+            client = client.timeout(timeout);
+        }
+        if let Some(timeout) = options.connect_timeout {
+            client = client.connect_timeout(timeout);
+        }
+
+        let proxies = Proxies::new(options.proxy)?;
+        let selection = proxies.clone();
+        client = client.proxy(Proxy::custom(move |url| selection.for_url(url).cloned()));
+
+        Ok(Self {
+            client: client.build()?,
+            timeout: options.timeout,
+            connect_timeout: options.connect_timeout,
+            proxies,
+        })
+    }
+
+    fn error(&self, url: &str, err: reqwest::Error) -> anyhow::Error {
+        let err = if err.is_connect() {
+            let message = match (err.is_timeout(), self.connect_timeout.or(self.timeout)) {
+                (true, Some(timeout)) => {
+                    format!("Could not connect to the API within {:?}", timeout)
+                }
+                _ => "Could not connect to the API".into(),
+            };
+            not_connected(err, message)
+        } else if err.is_timeout() {
+            timed_out(err, self.timeout)
+        } else {
+            err.into()
+        };
+
+        let proxy = Url::parse(url)
+            .ok()
+            .and_then(|url| self.proxies.for_url(&url).map(proxy::redacted));
+        match proxy {
+            Some(proxy) => err.context(format!("Sent through the proxy {}", proxy)),
+            None => err,
+        }
+    }
+}
+
+impl NetcupTransport for Transport {
+    fn post(&self, url: &str, body: String) -> anyhow::Result<TransportResponse> {
+        let response = self
+            .client
+            .post(url)
+            .body(body)
+            .send()
+            .map_err(|err| self.error(url, err))?;
+
+        Ok(TransportResponse {
+            status: response.status().as_u16(),
+            body: response.text().map_err(|err| self.error(url, err))?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        thread,
+        time::Instant,
+    };
+
+    use serde_json::{json, Value};
+
+    use super::*;
+    use crate::{testing, NetcupError, DEFAULT_USER_AGENT};
+
+    fn options() -> TransportOptions<'static> {
+        TransportOptions {
+            user_agent: DEFAULT_USER_AGENT,
+            headers: &[],
+            timeout: None,
+            connect_timeout: None,
+            family: None,
+            proxy: None,
+        }
+    }
+
+    /// Answers `calls` requests with success, one connection each, and
+    /// returns the action and lowercase headers of each.
+    fn serve(listener: TcpListener, calls: usize) -> Vec<(String, Vec<(String, String)>)> {
+        (0..calls)
+            .map(|_| {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+                let mut headers = Vec::new();
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                loop {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    match line.trim_end().split_once(':') {
+                        Some((name, value)) => {
+                            headers.push((name.to_ascii_lowercase(), value.trim().to_string()))
+                        }
+                        None => break,
+                    }
+                }
+
+                let length: usize = headers
+                    .iter()
+                    .find(|(name, _)| name == "content-length")
+                    .map_or(0, |(_, value)| value.parse().unwrap());
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                let request: Value = serde_json::from_slice(&body).unwrap();
+                let action = request["action"].as_str().unwrap().to_string();
+
+                let response = json!({
+                    "serverrequestid": "server",
+                    "clientrequestid": "",
+                    "action": action,
+                    "status": "success",
+                    "statuscode": 2000,
+                    "shortmessage": "",
+                    "longmessage": "",
+                    "responsedata": match action.as_str() {
+                        "login" => json!({ "apisessionid": "session" }),
+                        "infoDnsRecords" => json!({ "dnsrecords": [] }),
+                        _ => json!(""),
+                    },
+                })
+                .to_string();
+                write!(
+                    &stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    response.len(),
+                    response
+                )
+                .unwrap();
+
+                (action, headers)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn user_agent_and_headers_go_with_login_calls_and_logout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}/endpoint.php", listener.local_addr().unwrap());
+        let server = thread::spawn(move || serve(listener, 3));
+
+        let client = testing::builder()
+            .endpoint(endpoint)
+            .user_agent("renewal-bot/2.0")
+            .header("X-Tenant", "lab")
+            .login("12345".into(), "password".into(), "key".into())
+            .unwrap();
+        client.list_records("example.de").unwrap();
+        client.logout().unwrap();
+
+        let requests = server.join().unwrap();
+        let actions: Vec<_> = requests.iter().map(|(action, _)| action.as_str()).collect();
+        assert_eq!(actions, ["login", "infoDnsRecords", "logout"]);
+        for (action, headers) in &requests {
+            let header = |name: &str| {
+                headers
+                    .iter()
+                    .find(|(header, _)| header == name)
+                    .map(|(_, value)| value.as_str())
+            };
+            assert_eq!(header("user-agent"), Some("renewal-bot/2.0"), "{}", action);
+            assert_eq!(header("x-tenant"), Some("lab"), "{}", action);
+        }
+    }
+
+    #[test]
+    fn a_non_routable_endpoint_fails_quickly() {
+        let start = Instant::now();
+
+        let err = testing::builder()
+            .endpoint("http://10.255.255.1/endpoint.php")
+            .connect_timeout(Duration::from_secs(1))
+            .timeout(Duration::from_secs(1))
+            .login("12345".into(), "password".into(), "key".into())
+            .err()
+            .unwrap();
+
+        assert!(
+            start.elapsed() < Duration::from_secs(5),
+            "{:?}",
+            start.elapsed()
+        );
+        // Usually a connect timeout, but where there is no route at all, or
+        // the network refuses it, it fails even sooner
+        assert!(
+            matches!(
+                err.downcast_ref(),
+                Some(
+                    NetcupError::Connect { .. }
+                        | NetcupError::Timeout { .. }
+                        | NetcupError::Transport { .. }
+                )
+            ),
+            "{:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn an_endpoint_that_never_answers_times_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}/endpoint.php", listener.local_addr().unwrap());
+        // Accepts the connection but never answers
+        let server = thread::spawn(move || listener.accept().unwrap());
+
+        let err = testing::builder()
+            .endpoint(endpoint)
+            .timeout(Duration::from_secs(1))
+            .login("12345".into(), "password".into(), "key".into())
+            .err()
+            .unwrap();
+
+        assert!(
+            matches!(err.downcast_ref(), Some(NetcupError::Timeout { .. })),
+            "{:?}",
+            err
+        );
+        drop(server.join().unwrap());
+    }
+
+    #[cfg(feature = "tls-native")]
+    #[test]
+    fn builds_with_native_tls() {
+        Transport::new(&options()).unwrap();
+    }
+
+    #[cfg(feature = "tls-rustls-webpki-roots")]
+    #[test]
+    fn builds_with_rustls_and_webpki_roots() {
+        Transport::new(&options()).unwrap();
+    }
+
+    #[cfg(feature = "tls-rustls-native-roots")]
+    #[test]
+    fn builds_with_rustls_and_native_roots() {
+        Transport::new(&options()).unwrap();
+    }
+}
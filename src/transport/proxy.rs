@@ -0,0 +1,204 @@
+//! Which proxy a request goes through, decided here rather than by reqwest
+//! so errors can name it.
+
+use std::env;
+
+use anyhow::{anyhow, bail};
+use reqwest::Url;
+
+/// The proxies of a client, read once when it is built.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Proxies {
+    /// Given to the builder, used for every request
+    explicit: Option<Url>,
+    https: Option<Url>,
+    http: Option<Url>,
+    /// `NO_PROXY`, only applied to the proxies from the environment
+    no_proxy: Vec<String>,
+}
+
+impl Proxies {
+    /// `explicit` if set, otherwise `HTTPS_PROXY` for https and `HTTP_PROXY`
+    /// for http URLs, both falling back to `ALL_PROXY`, in upper or lower
+    /// case.
+    pub(crate) fn new(explicit: Option<&str>) -> anyhow::Result<Self> {
+        Self::with_env(explicit, |name| env::var(name).ok())
+    }
+
+    /// [`new`](Self::new), reading the environment through `var`.
+    fn with_env(
+        explicit: Option<&str>,
+        var: impl Fn(&str) -> Option<String>,
+    ) -> anyhow::Result<Self> {
+        let explicit = explicit
+            .map(|proxy| parse(proxy, "the proxy"))
+            .transpose()?;
+
+        let from_env = |names: &[&str]| -> anyhow::Result<Option<Url>> {
+            for name in names {
+                for name in [name.to_string(), name.to_lowercase()] {
+                    if let Some(proxy) = var(&name).filter(|value| !value.is_empty()) {
+                        return parse(&proxy, &name).map(Some);
+                    }
+                }
+            }
+            Ok(None)
+        };
+
+        Ok(Self {
+            explicit,
+            https: from_env(&["HTTPS_PROXY", "ALL_PROXY"])?,
+            http: from_env(&["HTTP_PROXY", "ALL_PROXY"])?,
+            no_proxy: var("NO_PROXY")
+                .or_else(|| var("no_proxy"))
+                .unwrap_or_default()
+                .split(',')
+                .map(|entry| entry.trim().trim_start_matches('.').to_ascii_lowercase())
+                .filter(|entry| !entry.is_empty())
+                .collect(),
+        })
+    }
+
+    /// The proxy for requests to `url`, if any.
+    pub(crate) fn for_url(&self, url: &Url) -> Option<&Url> {
+        if let Some(explicit) = &self.explicit {
+            return Some(explicit);
+        }
+
+        let host = url.host_str()?.to_ascii_lowercase();
+        let excluded = self
+            .no_proxy
+            .iter()
+            .any(|entry| entry == "*" || host == *entry || host.ends_with(&format!(".{}", entry)));
+        if excluded {
+            return None;
+        }
+
+        match url.scheme() {
+            "https" => self.https.as_ref(),
+            "http" => self.http.as_ref(),
+            _ => None,
+        }
+    }
+}
+
+/// Reads a proxy URL; like curl, `proxy:3128` means `http://proxy:3128`.
+fn parse(proxy: &str, source: &str) -> anyhow::Result<Url> {
+    let url = match Url::parse(proxy) {
+        Ok(url) if url.has_host() => Ok(url),
+        // Has a scheme already, prefixing one would misread it as the host
+        Err(err) if proxy.contains("://") => Err(err),
+        _ => Url::parse(&format!("http://{}", proxy)),
+    }
+    .map_err(|err| {
+        anyhow!(
+            "Invalid URL in {}, {}: {}",
+            source,
+            redacted_str(proxy),
+            err
+        )
+    })?;
+
+    if !matches!(url.scheme(), "http" | "https") {
+        bail!(
+            "Unsupported scheme in {}, {}: only http and https proxies are supported",
+            source,
+            redacted(&url)
+        );
+    }
+
+    Ok(url)
+}
+
+/// The proxy URL with any credentials masked, for messages and logs.
+pub(crate) fn redacted(url: &Url) -> String {
+    let mut url = url.clone();
+    if !url.username().is_empty() || url.password().is_some() {
+        let _ = url.set_username("***");
+        let _ = url.set_password(None);
+    }
+    url.to_string()
+}
+
+/// Like [`redacted`], for a value that didn't parse.
+fn redacted_str(proxy: &str) -> String {
+    match proxy.rsplit_once('@') {
+        Some((_, host)) => format!("***@{}", host),
+        None => proxy.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proxies(explicit: Option<&str>, vars: &[(&str, &str)]) -> anyhow::Result<Proxies> {
+        Proxies::with_env(explicit, |name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| value.to_string())
+        })
+    }
+
+    fn proxy_for(proxies: &Proxies, url: &str) -> Option<String> {
+        proxies
+            .for_url(&Url::parse(url).unwrap())
+            .map(|proxy| proxy.to_string())
+    }
+
+    const API: &str = "https://ccp.netcup.net/run/webservice/servers/endpoint.php";
+
+    #[test]
+    fn explicit_proxy_beats_the_environment() {
+        let vars = [
+            ("HTTPS_PROXY", "http://env:3128"),
+            ("NO_PROXY", "netcup.net"),
+        ];
+
+        let from_env = proxies(None, &vars).unwrap();
+        assert_eq!(
+            proxy_for(&from_env, "https://example.de"),
+            Some("http://env:3128/".into())
+        );
+
+        let explicit = proxies(Some("proxy:8080"), &vars).unwrap();
+        assert_eq!(proxy_for(&explicit, API), Some("http://proxy:8080/".into()));
+    }
+
+    #[test]
+    fn no_proxy_excludes_hosts_and_their_subdomains() {
+        let proxies = proxies(
+            None,
+            &[
+                ("https_proxy", "http://env:3128"),
+                ("no_proxy", " .netcup.net, localhost"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(proxy_for(&proxies, API), None);
+        assert_eq!(proxy_for(&proxies, "https://netcup.net"), None);
+        assert_eq!(proxy_for(&proxies, "https://localhost"), None);
+        assert_eq!(
+            proxy_for(&proxies, "https://notnetcup.net"),
+            Some("http://env:3128/".into())
+        );
+    }
+
+    #[test]
+    fn invalid_proxies_are_errors_without_their_credentials() {
+        let err = proxies(Some("http://user:pw@[::1"), &[]).unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("Invalid URL in the proxy, ***@[::1"),
+            "{}",
+            err
+        );
+
+        let err = proxies(None, &[("ALL_PROXY", "socks5://user:pw@proxy:1080")]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unsupported scheme in ALL_PROXY, socks5://***@proxy:1080: only http and https proxies are supported"
+        );
+    }
+}
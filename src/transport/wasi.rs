@@ -0,0 +1,171 @@
+use std::{
+    io::{Read, Write},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context};
+use wasip2::{
+    http::{
+        outgoing_handler,
+        types::{ErrorCode, Fields, Method, OutgoingBody, OutgoingRequest, RequestOptions, Scheme},
+    },
+    io::streams::StreamError,
+};
+
+use super::{not_connected, timed_out, NetcupTransport, TransportOptions, TransportResponse};
+
+/// Sends requests through the host's `wasi:http/outgoing-handler`, TLS is
+/// up to the host.
+#[derive(Debug)]
+pub(crate) struct Transport {
+    headers: Vec<(String, Vec<u8>)>,
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+}
+
+impl Transport {
+    pub(crate) fn new(options: &TransportOptions) -> anyhow::Result<Self> {
+        if options.family.is_some() {
+            anyhow::bail!("wasi-http doesn't support choosing the address family");
+        }
+        if options.proxy.is_some() {
+            anyhow::bail!("wasi-http doesn't support proxies, the host sends the requests");
+        }
+
+        let mut headers = vec![("user-agent".to_string(), options.user_agent.into())];
+        headers.extend(
+            options
+                .headers
+                .iter()
+                .map(|(name, value)| (name.to_lowercase(), value.as_bytes().to_vec())),
+        );
+
+        // Reports invalid names and values now rather than on every request
+        Fields::from_list(&headers).map_err(|err| anyhow!("Invalid header: {:?}", err))?;
+
+        Ok(Self {
+            headers,
+            timeout: options.timeout,
+            connect_timeout: options.connect_timeout,
+        })
+    }
+
+    fn request_failed(&self, err: ErrorCode) -> anyhow::Error {
+        let timed_out_after = match err {
+            ErrorCode::DnsTimeout
+            | ErrorCode::DnsError(_)
+            | ErrorCode::DestinationNotFound
+            | ErrorCode::DestinationUnavailable
+            | ErrorCode::ConnectionRefused
+            | ErrorCode::ConnectionTimeout => {
+                return not_connected(
+                    anyhow!("Request failed: {:?}", err),
+                    "Could not connect to the API",
+                )
+            }
+            ErrorCode::ConnectionReadTimeout
+            | ErrorCode::ConnectionWriteTimeout
+            | ErrorCode::HttpResponseTimeout => self.timeout,
+            _ => return anyhow!("Request failed: {:?}", err),
+        };
+        timed_out(anyhow!("Request failed: {:?}", err), timed_out_after)
+    }
+}
+
+impl NetcupTransport for Transport {
+    fn post(&self, url: &str, body: String) -> anyhow::Result<TransportResponse> {
+        let (scheme, rest) = url
+            .split_once("://")
+            .context(format!("Invalid endpoint URL '{}'", url))?;
+        let scheme = match scheme {
+            "https" => Scheme::Https,
+            "http" => Scheme::Http,
+            other => Scheme::Other(other.into()),
+        };
+        let (authority, path) = match rest.find(['/', '?']) {
+            Some(i) if rest[i..].starts_with('?') => (&rest[..i], format!("/{}", &rest[i..])),
+            Some(i) => (&rest[..i], rest[i..].to_string()),
+            None => (rest, "/".to_string()),
+        };
+
+        // Without a length the host sends the body chunked, which not every
+        // server accepts
+        let mut headers = self.headers.clone();
+        headers.push(("content-length".into(), body.len().to_string().into_bytes()));
+
+        let fields =
+            Fields::from_list(&headers).map_err(|err| anyhow!("Invalid header: {:?}", err))?;
+        let request = OutgoingRequest::new(fields);
+        let invalid = |part: &str| anyhow!("Invalid {} in endpoint URL '{}'", part, url);
+        request
+            .set_method(&Method::Post)
+            .map_err(|()| invalid("method"))?;
+        request
+            .set_scheme(Some(&scheme))
+            .map_err(|()| invalid("scheme"))?;
+        request
+            .set_authority(Some(authority))
+            .map_err(|()| invalid("host"))?;
+        request
+            .set_path_with_query(Some(&path))
+            .map_err(|()| invalid("path"))?;
+
+        let outgoing = request
+            .body()
+            .map_err(|()| anyhow!("Request body already taken"))?;
+
+        let nanos = |timeout: Duration| u64::try_from(timeout.as_nanos()).unwrap_or(u64::MAX);
+        let options = (self.timeout.is_some() || self.connect_timeout.is_some()).then(|| {
+            let options = RequestOptions::new();
+            // Hosts may not support every timeout, the others still apply
+            if let Some(timeout) = self.connect_timeout.or(self.timeout) {
+                let _ = options.set_connect_timeout(Some(nanos(timeout)));
+            }
+            if let Some(timeout) = self.timeout {
+                let _ = options.set_first_byte_timeout(Some(nanos(timeout)));
+                let _ = options.set_between_bytes_timeout(Some(nanos(timeout)));
+            }
+            options
+        });
+
+        let future =
+            outgoing_handler::handle(request, options).map_err(|err| self.request_failed(err))?;
+
+        // Written after handing the request over, so the host drains the body
+        {
+            let mut stream = outgoing
+                .write()
+                .map_err(|()| anyhow!("Request body already taken"))?;
+            stream.write_all(body.as_bytes())?;
+            // The host closes the stream once the announced length is written
+            match stream.blocking_flush() {
+                Ok(()) | Err(StreamError::Closed) => {}
+                Err(err) => return Err(anyhow!("Request failed: {:?}", err)),
+            }
+        }
+        OutgoingBody::finish(outgoing, None).map_err(|err| anyhow!("Request failed: {:?}", err))?;
+
+        future.subscribe().block();
+        let response = future
+            .get()
+            .context("No response after waiting for it")?
+            .map_err(|()| anyhow!("Response already taken"))?
+            .map_err(|err| self.request_failed(err))?;
+
+        let status = response.status();
+        let incoming = response
+            .consume()
+            .map_err(|()| anyhow!("Response body already taken"))?;
+
+        let mut body = Vec::new();
+        incoming
+            .stream()
+            .map_err(|()| anyhow!("Response body already taken"))?
+            .read_to_end(&mut body)?;
+
+        Ok(TransportResponse {
+            status,
+            body: String::from_utf8(body).context("The response is not valid UTF-8")?,
+        })
+    }
+}
@@ -0,0 +1,630 @@
+use anyhow::{anyhow, Context};
+
+use crate::{DNSRecord, DeletionPolicy, NetcupAPIClient, NetcupError, RecordType, ZoneChangeset};
+
+/// How [`NetcupAPIClient::upsert_record`] treats other records with the same
+/// hostname and type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpsertStrategy {
+    /// The record ends up as the only value at its hostname and type: an
+    /// existing record is updated in place and further ones are deleted.
+    /// Without `collapse_duplicates`, finding several existing records is an
+    /// error instead of a guess.
+    Replace { collapse_duplicates: bool },
+    /// Other values are preserved and the record is only created if it does
+    /// not exist yet. This is what multi-value types (TXT, MX, NS) usually
+    /// want, e.g. two ACME challenges at the same name.
+    Preserve,
+}
+
+/// What [`NetcupAPIClient::upsert_record`] did, with the id of the record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpsertOutcome {
+    Created { id: String },
+    Updated { id: String },
+    Unchanged { id: String },
+}
+
+impl UpsertOutcome {
+    pub fn id(&self) -> &str {
+        match self {
+            UpsertOutcome::Created { id }
+            | UpsertOutcome::Updated { id }
+            | UpsertOutcome::Unchanged { id } => id,
+        }
+    }
+}
+
+/// What [`NetcupAPIClient::add_txt_record`] did, with the record as it is
+/// in the zone.
+#[derive(Debug, Clone)]
+pub enum AddOutcome {
+    Created(DNSRecord),
+    /// The value was already there, e.g. from an earlier attempt of a
+    /// retried renewal, and nothing was submitted
+    AlreadyExisted(DNSRecord),
+}
+
+impl AddOutcome {
+    pub fn record(&self) -> &DNSRecord {
+        match self {
+            AddOutcome::Created(record) | AddOutcome::AlreadyExisted(record) => record,
+        }
+    }
+
+    pub fn into_record(self) -> DNSRecord {
+        match self {
+            AddOutcome::Created(record) | AddOutcome::AlreadyExisted(record) => record,
+        }
+    }
+
+    pub fn is_created(&self) -> bool {
+        matches!(self, AddOutcome::Created(_))
+    }
+}
+
+/// What [`NetcupAPIClient::remove_txt_record`] did.
+#[derive(Debug, Clone)]
+pub enum RemoveOutcome {
+    /// The deleted records, one unless `all` was set
+    Removed(Vec<DNSRecord>),
+    /// No record had the value, nothing was submitted
+    NotFound,
+}
+
+impl RemoveOutcome {
+    /// The deleted records, empty for [`NotFound`](RemoveOutcome::NotFound).
+    pub fn removed(&self) -> &[DNSRecord] {
+        match self {
+            RemoveOutcome::Removed(records) => records,
+            RemoveOutcome::NotFound => &[],
+        }
+    }
+}
+
+impl NetcupAPIClient {
+    /// Adds a TXT record unless the same value already exists at
+    /// `hostname`, so retrying doesn't leave duplicates that a cleanup
+    /// removes only one of. Other values at the name, e.g. the challenges
+    /// of a wildcard and its apex, are left alone.
+    /// [`force_add_txt_record`](Self::force_add_txt_record) always adds.
+    pub fn add_txt_record(
+        &self,
+        domain: &str,
+        hostname: &str,
+        content: &str,
+    ) -> anyhow::Result<AddOutcome> {
+        self.ensure_txt_records(domain, hostname, &[content])?
+            .pop()
+            .context("Netcup accepted the record but did not return it")
+    }
+
+    /// [`add_txt_record`](Self::add_txt_record) for several values at the
+    /// same name: those that don't exist yet are added in one
+    /// `updateDnsRecords` call, nothing is submitted if all of them do.
+    /// Returns the outcomes in the order of `contents`.
+    pub fn ensure_txt_records(
+        &self,
+        domain: &str,
+        hostname: &str,
+        contents: &[&str],
+    ) -> anyhow::Result<Vec<AddOutcome>> {
+        let existing = self.find_records(domain, hostname, RecordType::Txt)?;
+        let find = |content: &str| existing.iter().find(|r| r.has_destination(content));
+
+        let missing: Vec<&str> = contents
+            .iter()
+            .copied()
+            .filter(|content| find(content).is_none())
+            .collect();
+        let mut created = if missing.is_empty() {
+            Vec::new()
+        } else {
+            self.add_txt_records(domain, hostname, &missing)?
+        }
+        .into_iter();
+
+        contents
+            .iter()
+            .map(|content| match find(content) {
+                Some(record) => Ok(AddOutcome::AlreadyExisted(record.clone())),
+                None => created
+                    .next()
+                    .map(AddOutcome::Created)
+                    .context("Netcup accepted the records but did not return all of them"),
+            })
+            .collect()
+    }
+
+    /// Adds a TXT record even if the value already exists at `hostname`,
+    /// and returns it as created, with its id.
+    pub fn force_add_txt_record(
+        &self,
+        domain: &str,
+        hostname: &str,
+        content: &str,
+    ) -> anyhow::Result<DNSRecord> {
+        self.add_record(domain, DNSRecord::new(hostname, RecordType::Txt, content))
+    }
+
+    /// Deletes the TXT record at `hostname` with exactly this value, reading
+    /// the zone once. A value that doesn't exist is
+    /// [`RemoveOutcome::NotFound`], not an error. Several records with the
+    /// value are a [`NetcupError::AmbiguousRecord`] unless `all` is set,
+    /// which deletes each of them.
+    pub fn remove_txt_record(
+        &self,
+        domain: &str,
+        hostname: &str,
+        content: &str,
+        all: bool,
+    ) -> anyhow::Result<RemoveOutcome> {
+        let matching: Vec<_> = self
+            .find_records(domain, hostname, RecordType::Txt)?
+            .into_iter()
+            .filter(|r| r.has_destination(content))
+            .collect();
+
+        if matching.is_empty() {
+            return Ok(RemoveOutcome::NotFound);
+        }
+        if matching.len() > 1 && !all {
+            return Err(NetcupError::AmbiguousRecord {
+                domain: domain.into(),
+                hostname: hostname.into(),
+                content: content.into(),
+                ids: matching.iter().filter_map(|r| r.id.clone()).collect(),
+            }
+            .into());
+        }
+
+        let result = matching
+            .into_iter()
+            .fold(self.changeset(domain), ZoneChangeset::delete)
+            .commit()?;
+
+        Ok(RemoveOutcome::Removed(result.deleted))
+    }
+
+    /// Swaps the value of the TXT record at `hostname` for `new_content`
+    /// with one `updateDnsRecords` call, keeping its id, e.g. for an ACME
+    /// client that reissues a challenge with a new token. There is no
+    /// moment without a record, as there would be between an unset and a
+    /// set.
+    ///
+    /// The record is the one with `old_content`, or without it the only TXT
+    /// record at the name; several are a [`NetcupError::AmbiguousRecord`].
+    /// If there is none, the record is created, with an empty id in a dry
+    /// run.
+    pub fn replace_txt_record(
+        &self,
+        domain: &str,
+        hostname: &str,
+        old_content: Option<&str>,
+        new_content: &str,
+    ) -> anyhow::Result<UpsertOutcome> {
+        let mut matching: Vec<_> = self
+            .find_records(domain, hostname, RecordType::Txt)?
+            .into_iter()
+            .filter(|r| old_content.is_none_or(|content| r.has_destination(content)))
+            .collect();
+
+        if matching.len() > 1 {
+            return Err(NetcupError::AmbiguousRecord {
+                domain: domain.into(),
+                hostname: hostname.into(),
+                content: old_content.unwrap_or("(any value)").into(),
+                ids: matching.iter().filter_map(|r| r.id.clone()).collect(),
+            }
+            .into());
+        }
+
+        match matching.pop() {
+            None => self
+                .force_add_txt_record(domain, hostname, new_content)?
+                .id
+                // Netcup assigns it, so a dry run has none to report
+                .or_else(|| self.dry_run.then(String::new))
+                .context("Record has no id!")
+                .map(|id| UpsertOutcome::Created { id }),
+            Some(record) if record.has_destination(new_content) => {
+                let id = record.id.context("Record has no id!")?;
+                Ok(UpsertOutcome::Unchanged { id })
+            }
+            Some(record) => self
+                .update_record(domain, record.with_destination(new_content))?
+                .id
+                .context("Record has no id!")
+                .map(|id| UpsertOutcome::Updated { id }),
+        }
+    }
+
+    /// Makes sure `record` exists in the zone, keyed by hostname and type.
+    ///
+    /// All necessary changes are submitted in a single `updateDnsRecords`
+    /// call; nothing is submitted if the record is already in place. The
+    /// record is [`Unchanged`](UpsertOutcome::Unchanged) as well if only its
+    /// duplicates had to be deleted. A record created in a dry run has an
+    /// empty id.
+    pub fn upsert_record(
+        &self,
+        domain: &str,
+        record: DNSRecord,
+        strategy: UpsertStrategy,
+    ) -> anyhow::Result<UpsertOutcome> {
+        record.validate()?;
+
+        let existing: Vec<_> = self
+            .list_records(domain)?
+            .into_iter()
+            .filter(|r| r.hostname == record.hostname && r.record_type == record.record_type)
+            .collect();
+
+        let identical = existing.iter().position(|r| r.matches(&record));
+
+        let (changes, target) = match (strategy, identical) {
+            (UpsertStrategy::Preserve, Some(index)) => {
+                let id = existing[index].id.clone().context("Record has no id!")?;
+                return Ok(UpsertOutcome::Unchanged { id });
+            }
+            (UpsertStrategy::Preserve, None) => (vec![record.clone()], None),
+            (UpsertStrategy::Replace { .. }, _) if existing.is_empty() => {
+                (vec![record.clone()], None)
+            }
+            (
+                UpsertStrategy::Replace {
+                    collapse_duplicates,
+                },
+                _,
+            ) => {
+                if existing.len() > 1 && !collapse_duplicates {
+                    return Err(anyhow!(
+                        "Found {} {} records at {}, refusing to pick one",
+                        existing.len(),
+                        record.record_type,
+                        record.hostname
+                    ));
+                }
+
+                // Keep the identical record if there is one, otherwise update the first
+                let keep = identical.unwrap_or(0);
+                let id = existing[keep].id.clone().context("Record has no id!")?;
+
+                let mut changes: Vec<_> = existing
+                    .iter()
+                    .enumerate()
+                    .filter(|(index, _)| *index != keep)
+                    .map(|(_, r)| r.clone().into_deletion())
+                    .collect();
+
+                if identical.is_none() {
+                    changes.push(DNSRecord {
+                        id: Some(id.clone()),
+                        ..record.clone()
+                    });
+                }
+
+                // Only deleting duplicates leaves the record itself as it was
+                let outcome = match identical {
+                    Some(_) => UpsertOutcome::Unchanged { id },
+                    None => UpsertOutcome::Updated { id },
+                };
+                if changes.is_empty() {
+                    return Ok(outcome);
+                }
+
+                (changes, Some(outcome))
+            }
+        };
+
+        // Replacing an NS or apex MX record is what was asked for
+        let changeset = self.changeset(domain).policy(DeletionPolicy::AllowCritical);
+        let result = changes
+            .into_iter()
+            .fold(changeset, |changeset, change| {
+                if change.is_deletion() {
+                    changeset.delete(change)
+                } else {
+                    changeset.add(change)
+                }
+            })
+            .commit()?;
+
+        match target {
+            Some(outcome) => Ok(outcome),
+            None => {
+                let id = result
+                    .added
+                    .first()
+                    .and_then(|created| created.id.clone())
+                    // Netcup assigns it, so a dry run has none to report
+                    .or_else(|| self.dry_run.then(String::new))
+                    .context("Record has no id!")?;
+
+                Ok(UpsertOutcome::Created { id })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{
+        testing::{self, txt},
+        AddOutcome, DNSRecord, NetcupError, RecordType, RemoveOutcome, UpsertOutcome,
+        UpsertStrategy,
+    };
+
+    #[test]
+    fn existing_identical_value_is_not_added_again() {
+        let mock = testing::MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_success(
+            "infoDnsRecords",
+            json!({ "dnsrecords": [{
+                "id": "1",
+                "hostname": "_acme-challenge",
+                "type": "TXT",
+                "destination": "\"token\"",
+            }] }),
+        );
+
+        let outcome = client
+            .add_txt_record("example.com", "_acme-challenge", "token")
+            .unwrap();
+        assert!(matches!(outcome, AddOutcome::AlreadyExisted(_)));
+        assert_eq!(outcome.record().id(), Some("1"));
+        assert_eq!(mock.actions(), ["login", "infoDnsRecords"]);
+    }
+
+    #[test]
+    fn other_value_at_the_name_is_kept() {
+        let mock = testing::MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        let before = json!({ "dnsrecords": [txt(1, "wildcard")] });
+        mock.respond_success("infoDnsRecords", before.clone())
+            .respond_success("infoDnsRecords", before)
+            .respond_success(
+                "updateDnsRecords",
+                json!({ "dnsrecords": [txt(1, "wildcard"), txt(2, "apex")] }),
+            );
+
+        let outcome = client
+            .add_txt_record("example.com", "_acme-challenge", "apex")
+            .unwrap();
+        assert!(outcome.is_created());
+        assert_eq!(outcome.record().id(), Some("2"));
+
+        let sent = &mock.requests()[3]["param"]["dnsrecordset"]["dnsrecords"];
+        assert_eq!(sent.as_array().unwrap().len(), 1);
+        assert_eq!(sent[0]["destination"], "apex");
+    }
+
+    #[test]
+    fn empty_zone_gets_the_record() {
+        let mock = testing::MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_success("infoDnsRecords", json!({ "dnsrecords": [] }))
+            .respond_success("infoDnsRecords", json!({ "dnsrecords": [] }))
+            .respond_success(
+                "updateDnsRecords",
+                json!({ "dnsrecords": [txt(7, "token")] }),
+            );
+
+        let outcome = client
+            .add_txt_record("example.com", "_acme-challenge", "token")
+            .unwrap();
+        assert!(outcome.is_created());
+        assert_eq!(outcome.record().id(), Some("7"));
+    }
+
+    #[test]
+    fn only_missing_values_are_added() {
+        let mock = testing::MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        let before = json!({ "dnsrecords": [txt(1, "b")] });
+        mock.respond_success("infoDnsRecords", before.clone())
+            .respond_success("infoDnsRecords", before)
+            .respond_success(
+                "updateDnsRecords",
+                json!({ "dnsrecords": [txt(1, "b"), txt(2, "a"), txt(3, "c")] }),
+            );
+
+        let outcomes = client
+            .ensure_txt_records("example.com", "_acme-challenge", &["a", "b", "c"])
+            .unwrap();
+        let summary: Vec<_> = outcomes
+            .iter()
+            .map(|o| (o.is_created(), o.record().id().unwrap()))
+            .collect();
+        assert_eq!(summary, [(true, "2"), (false, "1"), (true, "3")]);
+
+        let sent = &mock.requests()[3]["param"]["dnsrecordset"]["dnsrecords"];
+        assert_eq!(sent.as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn dry_run_upsert_reports_the_creation_without_an_id() {
+        let mock = testing::MockTransport::new();
+        let client = testing::login(&mock, testing::builder().dry_run(true));
+        // The upsert, the changeset and the planned update each list the zone
+        for _ in 0..3 {
+            mock.respond_success("infoDnsRecords", json!({ "dnsrecords": [] }));
+        }
+
+        let outcome = client
+            .upsert_record(
+                "example.com",
+                DNSRecord::new("home", RecordType::A, "192.0.2.1"),
+                UpsertStrategy::Preserve,
+            )
+            .unwrap();
+
+        assert!(matches!(outcome, UpsertOutcome::Created { id } if id.is_empty()));
+        assert!(!mock
+            .actions()
+            .iter()
+            .any(|action| action == "updateDnsRecords"));
+    }
+
+    #[test]
+    fn replace_updates_the_record_in_place() {
+        let mock = testing::MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        let before = json!({ "dnsrecords": [txt(1, "old"), txt(2, "other")] });
+        mock.respond_success("infoDnsRecords", before)
+            .respond_success(
+                "updateDnsRecords",
+                json!({ "dnsrecords": [txt(1, "new"), txt(2, "other")] }),
+            );
+
+        let outcome = client
+            .replace_txt_record("example.com", "_acme-challenge", Some("old"), "new")
+            .unwrap();
+
+        assert_eq!(outcome, UpsertOutcome::Updated { id: "1".into() });
+        assert_eq!(
+            mock.actions(),
+            ["login", "infoDnsRecords", "updateDnsRecords"]
+        );
+        let sent = &mock.requests()[2]["param"]["dnsrecordset"]["dnsrecords"];
+        assert_eq!(sent.as_array().unwrap().len(), 1);
+        assert_eq!(sent[0]["id"], "1");
+        assert_eq!(sent[0]["destination"], "new");
+    }
+
+    #[test]
+    fn replace_creates_a_missing_record() {
+        let mock = testing::MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_success("infoDnsRecords", json!({ "dnsrecords": [] }))
+            .respond_success("infoDnsRecords", json!({ "dnsrecords": [] }))
+            .respond_success("updateDnsRecords", json!({ "dnsrecords": [txt(5, "new")] }));
+
+        let outcome = client
+            .replace_txt_record("example.com", "_acme-challenge", None, "new")
+            .unwrap();
+
+        assert_eq!(outcome, UpsertOutcome::Created { id: "5".into() });
+    }
+
+    #[test]
+    fn replace_refuses_to_pick_one_of_several_records() {
+        let mock = testing::MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_success(
+            "infoDnsRecords",
+            json!({ "dnsrecords": [txt(1, "a"), txt(2, "b")] }),
+        );
+
+        let err = client
+            .replace_txt_record("example.com", "_acme-challenge", None, "new")
+            .unwrap_err();
+
+        assert!(matches!(
+            err.downcast_ref::<NetcupError>(),
+            Some(NetcupError::AmbiguousRecord { ids, .. }) if ids == &["1", "2"]
+        ));
+        assert_eq!(mock.actions(), ["login", "infoDnsRecords"]);
+    }
+
+    #[test]
+    fn replace_strategy_only_deleting_duplicates_is_unchanged() {
+        let mock = testing::MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        let before = json!({ "dnsrecords": [txt(1, "other"), txt(2, "token")] });
+        mock.respond_success("infoDnsRecords", before)
+            .respond_success(
+                "updateDnsRecords",
+                json!({ "dnsrecords": [txt(2, "token")] }),
+            );
+
+        let outcome = client
+            .upsert_record(
+                "example.com",
+                DNSRecord::new("_acme-challenge", RecordType::Txt, "token"),
+                UpsertStrategy::Replace {
+                    collapse_duplicates: true,
+                },
+            )
+            .unwrap();
+
+        assert_eq!(outcome, UpsertOutcome::Unchanged { id: "2".into() });
+        assert_eq!(
+            mock.actions(),
+            ["login", "infoDnsRecords", "updateDnsRecords"]
+        );
+        let sent = &mock.requests()[2]["param"]["dnsrecordset"]["dnsrecords"];
+        assert_eq!(sent.as_array().unwrap().len(), 1);
+        assert_eq!(sent[0]["id"], "1");
+        assert_eq!(sent[0]["deleterecord"], true);
+    }
+
+    #[test]
+    fn remove_deletes_the_quoted_value() {
+        let mock = testing::MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_success(
+            "infoDnsRecords",
+            json!({ "dnsrecords": [txt(1, "\"token\""), txt(2, "other")] }),
+        )
+        .respond_success(
+            "updateDnsRecords",
+            json!({ "dnsrecords": [txt(2, "other")] }),
+        );
+
+        let outcome = client
+            .remove_txt_record("example.com", "_acme-challenge", "token", false)
+            .unwrap();
+
+        let ids: Vec<_> = outcome.removed().iter().map(|r| r.id().unwrap()).collect();
+        assert_eq!(ids, ["1"]);
+        let sent = &mock.requests()[2]["param"]["dnsrecordset"]["dnsrecords"];
+        assert_eq!(sent.as_array().unwrap().len(), 1);
+        assert_eq!(sent[0]["id"], "1");
+        assert_eq!(sent[0]["deleterecord"], true);
+    }
+
+    #[test]
+    fn remove_of_a_missing_value_submits_nothing() {
+        let mock = testing::MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_success("infoDnsRecords", json!({ "dnsrecords": [txt(2, "other")] }));
+
+        let outcome = client
+            .remove_txt_record("example.com", "_acme-challenge", "token", false)
+            .unwrap();
+
+        assert!(matches!(outcome, RemoveOutcome::NotFound));
+        assert_eq!(mock.actions(), ["login", "infoDnsRecords"]);
+    }
+
+    #[test]
+    fn remove_of_several_matches_needs_all() {
+        let mock = testing::MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        let before = json!({ "dnsrecords": [txt(1, "token"), txt(2, "token")] });
+        mock.respond_success("infoDnsRecords", before.clone());
+
+        let err = client
+            .remove_txt_record("example.com", "_acme-challenge", "token", false)
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<NetcupError>(),
+            Some(NetcupError::AmbiguousRecord { ids, .. }) if ids == &["1", "2"]
+        ));
+        assert_eq!(mock.actions(), ["login", "infoDnsRecords"]);
+
+        mock.respond_success("infoDnsRecords", before)
+            .respond_success("updateDnsRecords", json!({ "dnsrecords": [] }));
+        let outcome = client
+            .remove_txt_record("example.com", "_acme-challenge", "token", true)
+            .unwrap();
+
+        assert_eq!(outcome.removed().len(), 2);
+        let sent = &mock.requests()[3]["param"]["dnsrecordset"]["dnsrecords"];
+        assert_eq!(sent.as_array().unwrap().len(), 2);
+    }
+}
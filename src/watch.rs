@@ -0,0 +1,60 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::DNSRecord;
+
+/// A difference between two listings of the same zone.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum RecordChange {
+    Added { record: DNSRecord },
+    Removed { record: DNSRecord },
+    Modified { old: DNSRecord, new: DNSRecord },
+}
+
+/// Compares two listings by record id. Records without an id cannot be
+/// tracked across listings and are ignored.
+pub fn diff_snapshots(old: &[DNSRecord], new: &[DNSRecord]) -> Vec<RecordChange> {
+    let old_by_id: HashMap<_, _> = old
+        .iter()
+        .filter_map(|r| r.id.as_deref().map(|id| (id, r)))
+        .collect();
+    let new_by_id: HashMap<_, _> = new
+        .iter()
+        .filter_map(|r| r.id.as_deref().map(|id| (id, r)))
+        .collect();
+
+    let mut changes = Vec::new();
+
+    for record in new {
+        let Some(id) = record.id.as_deref() else {
+            continue;
+        };
+
+        match old_by_id.get(id) {
+            None => changes.push(RecordChange::Added {
+                record: record.clone(),
+            }),
+            Some(old) if !old.matches(record) || old.state != record.state => {
+                changes.push(RecordChange::Modified {
+                    old: (*old).clone(),
+                    new: record.clone(),
+                })
+            }
+            Some(_) => {}
+        }
+    }
+
+    for record in old {
+        if let Some(id) = record.id.as_deref() {
+            if !new_by_id.contains_key(id) {
+                changes.push(RecordChange::Removed {
+                    record: record.clone(),
+                });
+            }
+        }
+    }
+
+    changes
+}
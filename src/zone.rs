@@ -0,0 +1,311 @@
+use anyhow::{anyhow, bail};
+use serde::{de::IgnoredAny, Deserialize, Serialize};
+
+use crate::{
+    de,
+    request::{DnsZoneRequest, InfoDnsZoneRequest, Request, UpdateDnsZoneRequest},
+    NetcupAPIClient, NetcupError,
+};
+
+/// The settings of a zone, times in seconds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DnsZone {
+    pub name: String,
+    /// The TTL of every record in the zone
+    #[serde(deserialize_with = "de::number_or_string")]
+    pub ttl: u32,
+    pub serial: String,
+    #[serde(deserialize_with = "de::number_or_string")]
+    pub refresh: u32,
+    #[serde(deserialize_with = "de::number_or_string")]
+    pub retry: u32,
+    #[serde(deserialize_with = "de::number_or_string")]
+    pub expire: u32,
+    #[serde(default, deserialize_with = "de::bool_or_string")]
+    pub dnssecstatus: bool,
+}
+
+impl NetcupAPIClient {
+    /// Reads the zone settings with `infoDnsZone`.
+    pub fn get_zone(&self, domain: &str) -> anyhow::Result<DnsZone> {
+        let domain = &self.check_domain(domain)?;
+
+        let payload = Request::new(
+            "infoDnsZone",
+            InfoDnsZoneRequest {
+                session: self.session(),
+                domainname: domain,
+            },
+        );
+
+        let response = self.http.call::<DnsZone>(&payload)?.check(Some(domain))?;
+        match response.response_data {
+            Some(zone) => Ok(zone),
+            None => Err(response.missing("No zone was returned")),
+        }
+    }
+
+    /// [`get_zone`](Self::get_zone) under the name of the API action, the
+    /// SOA timers, TTL and DNSSEC status of the zone.
+    pub fn info_dns_zone(&self, domain: &str) -> anyhow::Result<DnsZone> {
+        self.get_zone(domain)
+    }
+
+    /// Changes the zone settings with `updateDnsZone` and returns them as
+    /// netcup stored them. A TTL below netcup's minimum is refused with a
+    /// [`NetcupError::Validation`]. A dry run returns `zone` unsent.
+    pub fn update_zone(&self, domain: &str, zone: &DnsZone) -> anyhow::Result<DnsZone> {
+        let domain = &self.check_domain(domain)?;
+
+        if self.dry_run {
+            return Ok(zone.clone());
+        }
+
+        let payload = Request::new(
+            "updateDnsZone",
+            UpdateDnsZoneRequest {
+                session: self.session(),
+                domainname: domain,
+                dnszone: DnsZoneRequest::from(zone),
+            },
+        );
+
+        let response = self.http.call::<DnsZone>(&payload)?.check(Some(domain))?;
+        match response.response_data {
+            Some(zone) => Ok(zone),
+            None => Err(response.missing("No zone was returned")),
+        }
+    }
+
+    /// Splits the full name of a record, e.g.
+    /// `_acme-challenge.host.example.co.uk`, into the zone this account
+    /// manages and the hostname relative to it, `@` at the apex.
+    ///
+    /// Walks the labels from the right (`co.uk`, `example.co.uk`, ...) and
+    /// asks for each with `infoDnsZone` until netcup knows it, skipping
+    /// names outside the allowlist. Answers are remembered for the lifetime
+    /// of the client, so a wildcard and its base domain cost one walk.
+    pub fn detect_zone(&self, fqdn: &str) -> anyhow::Result<(String, String)> {
+        let name = normalize_dns_name(fqdn)?;
+        let labels: Vec<&str> = name.split('.').collect();
+        if labels.len() < 2 || labels.iter().any(|label| label.is_empty()) {
+            bail!("{} is not a fully qualified name", fqdn);
+        }
+
+        // A TLD is never a zone of a customer
+        for start in (0..labels.len() - 1).rev() {
+            let zone = labels[start..].join(".");
+            if !self.is_domain_allowed(&zone) || !self.manages_zone(&zone)? {
+                continue;
+            }
+
+            let hostname = split_record_name(&name, &zone)?;
+            return Ok((zone, hostname));
+        }
+
+        Err(anyhow!(
+            "{} is not in any zone of this account{}",
+            fqdn,
+            if self.allowed_domains.is_some() {
+                " that allowed_domains permits"
+            } else {
+                ""
+            }
+        ))
+    }
+
+    /// Whether netcup has `zone` in this account.
+    fn manages_zone(&self, zone: &str) -> anyhow::Result<bool> {
+        if let Some(known) = self.known_zones.lock().unwrap().get(zone) {
+            return Ok(*known);
+        }
+
+        let payload = Request::new(
+            "infoDnsZone",
+            InfoDnsZoneRequest {
+                session: self.session(),
+                domainname: zone,
+            },
+        );
+
+        let managed = match self.http.call::<IgnoredAny>(&payload)?.error(Some(zone)) {
+            None => true,
+            Some(NetcupError::DomainNotFound { .. }) => false,
+            Some(err) => return Err(err.into()),
+        };
+
+        self.known_zones
+            .lock()
+            .unwrap()
+            .insert(zone.to_string(), managed);
+        Ok(managed)
+    }
+}
+
+/// The hostname of the record named `fqdn` relative to `zone`, as netcup
+/// expects it: `@` when the name is the zone itself, e.g. for the challenge
+/// of a bare domain. Both may end with a dot, differ in case and be given
+/// in Unicode or punycode, see [`normalize_dns_name`].
+pub fn split_record_name(fqdn: &str, zone: &str) -> anyhow::Result<String> {
+    let name = normalize_dns_name(fqdn)?;
+    let zone = normalize_dns_name(zone)?;
+    if name.is_empty() || zone.is_empty() {
+        bail!("The record name and zone can't be empty");
+    }
+
+    if name == zone {
+        return Ok("@".into());
+    }
+
+    match name
+        .strip_suffix(&zone)
+        .and_then(|host| host.strip_suffix('.'))
+    {
+        Some(host) if !host.is_empty() && !host.split('.').any(str::is_empty) => Ok(host.into()),
+        _ => Err(anyhow!("{} is not a name in the zone {}", fqdn, zone)),
+    }
+}
+
+/// The ASCII form of a domain or hostname, which netcup and the nameservers
+/// expect: Unicode labels such as `bücher-beispiel` become punycode
+/// (`xn--bcher-beispiel-gsb`) by the UTS 46 rules for hostnames, the rest
+/// is lowercased and a trailing dot dropped. `@`, `*` and labels like
+/// `_acme-challenge` are kept as they are.
+///
+/// Fails with the offending label if a Unicode label can't be converted.
+pub fn normalize_dns_name(name: &str) -> anyhow::Result<String> {
+    let name = name.trim_end_matches('.');
+    let labels = name
+        .split('.')
+        .map(|label| {
+            if label.is_ascii() {
+                Ok(label.to_ascii_lowercase())
+            } else {
+                idna::domain_to_ascii_strict(label)
+                    .map_err(|_| anyhow!("Invalid label '{}' in {}", label, name))
+            }
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    Ok(labels.join("."))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn zone_settings_are_parsed_from_strings() {
+        let mock = testing::MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_success(
+            "infoDnsZone",
+            json!({
+                "name": "example.de",
+                "ttl": "86400",
+                "serial": "2026101401",
+                "refresh": "28800",
+                "retry": "7200",
+                "expire": "1209600",
+                "dnssecstatus": "false",
+            }),
+        );
+
+        let zone = client.info_dns_zone("example.de").unwrap();
+
+        assert_eq!(zone.ttl, 86400);
+        assert_eq!(zone.expire, 1_209_600);
+        assert!(!zone.dnssecstatus);
+        assert_eq!(mock.requests()[1]["param"]["domainname"], "example.de");
+    }
+
+    #[test]
+    fn unicode_labels_become_punycode() {
+        assert_eq!(
+            normalize_dns_name("_acme-challenge.bücher-beispiel.de").unwrap(),
+            "_acme-challenge.xn--bcher-beispiel-gsb.de"
+        );
+        assert_eq!(
+            normalize_dns_name("WWW.Bücher-Beispiel.DE.").unwrap(),
+            "www.xn--bcher-beispiel-gsb.de"
+        );
+        assert_eq!(normalize_dns_name("@").unwrap(), "@");
+    }
+
+    #[test]
+    fn an_invalid_label_is_named() {
+        let err = normalize_dns_name("www.bü cher.de").unwrap_err();
+        assert_eq!(err.to_string(), "Invalid label 'bü cher' in www.bü cher.de");
+    }
+
+    #[test]
+    fn names_are_split_off_the_zone() {
+        assert_eq!(
+            split_record_name("example.com", "example.com").unwrap(),
+            "@"
+        );
+        assert_eq!(
+            split_record_name("Example.COM.", "example.com").unwrap(),
+            "@"
+        );
+        assert_eq!(
+            split_record_name("_acme-challenge.www.example.co.uk", "example.co.uk").unwrap(),
+            "_acme-challenge.www"
+        );
+
+        for (name, zone) in [
+            ("_acme-challenge.example.org", "example.com"),
+            // A suffix, but not at a label boundary
+            ("_acme-challenge.myexample.com", "example.com"),
+            ("co.uk", "example.co.uk"),
+        ] {
+            let err = split_record_name(name, zone).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!("{} is not a name in the zone {}", name, zone)
+            );
+        }
+    }
+
+    #[test]
+    fn zone_detection_walks_up_from_the_registrable_domain() {
+        let mock = testing::MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_error("infoDnsZone", 5029, "Domain not found")
+            .respond_success("infoDnsZone", json!({ "name": "example.co.uk" }));
+
+        let (zone, hostname) = client
+            .detect_zone("_acme-challenge.www.example.co.uk")
+            .unwrap();
+
+        assert_eq!(zone, "example.co.uk");
+        assert_eq!(hostname, "_acme-challenge.www");
+        let probed: Vec<_> = mock.requests()[1..]
+            .iter()
+            .map(|request| request["param"]["domainname"].clone())
+            .collect();
+        assert_eq!(probed, ["co.uk", "example.co.uk"]);
+
+        // Remembered, the apex of the same zone costs no further call
+        let (_, hostname) = client.detect_zone("example.co.uk").unwrap();
+        assert_eq!(hostname, "@");
+        assert_eq!(mock.requests().len(), 3);
+    }
+
+    #[test]
+    fn a_name_outside_the_account_is_refused() {
+        let mock = testing::MockTransport::new();
+        let client = testing::login(&mock, testing::builder());
+        mock.respond_error("infoDnsZone", 5029, "Domain not found")
+            .respond_error("infoDnsZone", 5029, "Domain not found");
+
+        let err = client.detect_zone("www.example.org").unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "www.example.org is not in any zone of this account"
+        );
+    }
+}
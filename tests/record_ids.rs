@@ -0,0 +1,72 @@
+//! The record ids `set` prints and `unset-by-id` deletes by, as the library
+//! hands them to the command line tool.
+
+use serde_json::json;
+use truenas_acme_auth::testing::{builder, login, txt, MockTransport};
+
+#[test]
+fn stale_duplicate_doesnt_take_the_new_records_id() {
+    let mock = MockTransport::new();
+    let client = login(&mock, builder());
+    mock.respond_success(
+        "infoDnsRecords",
+        json!({ "dnsrecords": [txt(1, "token"), txt(2, "other")] }),
+    )
+    .respond_success(
+        "updateDnsRecords",
+        json!({ "dnsrecords": [txt(1, "token"), txt(2, "other"), txt(3, "token")] }),
+    );
+
+    let records = client
+        .add_txt_records("example.com", "_acme-challenge", &["token"])
+        .unwrap();
+    let ids: Vec<_> = records.iter().map(|r| r.id().unwrap()).collect();
+    assert_eq!(ids, ["3"]);
+
+    // Deleting by id 3 removes that record and leaves the stale one alone
+    mock.respond_success(
+        "infoDnsRecords",
+        json!({ "dnsrecords": [txt(1, "token"), txt(2, "other"), txt(3, "token")] }),
+    )
+    .respond_success(
+        "updateDnsRecords",
+        json!({ "dnsrecords": [txt(1, "token"), txt(2, "other")] }),
+    );
+    assert!(client
+        .delete_record_by_id("example.com", ids[0], false)
+        .unwrap());
+
+    let deletion = &mock.requests()[4]["param"]["dnsrecordset"]["dnsrecords"];
+    assert_eq!(deletion.as_array().unwrap().len(), 1);
+    assert_eq!(deletion[0]["id"], "3");
+    assert_eq!(deletion[0]["deleterecord"], true);
+}
+
+#[test]
+fn selftest_cleanup_removes_the_record_it_created() {
+    let mock = MockTransport::new();
+    let client = login(&mock, builder());
+    let zone = json!({ "dnsrecords": [txt(1, "token"), txt(4, "token")] });
+    mock.respond_success("infoDnsRecords", json!({ "dnsrecords": [txt(1, "token")] }))
+        .respond_success("updateDnsRecords", zone.clone())
+        .respond_success("infoDnsRecords", zone)
+        .respond_success(
+            "updateDnsRecords",
+            json!({ "dnsrecords": [txt(1, "token")] }),
+        );
+
+    let created = client
+        .force_add_txt_record("example.com", "_acme-challenge", "token")
+        .unwrap();
+    client
+        .delete_record(
+            created.id().unwrap(),
+            "example.com",
+            "_acme-challenge",
+            "token",
+        )
+        .unwrap();
+
+    let deletion = &mock.requests()[4]["param"]["dnsrecordset"]["dnsrecords"];
+    assert_eq!(deletion[0]["id"], "4");
+}